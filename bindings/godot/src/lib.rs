@@ -1,4 +1,7 @@
-use bobbin_runtime::{HostState, Runtime, Value, VariableStorage};
+use bobbin_runtime::{
+    AriadneRenderer, Event, HostState, MemoryStorage, Prelude, ReloadOutcome, Renderer, Runtime,
+    RuntimeError, RuntimeOptions, RuntimeState, Value, VariableStorage,
+};
 use godot::classes::{
     Engine, FileAccess, IResourceFormatLoader, IResourceFormatSaver, IScriptExtension,
     IScriptLanguageExtension, Os, Resource, ResourceFormatLoader, ResourceFormatSaver,
@@ -9,55 +12,15 @@ use godot::classes::{
 use godot::meta::RawPtr;
 use godot::prelude::*;
 use std::collections::HashMap;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, RwLock};
 
 struct BobbinExtension;
 
 // =============================================================================
-// Storage and Host State Implementations
+// Host State Implementation
 // =============================================================================
 
-/// In-memory implementation of VariableStorage for Godot.
-/// Thread-safe via RwLock.
-struct MemoryStorage {
-    values: RwLock<HashMap<String, Value>>,
-}
-
-impl MemoryStorage {
-    fn new() -> Self {
-        Self {
-            values: RwLock::new(HashMap::new()),
-        }
-    }
-
-    /// Get all variables as a copy of the internal map.
-    fn get_all(&self) -> HashMap<String, Value> {
-        self.values.read().unwrap().clone()
-    }
-}
-
-impl VariableStorage for MemoryStorage {
-    fn get(&self, name: &str) -> Option<Value> {
-        self.values.read().unwrap().get(name).cloned()
-    }
-
-    fn set(&self, name: &str, value: Value) {
-        self.values.write().unwrap().insert(name.to_string(), value);
-    }
-
-    fn initialize_if_absent(&self, name: &str, default: Value) {
-        self.values
-            .write()
-            .unwrap()
-            .entry(name.to_string())
-            .or_insert(default);
-    }
-
-    fn contains(&self, name: &str) -> bool {
-        self.values.read().unwrap().contains_key(name)
-    }
-}
-
 /// Host state implementation backed by a HashMap.
 /// Thread-safe via RwLock. Game can update values at any time.
 struct VarDictionaryHostState {
@@ -91,6 +54,18 @@ impl HostState for VarDictionaryHostState {
     fn lookup(&self, name: &str) -> Option<Value> {
         self.values.read().unwrap().get(name).cloned()
     }
+
+    fn lookup_many(&self, names: &[&str]) -> Vec<Option<Value>> {
+        let values = self.values.read().unwrap();
+        names
+            .iter()
+            .map(|name| values.get(*name).cloned())
+            .collect()
+    }
+
+    fn debug_print(&self, message: &str) {
+        godot_print!("[bobbin] {}", message);
+    }
 }
 
 // =============================================================================
@@ -120,9 +95,38 @@ fn value_to_variant(v: &Value) -> Variant {
             }
         }
         Value::Bool(b) => Variant::from(*b),
+        Value::Null => Variant::nil(),
     }
 }
 
+/// Project-wide prelude loaded before every dialogue file, if it exists.
+const PRELUDE_PATH: &str = "res://globals.bobbin";
+
+/// Runtime options for a script, with the project prelude if there is one.
+/// The prelude itself is loaded without one.
+fn runtime_options(script_path: Option<&str>) -> RuntimeOptions {
+    let prelude_path = GString::from(PRELUDE_PATH);
+    let prelude = if script_path != Some(PRELUDE_PATH) && FileAccess::file_exists(&prelude_path) {
+        FileAccess::open(&prelude_path, ModeFlags::READ)
+            .map(|file| Prelude::new(PRELUDE_PATH, file.get_as_text().to_string()))
+    } else {
+        None
+    };
+    RuntimeOptions {
+        prelude,
+        ..Default::default()
+    }
+}
+
+/// Print any compile-time warnings for a freshly created runtime.
+fn report_warnings(runtime: &Runtime, source_id: &str, source: &str) {
+    if runtime.warnings().is_empty() {
+        return;
+    }
+    let rendered = AriadneRenderer::new().render_all(runtime.warnings(), source_id, source);
+    godot_warn!("{}", rendered);
+}
+
 /// Find the registered Bobbin language by iterating through Engine's script languages
 fn find_bobbin_language() -> Option<Gd<ScriptLanguage>> {
     let mut engine = Engine::singleton();
@@ -756,6 +760,16 @@ pub struct BobbinRuntime {
     storage: Arc<MemoryStorage>,
     host: Arc<VarDictionaryHostState>,
     inner: Runtime,
+    /// Whether `conversation_ended` has been emitted for the current runtime.
+    ended: bool,
+    /// Script the runtime was compiled from, for `disassemble()`.
+    source: String,
+    /// Applied to each line as it is shown; see `set_line_transform()`.
+    line_transform: Option<Callable>,
+    /// The current line with `line_transform` applied, updated once per step.
+    transformed_line: GString,
+    /// Consulted before a choice is selected; see `set_choice_validator()`.
+    choice_validator: Option<Callable>,
 
     // Hot reload support (debug builds only)
     source_path: Option<GString>,  // None if created via from_string()
@@ -781,16 +795,30 @@ impl BobbinRuntime {
         let storage_dyn: Arc<dyn VariableStorage> = storage.clone();
         let host_dyn: Arc<dyn HostState> = host.clone();
 
-        match Runtime::new(&content.to_string(), storage_dyn, host_dyn) {
-            Ok(runtime) => Some(Gd::from_init_fn(|base| Self {
-                base,
-                storage,
-                host,
-                inner: runtime,
-                source_path: None,
-                last_modified: 0,
-                poll_timer: None,
-            })),
+        match Runtime::with_options(
+            &content.to_string(),
+            storage_dyn,
+            host_dyn,
+            runtime_options(None),
+        ) {
+            Ok(runtime) => {
+                report_warnings(&runtime, "<script>", &content.to_string());
+                let transformed_line = GString::from(runtime.current_line());
+                Some(Gd::from_init_fn(|base| Self {
+                    base,
+                    storage,
+                    host,
+                    inner: runtime,
+                    ended: false,
+                    source: content.to_string(),
+                    line_transform: None,
+                    transformed_line,
+                    choice_validator: None,
+                    source_path: None,
+                    last_modified: 0,
+                    poll_timer: None,
+                }))
+            }
             Err(e) => {
                 godot_error!(
                     "Failed to create runtime:\n{}",
@@ -832,8 +860,16 @@ impl BobbinRuntime {
         let storage_dyn: Arc<dyn VariableStorage> = storage.clone();
         let host_dyn: Arc<dyn HostState> = host.clone();
 
-        match Runtime::new(&source, storage_dyn, host_dyn) {
+        match Runtime::with_options(
+            &source,
+            storage_dyn,
+            host_dyn,
+            runtime_options(Some(&path.to_string())),
+        ) {
             Ok(runtime) => {
+                report_warnings(&runtime, &path.to_string(), &source);
+                let transformed_line = GString::from(runtime.current_line());
+
                 // Get initial modification time and setup hot reload (debug builds only)
                 let (source_path, last_modified) = if Os::singleton().is_debug_build() {
                     let modified = FileAccess::get_modified_time(&path);
@@ -847,6 +883,11 @@ impl BobbinRuntime {
                     storage,
                     host,
                     inner: runtime,
+                    ended: false,
+                    source,
+                    line_transform: None,
+                    transformed_line,
+                    choice_validator: None,
                     source_path,
                     last_modified,
                     poll_timer: None,
@@ -877,7 +918,8 @@ impl BobbinRuntime {
     #[signal]
     fn reload_failed(error_message: GString);
 
-    /// Reload with new source code. Preserves save variables.
+    /// Reload with new source code. Preserves save variables, and stays on the
+    /// current line or choices if the edit doesn't touch what already ran.
     #[func]
     fn reload(&mut self, new_source: GString) -> bool {
         let source_str = new_source.to_string();
@@ -887,14 +929,23 @@ impl BobbinRuntime {
             .map(|p| p.to_string())
             .unwrap_or_else(|| "<script>".to_string());
 
-        let storage_dyn: Arc<dyn VariableStorage> = self.storage.clone();
-        let host_dyn: Arc<dyn HostState> = self.host.clone();
-
-        match Runtime::new(&source_str, storage_dyn, host_dyn) {
-            Ok(new_runtime) => {
-                self.inner = new_runtime;
+        let options = runtime_options(self.source_path.as_ref().map(|p| p.to_string()).as_deref());
+        match self.inner.reload(&self.source, &source_str, options) {
+            Ok(outcome) => {
+                report_warnings(&self.inner, &path_str, &source_str);
+                if outcome == ReloadOutcome::Restarted {
+                    godot_print!(
+                        "Hot reload: {} changed before the current line, restarting",
+                        path_str
+                    );
+                }
+                self.ended = false;
+                self.source = source_str;
+                self.refresh_transformed_line();
                 self.base_mut()
                     .emit_signal(&StringName::from("reloaded"), &[]);
+                // A restart shows the first line again
+                self.emit_events();
                 true
             }
             Err(e) => {
@@ -1008,16 +1059,187 @@ impl BobbinRuntime {
         }
     }
 
+    /// Emitted once, from the step that leaves nothing more to show
+    /// (`has_more()` turns false). Reloading starts a new conversation.
+    #[signal]
+    fn conversation_ended();
+
+    /// Emitted when a line starts showing, with its 1-based source line and
+    /// how many choice branches enclose it.
+    #[signal]
+    fn line_shown(text: GString, line_number: i64, depth: i64);
+
+    /// Emitted when choices start showing. Each option is a Dictionary with
+    /// `text`, `label` (empty when unlabelled), `tags` and `seen`.
+    #[signal]
+    fn choices_shown(options: Array<VarDictionary>);
+
+    /// Emit the signal for what is showing now if it hasn't been emitted yet.
+    /// Steps emit their own; call this once after connecting to get the
+    /// first line or choice set.
+    #[func]
+    fn emit_pending_events(&mut self) {
+        self.emit_events();
+    }
+
+    fn emit_events(&mut self) {
+        while let Some(event) = self.inner.next_event() {
+            match event {
+                Event::Line {
+                    line_number, depth, ..
+                } => {
+                    let text = self.transformed_line.clone();
+                    self.base_mut().emit_signal(
+                        &StringName::from("line_shown"),
+                        &[
+                            text.to_variant(),
+                            (line_number as i64).to_variant(),
+                            (depth as i64).to_variant(),
+                        ],
+                    );
+                }
+                Event::Choices { options } => {
+                    let mut arr: Array<VarDictionary> = Array::new();
+                    for option in options {
+                        let mut tags = PackedStringArray::new();
+                        for tag in &option.tags {
+                            tags.push(&GString::from(tag.as_str()));
+                        }
+                        let mut dict = VarDictionary::new();
+                        dict.set("text", GString::from(option.text.as_str()));
+                        dict.set(
+                            "label",
+                            GString::from(option.label.as_deref().unwrap_or("")),
+                        );
+                        dict.set("tags", tags);
+                        dict.set("seen", option.seen);
+                        arr.push(&dict);
+                    }
+                    self.base_mut()
+                        .emit_signal(&StringName::from("choices_shown"), &[arr.to_variant()]);
+                }
+                // `conversation_ended` already marks the end
+                Event::End => {}
+            }
+        }
+    }
+
     #[func]
     fn advance(&mut self) {
         if let Err(e) = self.inner.advance() {
             godot_error!("advance failed: {}", e);
         }
+        self.emit_step_signals();
+    }
+
+    /// Skip lines until choices are showing or the final line is reached.
+    #[func]
+    fn skip_to_choice(&mut self) {
+        if let Err(e) = self.inner.skip_to_choice() {
+            godot_error!("skip_to_choice failed: {}", e);
+        }
+        self.emit_step_signals();
+    }
+
+    /// Advance up to `count` lines and return their text. Stops early at
+    /// choices or the final line.
+    #[func]
+    fn advance_n(&mut self, count: i32) -> PackedStringArray {
+        let lines = match self.inner.advance_n(count.max(0) as usize) {
+            Ok(lines) => lines,
+            Err(e) => {
+                godot_error!("advance_n failed: {}", e);
+                Vec::new()
+            }
+        };
+        self.emit_step_signals();
+
+        // The last line is the current one, which is already transformed
+        let mut arr = PackedStringArray::new();
+        if let Some((_, earlier)) = lines.split_last() {
+            for line in earlier {
+                arr.push(&self.displayed_line(line));
+            }
+            arr.push(&self.transformed_line);
+        }
+        arr
+    }
+
+    /// Emit `line_shown` or `choices_shown` for whatever the runtime moved
+    /// to, then `conversation_ended` if nothing more is left.
+    fn emit_step_signals(&mut self) {
+        self.refresh_transformed_line();
+        self.emit_events();
+        if self.ended || self.inner.has_more() {
+            return;
+        }
+        self.ended = true;
+        self.base_mut()
+            .emit_signal(&StringName::from("conversation_ended"), &[]);
+    }
+
+    /// Interrupt execution. advance() and select_choice() fail until resume() is called.
+    #[func]
+    fn cancel(&self) {
+        self.inner.cancel_flag().store(true, Ordering::Relaxed);
+    }
+
+    /// Clear a previous cancel() so execution continues where it stopped.
+    #[func]
+    fn resume(&self) {
+        self.inner.cancel_flag().store(false, Ordering::Relaxed);
     }
 
     #[func]
     fn current_line(&self) -> GString {
-        GString::from(self.inner.current_line())
+        self.transformed_line.clone()
+    }
+
+    /// Pass every line through `transform` (a Callable taking and returning a
+    /// String) before `current_line()` and `state_payload()` return it, e.g. to
+    /// add BBCode. It runs after interpolation, once per line shown, and the
+    /// result is kept until the next step. Choices are never transformed: the
+    /// `choices_shown` signal, `current_choices()` and `state_payload()` give
+    /// their text as the script wrote it. Pass an empty Callable to remove it.
+    #[func]
+    fn set_line_transform(&mut self, transform: Callable) {
+        self.line_transform = transform.is_valid().then_some(transform);
+        self.refresh_transformed_line();
+    }
+
+    fn refresh_transformed_line(&mut self) {
+        self.transformed_line = self.displayed_line(self.inner.current_line());
+    }
+
+    fn displayed_line(&self, text: &str) -> GString {
+        let text = GString::from(text);
+        let Some(transform) = &self.line_transform else {
+            return text;
+        };
+        match transform.call(&[text.to_variant()]).try_to::<GString>() {
+            Ok(transformed) => transformed,
+            Err(e) => {
+                godot_error!("line transform must return a String: {}", e);
+                text
+            }
+        }
+    }
+
+    /// The current line's `//!` author note, or an empty string.
+    /// Always empty in release builds.
+    #[func]
+    fn current_line_debug_note(&self) -> GString {
+        if !Os::singleton().is_debug_build() {
+            return GString::new();
+        }
+        GString::from(self.inner.current_line_debug_note().unwrap_or(""))
+    }
+
+    /// The current line before interpolation (`Hello, {name}!`), or an
+    /// empty string when no line is showing.
+    #[func]
+    fn current_line_template(&self) -> GString {
+        GString::from(self.inner.current_line_template().unwrap_or(""))
     }
 
     #[func]
@@ -1025,6 +1247,42 @@ impl BobbinRuntime {
         self.inner.has_more()
     }
 
+    /// Counter that changes whenever a new line, choice set or the end is
+    /// shown, even when the text repeats. Compare against the last rendered
+    /// value to skip redundant UI updates.
+    #[func]
+    fn revision(&self) -> i64 {
+        self.inner.revision() as i64
+    }
+
+    #[constant]
+    const STATE_LINE: i32 = 0;
+    #[constant]
+    const STATE_CHOICES: i32 = 1;
+    #[constant]
+    const STATE_DONE: i32 = 2;
+
+    /// Current state as one of the STATE_* constants.
+    #[func]
+    fn state(&self) -> i32 {
+        match self.inner.state() {
+            RuntimeState::Line(_) => Self::STATE_LINE,
+            RuntimeState::Choices(_) => Self::STATE_CHOICES,
+            RuntimeState::Done => Self::STATE_DONE,
+        }
+    }
+
+    /// Payload for the current state: the line text (String) for STATE_LINE,
+    /// the choices (PackedStringArray) for STATE_CHOICES, or null for STATE_DONE.
+    #[func]
+    fn state_payload(&self) -> Variant {
+        match self.inner.state() {
+            RuntimeState::Line(_) => Variant::from(self.transformed_line.clone()),
+            RuntimeState::Choices(_) => self.current_choices().to_variant(),
+            RuntimeState::Done => Variant::nil(),
+        }
+    }
+
     #[func]
     fn is_waiting_for_choice(&self) -> bool {
         self.inner.is_waiting_for_choice()
@@ -1040,11 +1298,160 @@ impl BobbinRuntime {
         arr
     }
 
+    /// Tags of each current choice, in display order (`- Attack #combat`
+    /// has `["combat"]`). Choices without tags have an empty array.
+    #[func]
+    fn current_choice_tags(&self) -> Array<PackedStringArray> {
+        let mut arr = Array::new();
+        for index in 0..self.inner.current_choices().len() {
+            let mut tags = PackedStringArray::new();
+            for tag in self.inner.current_choice_tags(index) {
+                tags.push(&GString::from(tag.as_str()));
+            }
+            arr.push(&tags);
+        }
+        arr
+    }
+
+    /// Whether `select_choice(index)` would be accepted right now. Use it to
+    /// enable or disable choice buttons.
+    #[func]
+    fn can_select_choice(&self, index: i32) -> bool {
+        index >= 0 && self.inner.can_select_choice(index as usize) && self.validate(index).is_ok()
+    }
+
     #[func]
     fn select_choice(&mut self, index: i32) {
-        if let Err(e) = self.inner.select_choice(index as usize) {
+        let result = self
+            .validate(index)
+            .and_then(|()| self.inner.select_choice(index as usize));
+        if let Err(e) = result {
             godot_error!("select_choice failed: {}", e);
         }
+        self.emit_step_signals();
+    }
+
+    /// Let game logic veto a selection: `validator` is a Callable taking the
+    /// choice index and text and returning a bool. When it returns false,
+    /// `select_choice()` and `select_choice_checked()` log an error and the
+    /// choices stay up, and `can_select_choice()` returns false. Pass an empty
+    /// Callable to remove it.
+    ///
+    /// ```gdscript
+    /// runtime.set_choice_validator(func(index, text):
+    ///     return text != "Buy the sword" or gold >= 50)
+    /// ```
+    #[func]
+    fn set_choice_validator(&mut self, validator: Callable) {
+        self.choice_validator = validator.is_valid().then_some(validator);
+    }
+
+    /// Ask the choice validator, if one is set, about the pending choice at
+    /// `index`. Every selection path goes through here. Invalid indices are
+    /// left to the runtime.
+    fn validate(&self, index: i32) -> Result<(), RuntimeError> {
+        let Some(validator) = &self.choice_validator else {
+            return Ok(());
+        };
+        let choices = self.inner.current_choices();
+        if index < 0 || index as usize >= choices.len() {
+            return Ok(());
+        }
+        let text = GString::from(choices[index as usize].as_str());
+        let allowed = match validator
+            .call(&[index.to_variant(), text.to_variant()])
+            .try_to::<bool>()
+        {
+            Ok(allowed) => allowed,
+            Err(e) => {
+                godot_error!("choice validator must return a bool: {}", e);
+                true
+            }
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(RuntimeError::ChoiceVetoed {
+                index: index as usize,
+            })
+        }
+    }
+
+    /// Identifies the choices on screen; pass it to `select_choice_checked`.
+    #[func]
+    fn choice_token(&self) -> i64 {
+        self.inner.choice_token() as i64
+    }
+
+    /// Select a choice only if the choice set read with `token` is still
+    /// showing. Returns false, and logs why, when the selection fails.
+    #[func]
+    fn select_choice_checked(&mut self, index: i32, token: i64) -> bool {
+        // A stale token is reported as such, without asking the validator
+        // about choices the caller never saw
+        let token = token as u64;
+        let validated = if token == self.inner.choice_token() {
+            self.validate(index)
+        } else {
+            Ok(())
+        };
+        let result =
+            validated.and_then(|()| self.inner.select_choice_checked(index as usize, token));
+        if let Err(e) = &result {
+            godot_error!("select_choice_checked failed: {}", e);
+        }
+        self.emit_step_signals();
+        result.is_ok()
+    }
+
+    /// Bytecode listing of the running script, with source line numbers.
+    /// Useful in bug reports when it's unclear whether compiling or running went wrong.
+    #[func]
+    fn disassemble(&self) -> GString {
+        GString::from(self.inner.chunk().disassemble(&self.source).as_str())
+    }
+
+    /// Number of lines choice `index` would show before the next decision or the end.
+    /// Simulates the branch without selecting it. Returns -1 on error.
+    #[func]
+    fn peek_branch_length(&self, index: i32) -> i64 {
+        match self.inner.peek_branch_length(index as usize) {
+            Ok(lines) => lines as i64,
+            Err(e) => {
+                godot_error!("peek_branch_length failed: {}", e);
+                -1
+            }
+        }
+    }
+
+    /// First line each current choice would lead to, in display order. An
+    /// entry is null when the branch shows no line of its own, so it can't
+    /// be mistaken for a line that is empty.
+    #[func]
+    fn choice_previews(&self) -> VarArray {
+        let mut arr = VarArray::new();
+        for preview in self.inner.choice_previews() {
+            let preview = match preview {
+                Some(text) => GString::from(text.as_str()).to_variant(),
+                None => Variant::nil(),
+            };
+            arr.push(&preview);
+        }
+        arr
+    }
+
+    /// Whether the current choice at `index` has nested content, e.g. to show
+    /// a continuation arrow. Cheaper than `choice_previews`.
+    #[func]
+    fn choice_branch_has_content(&self, index: i32) -> bool {
+        index >= 0 && self.inner.choice_branch_has_content(index as usize)
+    }
+
+    /// Whether the player picked the current choice at `index` before, in
+    /// this or an earlier conversation. Only labelled choices are tracked.
+    #[func]
+    fn choice_seen(&self, index: i32) -> bool {
+        index >= 0 && self.inner.choice_seen(index as usize)
     }
 
     /// Get a save variable value.
@@ -1064,11 +1471,39 @@ impl BobbinRuntime {
         }
     }
 
-    /// Get all save variables as VarDictionary.
+    /// Give save variables missing from storage their declared defaults.
+    /// Call after loading a save made by an older version of the script.
+    #[func]
+    fn initialize_defaults(&self) {
+        self.inner.initialize_defaults();
+    }
+
+    /// Save variables the script wrote since the last `clear_dirty()`, for
+    /// autosaving only what changed.
+    #[func]
+    fn get_dirty_variables(&self) -> PackedStringArray {
+        let mut arr = PackedStringArray::new();
+        for name in self.inner.dirty_variables() {
+            arr.push(&GString::from(name.as_str()));
+        }
+        arr
+    }
+
+    /// Forget which variables were written, typically right after saving them.
+    #[func]
+    fn clear_dirty(&mut self) {
+        self.inner.clear_dirty();
+    }
+
+    /// Get all save variables as VarDictionary. Internal entries such as
+    /// `once` line counters are left out, as in `Runtime::variable_names`.
     #[func]
     fn get_all_variables(&self) -> VarDictionary {
         let mut dict = VarDictionary::new();
         for (key, value) in self.storage.get_all() {
+            if key.contains(':') {
+                continue;
+            }
             dict.set(GString::from(key.as_str()), value_to_variant(&value));
         }
         dict
@@ -0,0 +1,20 @@
+#!/bin/bash
+# Seeds every fuzz target's corpus with the test fixtures and with the
+# inputs in regressions/, which once crashed a target
+# Run before the first `cargo fuzz run`
+
+set -e
+
+FUZZ_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+CASES_DIR="$(dirname "$FUZZ_DIR")/tests/cases"
+
+for target in scan parse runtime; do
+    mkdir -p "$FUZZ_DIR/corpus/$target"
+    # Flatten paths so fixtures with the same name don't collide
+    find "$CASES_DIR" -name '*.bobbin' | while read -r case; do
+        name="${case#"$CASES_DIR"/}"
+        cp "$case" "$FUZZ_DIR/corpus/$target/${name//\//_}"
+    done
+    cp "$FUZZ_DIR"/regressions/* "$FUZZ_DIR/corpus/$target/"
+done
+echo "Seeded corpus from $CASES_DIR and $FUZZ_DIR/regressions"
@@ -1,35 +1,198 @@
+//! Syntax tree produced by the parser.
+//!
+//! Exposed for tooling (documentation generators, formatters) via [`crate::parse`].
+//! [`crate::emit`] renders a tree back to source, so importers from other
+//! formats can build a tree and write it out as Bobbin.
+//! [`crate::visit::walk`] traverses a tree for lints and extractors.
+//! With the `serde` feature the tree implements `Serialize`, and
+//! `crate::parse_to_json` emits it as JSON for tools outside Rust.
+
+use std::collections::BTreeMap;
+
 use crate::token::Span;
 
 /// Unique identifier for AST nodes that need semantic binding.
 /// Used to track which variable reference resolves to which slot.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct NodeId(pub usize);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Script {
     pub statements: Vec<Stmt>,
+    /// `//` comments directly above a statement or choice, keyed by the
+    /// node's span start. Use [`Script::leading_comments`] to look them up.
+    pub comments: BTreeMap<usize, Vec<String>>,
+}
+
+impl Script {
+    /// Comments on the lines directly above a statement.
+    pub fn leading_comments(&self, stmt: &Stmt) -> &[String] {
+        self.comments_at(stmt.span().start)
+    }
+
+    /// Comments on the lines directly above a choice.
+    pub fn choice_comments(&self, choice: &Choice) -> &[String] {
+        self.comments_at(choice.span.start)
+    }
+
+    fn comments_at(&self, start: usize) -> &[String] {
+        self.comments.get(&start).map_or(&[], Vec::as_slice)
+    }
+
+    /// Attach each comment to the first statement or choice that follows it.
+    /// Comments with nothing after them are dropped.
+    pub(crate) fn attach_comments(&mut self, comments: Vec<(usize, String)>) {
+        let mut starts = Vec::new();
+        collect_starts(&self.statements, &mut starts);
+        starts.sort_unstable();
+
+        for (offset, text) in comments {
+            let next = starts.partition_point(|&start| start < offset);
+            if let Some(&start) = starts.get(next) {
+                self.comments.entry(start).or_default().push(text);
+            }
+        }
+    }
+}
+
+/// Span starts of every statement and choice, recursively.
+fn collect_starts(statements: &[Stmt], starts: &mut Vec<usize>) {
+    for stmt in statements {
+        starts.push(stmt.span().start);
+        if let Stmt::ChoiceSet { choices, .. } = stmt {
+            for choice in choices {
+                starts.push(choice.span.start);
+                collect_starts(&choice.nested, starts);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Stmt {
-    Line { parts: Vec<TextPart>, span: Span },
+    Line {
+        parts: Vec<TextPart>,
+        span: Span,
+        /// Show the line only the first time it is reached (`once` modifier)
+        once: bool,
+        /// Assignment that runs when the line is shown: `text ~ set x = 1`
+        effect: Option<VarBindingData>,
+        /// Author note for development builds: `text //! note`
+        debug_note: Option<String>,
+    },
     TempDecl(VarBindingData),
     SaveDecl(VarBindingData),
+    /// `const name = value`: a fixed value, inlined wherever it is read
+    ConstDecl(VarBindingData),
     ExternDecl(ExternDeclData),
     Assignment(VarBindingData),
-    ChoiceSet { choices: Vec<Choice> },
+    /// `debug_print "message"`: sends the message to the host's log
+    DebugPrint {
+        message: String,
+        span: Span,
+    },
+    /// `assert condition`: stops with a runtime error if the condition is false
+    Assert {
+        condition: Expr,
+        span: Span,
+    },
+    ChoiceSet {
+        choices: Vec<Choice>,
+        /// Present the choices in a random order (`shuffle` directive)
+        shuffle: bool,
+    },
+    /// `end`: finishes the conversation here
+    End {
+        span: Span,
+    },
+    /// `namespace name`: prefixes the file's `save` variables in storage
+    Namespace {
+        name: String,
+        span: Span,
+    },
+}
+
+impl Stmt {
+    /// Source span of the statement. For a choice set, the span of its first choice.
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Line { span, .. } => *span,
+            Stmt::TempDecl(data)
+            | Stmt::SaveDecl(data)
+            | Stmt::ConstDecl(data)
+            | Stmt::Assignment(data) => data.span,
+            Stmt::ExternDecl(data) => data.span,
+            Stmt::DebugPrint { span, .. }
+            | Stmt::Assert { span, .. }
+            | Stmt::End { span }
+            | Stmt::Namespace { span, .. } => *span,
+            Stmt::ChoiceSet { choices, .. } => choices
+                .first()
+                .map_or(Span { start: 0, end: 0 }, |choice| choice.span),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Choice {
+    /// Which marker introduced the choice (see [`crate::ScannerConfig`])
+    pub marker: ChoiceMarker,
+    /// Stable id from `- (label) text`, for analytics and save data
+    pub label: Option<String>,
     pub parts: Vec<TextPart>,
+    /// The whole choice line, from the `-` marker to the end of the text
     pub span: Span,
+    /// Just the displayed text, after the marker, label, and any spaces
+    pub text_span: Span,
+    /// Trailing `#tags`, without the `#`, for hosts to style the option
+    pub tags: Vec<String>,
     /// Nested statements to execute when this choice is selected
     pub nested: Vec<Stmt>,
 }
 
-/// A part of text content - either literal text or a variable reference
+/// The character that starts a choice line.
+///
+/// Only `-` is recognized unless the scanner is configured otherwise. The
+/// runtime treats all markers alike; the kind is kept so `*` and `+` can later
+/// mean once-only and sticky choices, as in Ink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ChoiceMarker {
+    /// `- `
+    Dash,
+    /// `* `
+    Star,
+    /// `+ `
+    Plus,
+}
+
+impl ChoiceMarker {
+    /// The marker as written in source, without the following space.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            ChoiceMarker::Dash => "-",
+            ChoiceMarker::Star => "*",
+            ChoiceMarker::Plus => "+",
+        }
+    }
+
+    pub(crate) fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            "-" => Some(ChoiceMarker::Dash),
+            "*" => Some(ChoiceMarker::Star),
+            "+" => Some(ChoiceMarker::Plus),
+            _ => None,
+        }
+    }
+}
+
+/// A part of text content - literal text, a variable reference, or a conditional block
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum TextPart {
     Literal {
         text: String,
@@ -38,31 +201,179 @@ pub enum TextPart {
     VarRef {
         id: NodeId,
         name: String,
+        /// Decimal places from a `{name:.2}` format spec
+        precision: Option<usize>,
+        span: Span,
+    },
+    /// `{if name}then{else}otherwise{endif}`: shows `then` when the variable
+    /// is truthy and `otherwise` (empty without `{else}`) when it isn't.
+    Conditional {
+        /// Binding of the tested variable
+        id: NodeId,
+        name: String,
+        /// The tested variable's name inside `{if ...}`
+        name_span: Span,
+        then_parts: Vec<TextPart>,
+        else_parts: Vec<TextPart>,
+        /// From `{if` through `{endif}`
         span: Span,
     },
 }
 
 /// A literal value in declarations
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Literal {
     String(String),
     Number(f64),
     Bool(bool),
 }
 
+impl Literal {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Literal::String(_) => ValueType::String,
+            Literal::Number(_) => ValueType::Number,
+            Literal::Bool(_) => ValueType::Bool,
+        }
+    }
+}
+
+/// An expression. Only `assert` takes one so far, and it is limited to a
+/// single value or a comparison of two. An operand may be a comparison in
+/// parentheses; the parentheses leave no node of their own, but the grouped
+/// expression's span covers them.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Expr {
+    Literal {
+        value: Literal,
+        span: Span,
+    },
+    Var {
+        id: NodeId,
+        name: String,
+        span: Span,
+    },
+    Compare {
+        op: CompareOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        span: Span,
+    },
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal { span, .. } | Expr::Var { span, .. } | Expr::Compare { span, .. } => {
+                *span
+            }
+        }
+    }
+}
+
+/// A comparison operator: `==`, `!=`, `<`, `<=`, `>` or `>=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl CompareOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            CompareOp::Equal => "==",
+            CompareOp::NotEqual => "!=",
+            CompareOp::Less => "<",
+            CompareOp::LessEqual => "<=",
+            CompareOp::Greater => ">",
+            CompareOp::GreaterEqual => ">=",
+        }
+    }
+}
+
+/// An arithmetic operator, as used in compound assignment: `set gold += 5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ArithOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl ArithOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            ArithOp::Add => "+",
+            ArithOp::Subtract => "-",
+            ArithOp::Multiply => "*",
+            ArithOp::Divide => "/",
+        }
+    }
+}
+
 /// Shared data for variable binding operations (declarations and assignments)
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct VarBindingData {
     pub id: NodeId,
     pub name: String,
     pub value: Literal,
     pub span: Span,
+    /// For compound assignment (`set gold += 5`), the operator that combines
+    /// the current value with `value`. Declarations never have one.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub op: Option<ArithOp>,
+}
+
+/// A type named in a declaration: `extern gold: number`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ValueType {
+    Number,
+    String,
+    Bool,
+}
+
+impl ValueType {
+    /// Parse a type name as written in source.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "number" => Some(ValueType::Number),
+            "string" => Some(ValueType::String),
+            "bool" => Some(ValueType::Bool),
+            _ => None,
+        }
+    }
+
+    /// The name as written in source, which is also what
+    /// [`crate::Value::type_name`] returns for values of this type.
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueType::Number => "number",
+            ValueType::String => "string",
+            ValueType::Bool => "bool",
+        }
+    }
 }
 
 /// Declaration of a host-provided variable (read-only from dialogue perspective)
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ExternDeclData {
     pub id: NodeId,
     pub name: String,
+    /// Fallback used when the host doesn't provide the variable
+    pub default: Option<Literal>,
     pub span: Span,
+    /// Type the host's value must have, from `extern gold: number`
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub ty: Option<ValueType>,
 }
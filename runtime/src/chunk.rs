@@ -1,3 +1,9 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::ast::{ArithOp, CompareOp, ValueType};
+use crate::diagnostic::offset_to_position;
+
 #[derive(Debug, Clone)]
 pub enum Instruction {
     Constant {
@@ -11,6 +17,15 @@ pub enum Instruction {
     SetLocal {
         slot: usize,
     },
+    /// Discard the top `count` values: the temps of a choice branch, as it ends.
+    Pop {
+        count: usize,
+    },
+    /// Pop a value and push it as text with exactly `precision` decimal places
+    /// if it is a number; other values are pushed back unchanged.
+    FormatNumber {
+        precision: usize,
+    },
     /// Pop `count` values, concatenate as strings, push result.
     Concat {
         count: usize,
@@ -18,14 +33,30 @@ pub enum Instruction {
     Line,
     /// Present choices to the user. VM pauses for selection.
     /// On resume, jumps to targets[selected_index].
+    /// If `shuffle` is set, choices are presented in a random order.
     ChoiceSet {
         count: usize,
         targets: Vec<usize>,
+        /// Where every branch rejoins once its content runs out.
+        gather: usize,
+        shuffle: bool,
+        /// The `(label)` of each choice, in source order.
+        labels: Vec<Option<String>>,
     },
     /// Unconditional jump to target instruction index.
     Jump {
         target: usize,
     },
+    /// Pop a value; jump to target instruction index if it is falsy
+    /// (see [`Value::coerce_bool`]).
+    JumpIfFalse {
+        target: usize,
+    },
+    /// Push whether the counter stored under `key` is zero (absent counts as
+    /// zero), then increment the counter. Backs `once` lines.
+    CheckOnce {
+        key: String,
+    },
     /// Initialize a save variable only if it doesn't exist in storage.
     /// Pops value from stack, calls storage.initialize_if_absent(name, value).
     InitStorage {
@@ -39,21 +70,113 @@ pub enum Instruction {
     SetStorage {
         name: String,
     },
-    /// Read a host variable via HostState and push onto stack.
+    /// Read a host variable via HostState and push onto stack, falling back
+    /// to the declared default when the host doesn't provide it.
     GetHost {
         name: String,
     },
+    /// Pop two values and push the result of `left op right`
+    /// (see [`crate::values`]).
+    Arithmetic {
+        op: ArithOp,
+    },
+    /// Pop two values and push whether they compare as `op` says
+    /// (see [`crate::values`]).
+    Compare {
+        op: CompareOp,
+    },
+    /// Pop a value; if it is falsy, stop with
+    /// [`RuntimeError::AssertionFailed`](crate::RuntimeError::AssertionFailed).
+    Assert {
+        /// The asserted condition as written
+        message: String,
+        /// 1-based source line of the `assert`
+        line: usize,
+    },
+    /// Pass `message` to [`HostState::debug_print`](crate::HostState::debug_print).
+    DebugPrint {
+        message: String,
+    },
     Return,
 }
 
+impl Instruction {
+    /// Net change in stack height when this instruction executes.
+    pub fn stack_effect(&self) -> isize {
+        match self {
+            Instruction::Constant { .. }
+            | Instruction::GetLocal { .. }
+            | Instruction::GetStorage { .. }
+            | Instruction::GetHost { .. }
+            | Instruction::CheckOnce { .. } => 1,
+            Instruction::SetLocal { .. }
+            | Instruction::Line
+            | Instruction::InitStorage { .. }
+            | Instruction::SetStorage { .. }
+            | Instruction::JumpIfFalse { .. }
+            | Instruction::Arithmetic { .. }
+            | Instruction::Compare { .. }
+            | Instruction::Assert { .. } => -1,
+            Instruction::Pop { count } => -(*count as isize),
+            Instruction::Concat { count } => 1 - *count as isize,
+            Instruction::ChoiceSet { count, .. } => -(*count as isize),
+            Instruction::FormatNumber { .. }
+            | Instruction::Jump { .. }
+            | Instruction::DebugPrint { .. }
+            | Instruction::Return => 0,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     String(String),
     Number(f64),
     Bool(bool),
+    /// Absence of a value. Interpolates as an empty string.
+    Null,
 }
 
 impl Value {
+    /// Name of this value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::Null => "null",
+        }
+    }
+
+    /// Truthiness of this value when used as a condition.
+    ///
+    /// Booleans are themselves; numbers are true unless zero or NaN; strings
+    /// are true unless empty (so `"false"` is true); null is false.
+    pub fn coerce_bool(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Number(n) => *n != 0.0 && !n.is_nan(),
+            Value::String(s) => !s.is_empty(),
+            Value::Null => false,
+        }
+    }
+
+    /// A hashable stand-in for this value, for use as a map or set key.
+    ///
+    /// Keys are equal exactly when the values are equal, except that every
+    /// NaN maps to the same key (NaN never equals itself, so it could not
+    /// otherwise be looked up). `-0.0` and `0.0` share a key, as they compare equal.
+    pub fn eq_key(&self) -> ValueKey {
+        match self {
+            Value::String(s) => ValueKey::String(s.clone()),
+            Value::Number(n) if n.is_nan() => ValueKey::Number(f64::NAN.to_bits()),
+            Value::Number(n) if *n == 0.0 => ValueKey::Number(0.0f64.to_bits()),
+            Value::Number(n) => ValueKey::Number(n.to_bits()),
+            Value::Bool(b) => ValueKey::Bool(*b),
+            Value::Null => ValueKey::Null,
+        }
+    }
+
     /// Convert value to string representation for interpolation.
     pub fn to_string_value(&self) -> String {
         match self {
@@ -67,15 +190,72 @@ impl Value {
                 }
             }
             Value::Bool(b) => if *b { "true" } else { "false" }.to_string(),
+            Value::Null => String::new(),
         }
     }
 }
 
-#[derive(Debug)]
+/// Hashable form of a [`Value`], produced by [`Value::eq_key`].
+///
+/// Numbers are stored as their normalized bit pattern.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ValueKey {
+    String(String),
+    Number(u64),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Chunk {
     pub code: Vec<Instruction>,
     pub constants: Vec<Value>,
     pub lines: Vec<usize>,
+    /// Declared default value of each save variable, keyed by name.
+    pub save_defaults: HashMap<String, Value>,
+    /// Fallback value of each extern variable that declares one, keyed by name.
+    pub extern_defaults: HashMap<String, Value>,
+    /// Declared type of each extern variable that has one, keyed by name.
+    pub extern_types: HashMap<String, ValueType>,
+    /// Deepest the value stack can grow, as tracked by the compiler.
+    pub max_stack_depth: usize,
+    /// Stack height the compiler expects at the final `Return`: the top-level
+    /// temps, which stay in scope to the end. Earlier returns, from `end`,
+    /// expect an empty stack.
+    pub return_depth: usize,
+    /// `//!` author notes, keyed by the offset of their line's `Line` instruction.
+    pub debug_notes: HashMap<usize, String>,
+    /// Un-interpolated text of each line as written (`Hello, {name}!`), keyed
+    /// by the offset of its `Line` instruction.
+    pub templates: HashMap<usize, String>,
+    /// Un-interpolated text of each choice, in source order, keyed by the
+    /// offset of their `ChoiceSet` instruction.
+    pub choice_templates: HashMap<usize, Vec<String>>,
+    /// 1-based source line of each line of dialogue, keyed by the offset of
+    /// its `Line` instruction.
+    pub line_numbers: HashMap<usize, usize>,
+    /// Tags of each choice, in source order, keyed by the offset of their
+    /// `ChoiceSet` instruction. Sets without any tags have no entry.
+    pub choice_tags: HashMap<usize, Vec<Vec<String>>>,
+    /// How many choice branches enclose each `Line` and `ChoiceSet`, keyed by
+    /// instruction offset. Top-level statements have no entry.
+    pub nesting: HashMap<usize, usize>,
+    /// From a leading `namespace` directive; prefixes the storage keys that
+    /// record picked choices, `once` lines and seen lines.
+    pub namespace: Option<String>,
+    /// How many instructions at the start of `code` come from the prelude.
+    /// Their `lines` entries are offsets into the prelude's source, not the
+    /// script's.
+    pub prelude_len: usize,
+}
+
+/// Size metrics for a compiled chunk, for spotting pathological scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkStats {
+    pub instruction_count: usize,
+    pub constant_count: usize,
+    pub choice_point_count: usize,
+    pub max_stack_depth: usize,
 }
 
 impl Chunk {
@@ -84,6 +264,19 @@ impl Chunk {
             code: Vec::new(),
             constants: Vec::new(),
             lines: Vec::new(),
+            save_defaults: HashMap::new(),
+            extern_defaults: HashMap::new(),
+            extern_types: HashMap::new(),
+            max_stack_depth: 0,
+            return_depth: 0,
+            debug_notes: HashMap::new(),
+            templates: HashMap::new(),
+            choice_templates: HashMap::new(),
+            line_numbers: HashMap::new(),
+            choice_tags: HashMap::new(),
+            nesting: HashMap::new(),
+            namespace: None,
+            prelude_len: 0,
         }
     }
 
@@ -102,21 +295,338 @@ impl Chunk {
         self.code.len()
     }
 
-    /// Patch a Jump instruction at `offset` to jump to `target`.
+    /// Patch a Jump or JumpIfFalse instruction at `offset` to jump to `target`.
     pub fn patch_jump(&mut self, offset: usize, target: usize) {
-        if let Instruction::Jump { target: ref mut t } = self.code[offset] {
-            *t = target;
-        } else {
-            panic!("patch_jump called on non-Jump instruction");
+        match self.code[offset] {
+            Instruction::Jump { target: ref mut t }
+            | Instruction::JumpIfFalse { target: ref mut t } => *t = target,
+            _ => panic!("patch_jump called on non-Jump instruction"),
+        }
+    }
+
+    /// Every decision point in the script as `(offset, choice_count)`, in code order.
+    ///
+    /// `offset` indexes into `code`; `lines[offset]` is the source byte offset of
+    /// the first choice in the set.
+    pub fn choice_points(&self) -> Vec<(usize, usize)> {
+        self.code
+            .iter()
+            .enumerate()
+            .filter_map(|(offset, instruction)| match instruction {
+                Instruction::ChoiceSet { count, .. } => Some((offset, *count)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Where a VM paused at `ip` in this chunk, compiled from `source`, would be
+    /// paused in `new`, compiled from `new_source`.
+    ///
+    /// The position only carries over if everything that already ran is the
+    /// same in both versions, so every temp on the stack still fits: nothing up
+    /// to the end of the line just shown (or the first option of the pending
+    /// choice set) may have changed. A pending choice set must also have the
+    /// same options, though the branches under them may have been edited.
+    /// Returns `None` if the position doesn't carry over.
+    pub fn remap_pause(
+        &self,
+        source: &str,
+        new: &Chunk,
+        new_source: &str,
+        ip: usize,
+    ) -> Option<usize> {
+        let pending = self.code.get(ip);
+        let anchor = match pending {
+            Some(Instruction::ChoiceSet { .. }) => self.lines[ip],
+            _ => *self.lines.get(ip.checked_sub(1)?)?,
+        };
+        let end = anchor + source_line(source, anchor)?.len();
+        if new_source.get(..end)? != &source[..end] {
+            return None;
+        }
+
+        let mut new_code = new.code.iter().zip(&new.lines).enumerate();
+        match pending {
+            Some(Instruction::ChoiceSet {
+                count,
+                shuffle,
+                labels,
+                ..
+            }) => {
+                let options = self.option_lines(source, ip, *count)?;
+                new_code
+                    .find(|(offset, (instruction, line))| {
+                        **line == anchor
+                            && matches!(
+                                instruction,
+                                Instruction::ChoiceSet { count: c, shuffle: s, labels: l, .. }
+                                    if c == count && s == shuffle && l == labels
+                            )
+                            && new.option_lines(new_source, *offset, *count).as_ref()
+                                == Some(&options)
+                    })
+                    .map(|(offset, _)| offset)
+            }
+            _ if matches!(self.code[ip - 1], Instruction::Line) => new_code
+                .find(|(_, (instruction, line))| {
+                    **line == anchor && matches!(instruction, Instruction::Line)
+                })
+                .map(|(offset, _)| offset + 1),
+            _ => None,
+        }
+    }
+
+    /// Source line of each option of the choice set at `offset`. The options'
+    /// texts are evaluated just before the set, each tagged with its own line.
+    fn option_lines<'s>(
+        &self,
+        source: &'s str,
+        offset: usize,
+        count: usize,
+    ) -> Option<Vec<&'s str>> {
+        let mut starts: Vec<usize> = Vec::with_capacity(count);
+        for &line in self.lines[..offset].iter().rev() {
+            if starts.len() == count {
+                break;
+            }
+            if starts.last() != Some(&line) {
+                starts.push(line);
+            }
+        }
+        starts
+            .iter()
+            .rev()
+            .map(|&start| source_line(source, start))
+            .collect()
+    }
+
+    /// Every host variable the script reads, each once, in order of first read.
+    pub fn host_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for instruction in &self.code {
+            if let Instruction::GetHost { name } = instruction
+                && !names.contains(&name.as_str())
+            {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    pub fn stats(&self) -> ChunkStats {
+        ChunkStats {
+            instruction_count: self.code.len(),
+            constant_count: self.constants.len(),
+            choice_point_count: self.choice_points().len(),
+            max_stack_depth: self.max_stack_depth,
         }
     }
 
-    /// Patch a ChoiceSet instruction's targets at `offset`.
-    pub fn patch_choice_targets(&mut self, offset: usize, new_targets: Vec<usize>) {
-        if let Instruction::ChoiceSet { targets, .. } = &mut self.code[offset] {
+    /// Human-readable listing of the bytecode, one instruction per line.
+    ///
+    /// Each row shows the instruction offset and the 1-based line in `source`
+    /// (the script this chunk was compiled from) that produced it; `|` marks
+    /// the same source line as the row above. Instructions compiled from a
+    /// prelude show `pre` instead, since they come from another file.
+    pub fn disassemble(&self, source: &str) -> String {
+        let mut out = String::new();
+        let mut previous_line = None;
+        for (offset, instruction) in self.code.iter().enumerate() {
+            if offset < self.prelude_len {
+                let _ = writeln!(out, "{:04}  pre {}", offset, self.describe(instruction));
+                continue;
+            }
+            let line = offset_to_position(source, self.lines[offset]).line;
+            if previous_line == Some(line) {
+                let _ = write!(out, "{:04}    | ", offset);
+            } else {
+                let _ = write!(out, "{:04} {:>4} ", offset, line);
+            }
+            previous_line = Some(line);
+            let _ = writeln!(out, "{}", self.describe(instruction));
+        }
+        out
+    }
+
+    fn describe(&self, instruction: &Instruction) -> String {
+        match instruction {
+            Instruction::Constant { index } => {
+                let value = match &self.constants[*index] {
+                    Value::String(s) => format!("{:?}", s),
+                    Value::Null => "null".to_string(),
+                    other => other.to_string_value(),
+                };
+                format!("CONSTANT {} ({})", index, value)
+            }
+            Instruction::GetLocal { slot } => format!("GET_LOCAL {}", slot),
+            Instruction::SetLocal { slot } => format!("SET_LOCAL {}", slot),
+            Instruction::Pop { count } => format!("POP {}", count),
+            Instruction::FormatNumber { precision } => format!("FORMAT_NUMBER {}", precision),
+            Instruction::Concat { count } => format!("CONCAT {}", count),
+            Instruction::Line => "LINE".to_string(),
+            Instruction::ChoiceSet {
+                targets, shuffle, ..
+            } => {
+                let targets: Vec<String> = targets.iter().map(|t| format!("{:04}", t)).collect();
+                let shuffle = if *shuffle { " shuffle" } else { "" };
+                format!("CHOICE_SET -> [{}]{}", targets.join(", "), shuffle)
+            }
+            Instruction::Jump { target } => format!("JUMP -> {:04}", target),
+            Instruction::JumpIfFalse { target } => format!("JUMP_IF_FALSE -> {:04}", target),
+            Instruction::CheckOnce { key } => format!("CHECK_ONCE {:?}", key),
+            Instruction::InitStorage { name } => format!("INIT_STORAGE {}", name),
+            Instruction::GetStorage { name } => format!("GET_STORAGE {}", name),
+            Instruction::SetStorage { name } => format!("SET_STORAGE {}", name),
+            Instruction::GetHost { name } => format!("GET_HOST {}", name),
+            Instruction::Arithmetic { op } => format!("ARITHMETIC {}", op.symbol()),
+            Instruction::Compare { op } => format!("COMPARE {}", op.symbol()),
+            Instruction::Assert { message, .. } => format!("ASSERT {:?}", message),
+            Instruction::DebugPrint { message } => format!("DEBUG_PRINT {:?}", message),
+            Instruction::Return => "RETURN".to_string(),
+        }
+    }
+
+    /// Patch a ChoiceSet instruction's targets and gather point at `offset`.
+    pub fn patch_choice_targets(
+        &mut self,
+        offset: usize,
+        new_targets: Vec<usize>,
+        new_gather: usize,
+    ) {
+        if let Instruction::ChoiceSet {
+            targets, gather, ..
+        } = &mut self.code[offset]
+        {
             *targets = new_targets;
+            *gather = new_gather;
         } else {
             panic!("patch_choice_targets called on non-ChoiceSet instruction");
         }
     }
 }
+
+/// The rest of the source line starting at byte `offset`, including its newline.
+fn source_line(source: &str, offset: usize) -> Option<&str> {
+    let rest = source.get(offset..)?;
+    let Some(newline) = rest.find(['\n', '\r']) else {
+        return Some(rest);
+    };
+    let width = if rest[newline..].starts_with("\r\n") {
+        2
+    } else {
+        1
+    };
+    Some(&rest[..newline + width])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkStats, Value};
+    use std::collections::HashSet;
+
+    #[test]
+    fn choice_points_in_nested_fixture() {
+        let source = include_str!("../tests/cases/choices/nested.bobbin");
+        let chunk = crate::compile(source).unwrap();
+
+        let points = chunk.choice_points();
+        let counts: Vec<usize> = points.iter().map(|&(_, count)| count).collect();
+        assert_eq!(counts, vec![2, 2]);
+
+        let sources: Vec<&str> = points
+            .iter()
+            .map(|&(offset, _)| &source[chunk.lines[offset]..])
+            .collect();
+        assert!(sources[0].starts_with("- Talk to someone"));
+        assert!(sources[1].starts_with("- Alice"));
+    }
+
+    #[test]
+    fn stats_for_nested_fixture() {
+        let chunk = crate::compile(include_str!("../tests/cases/choices/nested.bobbin")).unwrap();
+
+        assert_eq!(
+            chunk.stats(),
+            ChunkStats {
+                instruction_count: 25,
+                constant_count: 11,
+                choice_point_count: 2,
+                max_stack_depth: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn disassemble_shows_source_lines() {
+        let source = "save gold = 5\nYou have {gold} gold.\n- Spend\n- Keep\n";
+        let chunk = crate::compile(source).unwrap();
+
+        let listing = chunk.disassemble(source);
+        let rows: Vec<&str> = listing.lines().collect();
+        assert_eq!(rows.len(), chunk.code.len());
+        assert_eq!(rows[0], "0000    1 CONSTANT 0 (5)");
+        assert_eq!(rows[1], "0001    | INIT_STORAGE gold");
+        assert_eq!(rows[2], "0002    2 CONSTANT 1 (\"You have \")");
+        assert!(rows.iter().any(|row| row.contains("CHOICE_SET -> [")));
+        assert!(rows.last().unwrap().ends_with("RETURN"));
+    }
+
+    #[test]
+    fn disassemble_labels_prelude_instructions() {
+        let options = crate::RuntimeOptions {
+            prelude: Some(crate::Prelude::new(
+                "globals.bobbin",
+                "save reputation = 10\nsave met_king = false\n",
+            )),
+            ..Default::default()
+        };
+        let source = "You have {reputation} reputation.\n";
+        let chunk = crate::compile_with_options(source, &options).unwrap();
+        assert_eq!(chunk.prelude_len, 4);
+
+        let listing = chunk.disassemble(source);
+        let rows: Vec<&str> = listing.lines().collect();
+        assert_eq!(rows[0], "0000  pre CONSTANT 0 (10)");
+        assert_eq!(rows[3], "0003  pre INIT_STORAGE met_king");
+        assert_eq!(rows[4], "0004    1 CONSTANT 2 (\"You have \")");
+    }
+
+    #[test]
+    fn coerce_bool_truthiness() {
+        assert!(Value::Bool(true).coerce_bool());
+        assert!(!Value::Bool(false).coerce_bool());
+        assert!(Value::Number(3.0).coerce_bool());
+        assert!(Value::Number(-0.5).coerce_bool());
+        assert!(!Value::Number(0.0).coerce_bool());
+        assert!(!Value::Number(-0.0).coerce_bool());
+        assert!(!Value::Number(f64::NAN).coerce_bool());
+        assert!(Value::String("false".to_string()).coerce_bool());
+        assert!(Value::String("0".to_string()).coerce_bool());
+        assert!(!Value::String(String::new()).coerce_bool());
+        assert!(!Value::Null.coerce_bool());
+    }
+
+    #[test]
+    fn eq_key_matches_value_equality() {
+        assert_eq!(Value::Number(1.0).eq_key(), Value::Number(1.0).eq_key());
+        assert_ne!(Value::Number(1.0).eq_key(), Value::Number(1.5).eq_key());
+        assert_ne!(
+            Value::Number(1.0).eq_key(),
+            Value::String("1".to_string()).eq_key()
+        );
+        assert_ne!(Value::Bool(false).eq_key(), Value::Null.eq_key());
+        assert_eq!(Value::Number(-0.0).eq_key(), Value::Number(0.0).eq_key());
+    }
+
+    #[test]
+    fn eq_key_collapses_nan() {
+        let nan = Value::Number(f64::NAN);
+        let other_nan = Value::Number(-f64::NAN);
+        assert_ne!(nan, nan);
+
+        let keys: HashSet<_> = [nan.eq_key(), other_nan.eq_key(), nan.eq_key()]
+            .into_iter()
+            .collect();
+        assert_eq!(keys.len(), 1);
+    }
+}
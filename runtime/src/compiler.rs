@@ -1,6 +1,9 @@
-use crate::ast::{Literal, NodeId, Script, Stmt, TextPart, VarBindingData};
+use crate::ast::{Expr, ExternDeclData, Literal, NodeId, Script, Stmt, TextPart, VarBindingData};
 use crate::chunk::{Chunk, Instruction, Value};
+use crate::diagnostic::offset_to_position;
+use crate::emitter::render_text;
 use crate::resolver::SymbolTable;
+use crate::token::Span;
 
 #[derive(Debug, Clone)]
 pub enum CompileError {}
@@ -8,28 +11,71 @@ pub enum CompileError {}
 #[derive(Debug)]
 pub struct Compiler<'a> {
     ast: &'a Script,
+    prelude: Option<&'a Script>,
     chunk: Chunk,
     symbols: &'a SymbolTable,
+    /// The script's source, for assertion messages and line numbers.
+    source: &'a str,
+    /// Stack height after the last emitted instruction.
+    depth: usize,
+    /// How many choice branches enclose the statement being compiled.
+    nesting: usize,
+    /// Byte offset where each source line starts, for `Chunk::line_numbers`.
+    line_starts: Vec<usize>,
 }
 
 impl<'a> Compiler<'a> {
-    pub fn new(ast: &'a Script, symbols: &'a SymbolTable) -> Self {
+    pub fn new(ast: &'a Script, symbols: &'a SymbolTable, source: &'a str) -> Self {
         Self {
             ast,
+            prelude: None,
             chunk: Chunk::new(),
             symbols,
+            source,
+            depth: 0,
+            nesting: 0,
+            line_starts: line_starts(source),
         }
     }
 
+    /// Run a prelude's declarations before the script. The prelude may only
+    /// contain `save` and `extern` declarations, which need no symbol bindings.
+    pub fn with_prelude(mut self, prelude: &'a Script) -> Self {
+        self.prelude = Some(prelude);
+        self
+    }
+
     pub fn compile(mut self) -> Result<Chunk, CompileError> {
+        if let Some(prelude) = self.prelude {
+            for stmt in &prelude.statements {
+                match stmt {
+                    // Prelude variables are shared by every file, so they're
+                    // never namespaced (and have no bindings in this script)
+                    Stmt::SaveDecl(data) => {
+                        self.compile_save_decl(data.name.clone(), &data.value, data.span)
+                    }
+                    _ => self.compile_stmt(stmt),
+                }
+            }
+            self.chunk.prelude_len = self.chunk.current_offset();
+        }
+
         for stmt in &self.ast.statements {
             self.compile_stmt(stmt);
         }
 
-        self.chunk.emit(Instruction::Return, 0);
+        self.chunk.return_depth = self.depth;
+        self.emit(Instruction::Return, 0);
         Ok(self.chunk)
     }
 
+    /// Emit an instruction, tracking how deep the stack gets.
+    fn emit(&mut self, instruction: Instruction, line: usize) {
+        self.depth = self.depth.saturating_add_signed(instruction.stack_effect());
+        self.chunk.max_stack_depth = self.chunk.max_stack_depth.max(self.depth);
+        self.chunk.emit(instruction, line);
+    }
+
     /// Look up the stack slot for a NodeId. Panics if not found (resolver bug).
     fn get_slot(&self, id: NodeId) -> usize {
         *self
@@ -49,17 +95,21 @@ impl<'a> Compiler<'a> {
         self.symbols.extern_bindings.get(&id).map(|s| s.as_str())
     }
 
-    /// Emit instruction to read a variable (temp, save, or extern) and push onto stack.
+    /// Emit instruction to read a variable (temp, save, extern, or constant) and push onto stack.
     fn emit_var_read(&mut self, id: NodeId, line: usize) {
-        if let Some(name) = self.get_save_name(id) {
-            self.chunk.emit(
+        if let Some(value) = self.symbols.constant_bindings.get(&id) {
+            // Constants are fixed when the runtime is created, so they're inlined
+            let index = self.chunk.add_constant(value.clone());
+            self.emit(Instruction::Constant { index }, line);
+        } else if let Some(name) = self.get_save_name(id) {
+            self.emit(
                 Instruction::GetStorage {
                     name: name.to_string(),
                 },
                 line,
             );
         } else if let Some(name) = self.get_extern_name(id) {
-            self.chunk.emit(
+            self.emit(
                 Instruction::GetHost {
                     name: name.to_string(),
                 },
@@ -67,14 +117,14 @@ impl<'a> Compiler<'a> {
             );
         } else {
             let slot = self.get_slot(id);
-            self.chunk.emit(Instruction::GetLocal { slot }, line);
+            self.emit(Instruction::GetLocal { slot }, line);
         }
     }
 
     /// Emit instruction to write a value (already on stack) to a variable (temp or save).
     fn emit_var_write(&mut self, id: NodeId, line: usize) {
         if let Some(name) = self.get_save_name(id) {
-            self.chunk.emit(
+            self.emit(
                 Instruction::SetStorage {
                     name: name.to_string(),
                 },
@@ -82,45 +132,154 @@ impl<'a> Compiler<'a> {
             );
         } else {
             let slot = self.get_slot(id);
-            self.chunk.emit(Instruction::SetLocal { slot }, line);
+            self.emit(Instruction::SetLocal { slot }, line);
+        }
+    }
+
+    /// Assignment modifies an existing variable (temp or save): push the new
+    /// value, then write it. A compound assignment (`set gold += 5`) reads
+    /// the current value first and combines the two.
+    fn compile_assignment(&mut self, data: &VarBindingData) {
+        let VarBindingData {
+            id,
+            value,
+            span,
+            op,
+            ..
+        } = data;
+        if let Some(op) = op {
+            self.emit_var_read(*id, span.start);
+            self.compile_literal(value, span.start);
+            self.emit(Instruction::Arithmetic { op: *op }, span.start);
+        } else {
+            self.compile_literal(value, span.start);
+        }
+        self.emit_var_write(*id, span.start);
+    }
+
+    /// Note how deeply the `Line` or `ChoiceSet` at `offset` is nested.
+    fn record_nesting(&mut self, offset: usize) {
+        if self.nesting > 0 {
+            self.chunk.nesting.insert(offset, self.nesting);
         }
     }
 
     fn compile_stmt(&mut self, stmt: &Stmt) {
         match stmt {
-            Stmt::TempDecl(VarBindingData { value, span, .. }) => {
+            Stmt::TempDecl(VarBindingData {
+                id, value, span, ..
+            }) => {
                 // Push initial value onto stack.
                 // The value lives at its assigned slot position (implicit from declaration order).
-                self.compile_literal(value, span.start);
+                if let Some(value) = self.symbols.initial_temps.get(id) {
+                    let index = self.chunk.add_constant(value.clone());
+                    self.emit(Instruction::Constant { index }, span.start);
+                } else {
+                    self.compile_literal(value, span.start);
+                }
             }
             Stmt::SaveDecl(VarBindingData {
-                name, value, span, ..
+                id, value, span, ..
             }) => {
-                // Push initial value onto stack, then emit InitStorage.
-                // InitStorage uses "initialize if absent" semantics for save variables.
-                self.compile_literal(value, span.start);
-                self.chunk
-                    .emit(Instruction::InitStorage { name: name.clone() }, span.start);
-            }
-            Stmt::ExternDecl(_) => {
-                // No-op: extern declarations don't generate code.
-                // The host provides values on-demand when GetHost executes.
+                let key = self
+                    .get_save_name(*id)
+                    .expect("binding not found: resolver bug")
+                    .to_string();
+                self.compile_save_decl(key, value, *span);
             }
-            Stmt::Assignment(VarBindingData {
-                id, value, span, ..
+            // No code: the resolver inlines the value wherever it is read
+            Stmt::ConstDecl(_) => {}
+            Stmt::ExternDecl(ExternDeclData {
+                name, default, ty, ..
             }) => {
-                // Assignment modifies an existing variable (temp or save).
-                // Push value, then emit appropriate write instruction.
-                self.compile_literal(value, span.start);
-                self.emit_var_write(*id, span.start);
+                // No code: the host provides values on-demand when GetHost executes.
+                // A declared default is kept for when it doesn't, and a declared
+                // type for checking what it provides.
+                if let Some(default) = default {
+                    self.chunk
+                        .extern_defaults
+                        .insert(name.clone(), literal_value(default));
+                }
+                if let Some(ty) = ty {
+                    self.chunk.extern_types.insert(name.clone(), *ty);
+                }
             }
-            Stmt::Line { parts, span } => {
+            Stmt::Assignment(data) => self.compile_assignment(data),
+            // Applied by the resolver when binding save variables
+            Stmt::Namespace { name, .. } => self.chunk.namespace = Some(name.clone()),
+            Stmt::End { span } => {
+                // Every Return but the last expects an empty stack. Anything
+                // compiled after this is unreachable, but keeps the stack
+                // height it would have had, so scopes still close correctly.
+                let depth = self.depth;
+                if depth > 0 {
+                    self.emit(Instruction::Pop { count: depth }, span.start);
+                }
+                self.emit(Instruction::Return, span.start);
+                self.depth = depth;
+            }
+            Stmt::DebugPrint { message, span } => {
+                self.emit(
+                    Instruction::DebugPrint {
+                        message: message.clone(),
+                    },
+                    span.start,
+                );
+            }
+            Stmt::Assert { condition, span } => {
+                self.compile_expr(condition);
+                let message = self.source[condition.span().start..condition.span().end].to_string();
+                let line = offset_to_position(self.source, span.start).line;
+                self.emit(Instruction::Assert { message, line }, span.start);
+            }
+            Stmt::Line {
+                parts,
+                span,
+                once,
+                effect,
+                debug_note,
+            } => {
+                // A once line is skipped after its first showing:
+                //   CheckOnce; JumpIfFalse -> after; <text>; Line; <effect>; after:
+                let skip = once.then(|| {
+                    let key = once_key(self.chunk.namespace.as_deref(), parts, span.start);
+                    self.emit(Instruction::CheckOnce { key }, span.start);
+                    let offset = self.chunk.current_offset();
+                    self.emit(Instruction::JumpIfFalse { target: 0 }, span.start);
+                    offset
+                });
+
                 self.compile_text_parts(parts, span.start);
-                self.chunk.emit(Instruction::Line, span.start);
+                let offset = self.chunk.current_offset();
+                if let Some(note) = debug_note {
+                    self.chunk.debug_notes.insert(offset, note.clone());
+                }
+                self.chunk.templates.insert(offset, render_text(parts));
+                let line = self
+                    .line_starts
+                    .partition_point(|&start| start <= span.start);
+                self.chunk.line_numbers.insert(offset, line);
+                self.record_nesting(offset);
+                self.emit(Instruction::Line, span.start);
+                // The effect runs once the line has been shown, when the
+                // dialogue moves on from it
+                if let Some(effect) = effect {
+                    self.compile_assignment(effect);
+                }
+
+                if let Some(offset) = skip {
+                    let after = self.chunk.current_offset();
+                    self.chunk.patch_jump(offset, after);
+                }
             }
-            Stmt::ChoiceSet { choices } => {
+            Stmt::ChoiceSet { choices, shuffle } => {
+                // The parser never produces an empty set, but error recovery
+                // could; there is nothing to present, so emit nothing.
+                let Some(first) = choices.first() else {
+                    return;
+                };
                 let count = choices.len();
-                let line = choices[0].span.start;
+                let line = first.span.start;
 
                 // 1. Emit code for all choice texts (may involve interpolation)
                 for choice in choices {
@@ -129,26 +288,53 @@ impl<'a> Compiler<'a> {
 
                 // 2. Emit ChoiceSet with placeholder targets (VM pauses here)
                 let choice_set_offset = self.chunk.current_offset();
-                self.chunk.emit(
+                self.emit(
                     Instruction::ChoiceSet {
                         count,
                         targets: vec![0; count],
+                        gather: 0,
+                        shuffle: *shuffle,
+                        labels: choices.iter().map(|choice| choice.label.clone()).collect(),
                     },
                     line,
                 );
 
+                self.record_nesting(choice_set_offset);
+                let templates = choices
+                    .iter()
+                    .map(|choice| render_text(&choice.parts))
+                    .collect();
+                self.chunk
+                    .choice_templates
+                    .insert(choice_set_offset, templates);
+                if choices.iter().any(|choice| !choice.tags.is_empty()) {
+                    let tags = choices.iter().map(|choice| choice.tags.clone()).collect();
+                    self.chunk.choice_tags.insert(choice_set_offset, tags);
+                }
+
                 // 3. Emit nested code for each choice, collecting their start offsets
                 let mut choice_targets = Vec::with_capacity(count);
                 let mut jump_patches = Vec::new();
+                let branch_depth = self.depth;
 
                 for choice in choices {
                     // Record the start offset for this choice's nested code
                     choice_targets.push(self.chunk.current_offset());
+                    self.depth = branch_depth;
 
                     // Emit nested statements
+                    self.nesting += 1;
                     for nested_stmt in &choice.nested {
                         self.compile_stmt(nested_stmt);
                     }
+                    self.nesting -= 1;
+
+                    // The branch's temps go out of scope, and their slots are
+                    // reused after the gather point
+                    let scoped = self.depth - branch_depth;
+                    if scoped > 0 {
+                        self.emit(Instruction::Pop { count: scoped }, choice.span.start);
+                    }
 
                     // Emit Jump to gather point (placeholder target)
                     let jump_offset = self.chunk.current_offset();
@@ -167,11 +353,22 @@ impl<'a> Compiler<'a> {
 
                 // 6. Patch ChoiceSet with actual targets
                 self.chunk
-                    .patch_choice_targets(choice_set_offset, choice_targets);
+                    .patch_choice_targets(choice_set_offset, choice_targets, gather_point);
             }
         }
     }
 
+    /// Declare a save variable stored under `key`.
+    fn compile_save_decl(&mut self, key: String, value: &Literal, span: Span) {
+        // Push initial value onto stack, then emit InitStorage.
+        // InitStorage uses "initialize if absent" semantics for save variables.
+        self.compile_literal(value, span.start);
+        self.chunk
+            .save_defaults
+            .insert(key.clone(), literal_value(value));
+        self.emit(Instruction::InitStorage { name: key }, span.start);
+    }
+
     /// Compile text parts (literals and variable references) onto the stack.
     /// If there's only one literal part, just push it.
     /// If there are multiple parts, push all and emit Concat.
@@ -179,17 +376,17 @@ impl<'a> Compiler<'a> {
         if parts.is_empty() {
             // Empty text - push empty string
             let index = self.chunk.add_constant(Value::String(String::new()));
-            self.chunk.emit(Instruction::Constant { index }, line);
+            self.emit(Instruction::Constant { index }, line);
             return;
         }
 
         // Optimization: single literal part, no concat needed
-        if parts.len() == 1 {
-            if let TextPart::Literal { text, .. } = &parts[0] {
-                let index = self.chunk.add_constant(Value::String(text.clone()));
-                self.chunk.emit(Instruction::Constant { index }, line);
-                return;
-            }
+        if parts.len() == 1
+            && let TextPart::Literal { text, .. } = &parts[0]
+        {
+            let index = self.chunk.add_constant(Value::String(text.clone()));
+            self.emit(Instruction::Constant { index }, line);
+            return;
         }
 
         // Multiple parts or single var ref - push all and concat
@@ -197,29 +394,162 @@ impl<'a> Compiler<'a> {
             match part {
                 TextPart::Literal { text, span } => {
                     let index = self.chunk.add_constant(Value::String(text.clone()));
-                    self.chunk.emit(Instruction::Constant { index }, span.start);
+                    self.emit(Instruction::Constant { index }, span.start);
                 }
-                TextPart::VarRef { id, span, .. } => {
+                TextPart::VarRef {
+                    id,
+                    precision,
+                    span,
+                    ..
+                } => {
                     self.emit_var_read(*id, span.start);
+                    if let Some(precision) = *precision {
+                        self.emit(Instruction::FormatNumber { precision }, span.start);
+                    }
+                }
+                TextPart::Conditional {
+                    id,
+                    then_parts,
+                    else_parts,
+                    span,
+                    ..
+                } => {
+                    // Pushes one string either way:
+                    //   <read>; JumpIfFalse -> else; <then>; Jump -> after; else: <else>; after:
+                    self.emit_var_read(*id, span.start);
+                    let jump_to_else = self.chunk.current_offset();
+                    self.emit(Instruction::JumpIfFalse { target: 0 }, span.start);
+                    self.compile_text_parts(then_parts, span.start);
+                    let jump_to_after = self.chunk.current_offset();
+                    self.emit(Instruction::Jump { target: 0 }, span.start);
+
+                    // Only one branch runs, so the else branch starts without
+                    // the then branch's string on the stack
+                    self.depth -= 1;
+                    let else_start = self.chunk.current_offset();
+                    self.chunk.patch_jump(jump_to_else, else_start);
+                    self.compile_text_parts(else_parts, span.start);
+                    let after = self.chunk.current_offset();
+                    self.chunk.patch_jump(jump_to_after, after);
                 }
             }
         }
 
         // Concat if more than one part
         if parts.len() > 1 {
-            self.chunk
-                .emit(Instruction::Concat { count: parts.len() }, line);
+            self.emit(Instruction::Concat { count: parts.len() }, line);
+        }
+    }
+
+    /// Compile an expression, leaving its value on the stack.
+    fn compile_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { value, span } => self.compile_literal(value, span.start),
+            Expr::Var { id, span, .. } => self.emit_var_read(*id, span.start),
+            Expr::Compare {
+                op,
+                left,
+                right,
+                span,
+            } => {
+                self.compile_expr(left);
+                self.compile_expr(right);
+                self.emit(Instruction::Compare { op: *op }, span.start);
+            }
         }
     }
 
     /// Compile a literal value and push onto stack.
     fn compile_literal(&mut self, literal: &Literal, line: usize) {
-        let value = match literal {
-            Literal::String(s) => Value::String(s.clone()),
-            Literal::Number(n) => Value::Number(*n),
-            Literal::Bool(b) => Value::Bool(*b),
+        let index = self.chunk.add_constant(literal_value(literal));
+        self.emit(Instruction::Constant { index }, line);
+    }
+}
+
+/// Storage key for a `once` line's counter.
+///
+/// Storage is shared by every script the host runs, so the key combines the
+/// file's namespace, if any, with the line's source offset and its text. The
+/// `:` keeps it clear of variable names.
+fn once_key(namespace: Option<&str>, parts: &[TextPart], offset: usize) -> String {
+    let mut key = match namespace {
+        Some(namespace) => format!("once:{}.{}:", namespace, offset),
+        None => format!("once:{}:", offset),
+    };
+    push_key_text(&mut key, parts);
+    key
+}
+
+/// Append `parts` to `key` as written in the source.
+fn push_key_text(key: &mut String, parts: &[TextPart]) {
+    for part in parts {
+        match part {
+            TextPart::Literal { text, .. } => key.push_str(text),
+            TextPart::VarRef { name, .. } => {
+                key.push('{');
+                key.push_str(name);
+                key.push('}');
+            }
+            TextPart::Conditional {
+                name,
+                then_parts,
+                else_parts,
+                ..
+            } => {
+                key.push_str("{if ");
+                key.push_str(name);
+                key.push('}');
+                push_key_text(key, then_parts);
+                if !else_parts.is_empty() {
+                    key.push_str("{else}");
+                    push_key_text(key, else_parts);
+                }
+                key.push_str("{endif}");
+            }
+        }
+    }
+}
+
+/// Byte offset of the start of each line. Like `offset_to_position`, a lone
+/// `\r` ends a line as well as `\n` and `\r\n`.
+fn line_starts(source: &str) -> Vec<usize> {
+    let bytes = source.as_bytes();
+    let mut starts = vec![0];
+    for (index, &byte) in bytes.iter().enumerate() {
+        let ends_line = byte == b'\n' || (byte == b'\r' && bytes.get(index + 1) != Some(&b'\n'));
+        if ends_line {
+            starts.push(index + 1);
+        }
+    }
+    starts
+}
+
+/// Convert an AST literal into the runtime value it denotes.
+pub(crate) fn literal_value(literal: &Literal) -> Value {
+    match literal {
+        Literal::String(s) => Value::String(s.clone()),
+        Literal::Number(n) => Value::Number(*n),
+        Literal::Bool(b) => Value::Bool(*b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_choice_set_emits_nothing() {
+        let ast = Script {
+            statements: vec![Stmt::ChoiceSet {
+                choices: Vec::new(),
+                shuffle: false,
+            }],
+            ..Default::default()
         };
-        let index = self.chunk.add_constant(value);
-        self.chunk.emit(Instruction::Constant { index }, line);
+        let symbols = SymbolTable::default();
+
+        let chunk = Compiler::new(&ast, &symbols, "").compile().unwrap();
+
+        assert!(matches!(chunk.code.as_slice(), [Instruction::Return]));
     }
 }
@@ -16,10 +16,12 @@
 
 mod convert;
 mod fuzzy;
+mod position;
 mod render;
 mod types;
 
 pub use convert::{DiagnosticContext, IntoDiagnostic};
 pub use fuzzy::{JaroWinklerMatcher, Matcher};
+pub use position::{Position, offset_to_position, offset_to_position_with_tabs};
 pub use render::{AriadneRenderer, Renderer};
 pub use types::{Diagnostic, Label, LabelStyle, Severity, Suggestion};
@@ -0,0 +1,100 @@
+//! Byte offset to line/column conversion for reporting locations.
+
+/// A 1-based line and column in a source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Convert a byte offset in `source` to a line and column.
+///
+/// Columns count characters, so each tab is one column. Offsets past the end
+/// of `source` map to the end; offsets inside a character map to its start.
+pub fn offset_to_position(source: &str, offset: usize) -> Position {
+    offset_to_position_with_tabs(source, offset, 1)
+}
+
+/// Like [`offset_to_position`], but a tab advances the column to the next
+/// multiple of `tab_width`, matching where an editor draws the caret.
+///
+/// A `tab_width` of 1 (or 0) counts a tab as one column.
+pub fn offset_to_position_with_tabs(source: &str, offset: usize, tab_width: usize) -> Position {
+    let tab_width = tab_width.max(1);
+    let mut line = 1;
+    let mut column = 0;
+    let mut chars = source.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if index >= offset {
+            break;
+        }
+        match c {
+            // `\r\n` ends a line at its `\n`; a lone `\r` ends one by itself
+            '\r' if chars.peek().is_some_and(|&(_, next)| next == '\n') => column += 1,
+            '\n' | '\r' => {
+                line += 1;
+                column = 0;
+            }
+            '\t' => column = (column / tab_width + 1) * tab_width,
+            _ => column += 1,
+        }
+    }
+    Position {
+        line,
+        column: column + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_lines_and_characters() {
+        let source = "Hello\nCafé {name}";
+        let offset = source.find('{').unwrap();
+        assert_eq!(
+            offset_to_position(source, offset),
+            Position { line: 2, column: 6 }
+        );
+        assert_eq!(
+            offset_to_position(source, 0),
+            Position { line: 1, column: 1 }
+        );
+    }
+
+    #[test]
+    fn counts_every_line_ending_once() {
+        for source in ["a\nb\nc", "a\r\nb\r\nc", "a\rb\rc"] {
+            let offset = source.find('c').unwrap();
+            assert_eq!(
+                offset_to_position(source, offset),
+                Position { line: 3, column: 1 },
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn tab_inside_string_literal() {
+        // The tab starts at column 15; with a width of 4 it ends at column 16
+        let source = "save title = \"\tSir\" x";
+        let offset = source.find('x').unwrap();
+
+        assert_eq!(
+            offset_to_position(source, offset),
+            Position {
+                line: 1,
+                column: 21
+            }
+        );
+        assert_eq!(
+            offset_to_position_with_tabs(source, offset, 4),
+            Position {
+                line: 1,
+                column: 22
+            }
+        );
+    }
+}
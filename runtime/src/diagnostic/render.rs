@@ -38,17 +38,32 @@ pub trait Renderer {
 pub struct AriadneRenderer {
     /// Whether to use colors in output.
     pub colors: bool,
+    /// Expand tabs to this width in snippets and reported columns, so columns
+    /// match the editor's caret. `None` counts a tab as one column.
+    pub tab_width: Option<usize>,
 }
 
 impl AriadneRenderer {
     /// Create a new renderer with colors enabled.
     pub fn new() -> Self {
-        Self { colors: true }
+        Self {
+            colors: true,
+            tab_width: None,
+        }
     }
 
     /// Create a new renderer without colors.
     pub fn without_colors() -> Self {
-        Self { colors: false }
+        Self {
+            colors: false,
+            tab_width: None,
+        }
+    }
+
+    /// Report columns with tabs expanded to `width`.
+    pub fn with_tab_width(mut self, width: usize) -> Self {
+        self.tab_width = Some(width);
+        self
     }
 }
 
@@ -61,15 +76,27 @@ impl Renderer for AriadneRenderer {
             Severity::Help => ReportKind::Advice,
         };
 
+        // Ariadne counts a tab as one column in its location header, so hand it
+        // the source as the editor draws it and move the spans to match
+        let (source, offsets) = match self.tab_width {
+            Some(width) => expand_tabs(source, width),
+            None => (source.to_string(), (0..=source.len()).collect()),
+        };
+        let at = |offset: usize| offsets[offset.min(offsets.len() - 1)];
+
         // Start building the report with the first label's span as the primary location
-        let offset = diagnostic.labels.first().map(|l| l.span.start).unwrap_or(0);
+        let offset = diagnostic
+            .labels
+            .first()
+            .map(|l| at(l.span.start))
+            .unwrap_or(0);
+
+        let config = Config::default()
+            .with_color(self.colors)
+            .with_index_type(IndexType::Byte);
 
         let mut builder = Report::<(&str, std::ops::Range<usize>)>::build(kind, source_id, offset)
-            .with_config(
-                Config::default()
-                    .with_color(self.colors)
-                    .with_index_type(IndexType::Byte),
-            )
+            .with_config(config)
             .with_message(&diagnostic.message);
 
         // Add labels
@@ -79,9 +106,10 @@ impl Renderer for AriadneRenderer {
                 LabelStyle::Secondary => Color::Blue,
             };
 
-            let ariadne_label = AriadneLabel::new((source_id, label.span.start..label.span.end))
-                .with_message(&label.message)
-                .with_color(color);
+            let ariadne_label =
+                AriadneLabel::new((source_id, at(label.span.start)..at(label.span.end)))
+                    .with_message(&label.message)
+                    .with_color(color);
 
             builder = builder.with_label(ariadne_label);
         }
@@ -108,6 +136,37 @@ impl Renderer for AriadneRenderer {
     }
 }
 
+/// Replace each tab in `source` with spaces up to the next multiple of `width`.
+///
+/// Also returns, for every byte offset in `source` (end included), the offset
+/// of the same position in the expanded text.
+fn expand_tabs(source: &str, width: usize) -> (String, Vec<usize>) {
+    let width = width.max(1);
+    let mut expanded = String::with_capacity(source.len());
+    let mut offsets = Vec::with_capacity(source.len() + 1);
+    let mut column = 0;
+    for c in source.chars() {
+        offsets.extend(std::iter::repeat_n(expanded.len(), c.len_utf8()));
+        match c {
+            '\t' => {
+                let next = (column / width + 1) * width;
+                expanded.extend(std::iter::repeat_n(' ', next - column));
+                column = next;
+            }
+            '\n' | '\r' => {
+                expanded.push(c);
+                column = 0;
+            }
+            _ => {
+                expanded.push(c);
+                column += 1;
+            }
+        }
+    }
+    offsets.push(expanded.len());
+    (expanded, offsets)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -185,4 +244,28 @@ mod tests {
         assert!(output.contains("here"));
         assert!(output.contains("error at 'here'"));
     }
+
+    #[test]
+    fn render_tab_aware_column() {
+        let source = "save title = \"\tSir\" x";
+        let start = source.find('x').unwrap();
+        let diagnostic = Diagnostic::error(
+            "unexpected token",
+            Span {
+                start,
+                end: start + 1,
+            },
+            "here",
+        );
+
+        let output = AriadneRenderer::without_colors().render(&diagnostic, "test.bobbin", source);
+        assert!(output.contains("test.bobbin:1:21"), "{}", output);
+
+        let output = AriadneRenderer::without_colors().with_tab_width(4).render(
+            &diagnostic,
+            "test.bobbin",
+            source,
+        );
+        assert!(output.contains("test.bobbin:1:22"), "{}", output);
+    }
 }
@@ -0,0 +1,252 @@
+//! Renders a syntax tree back to Bobbin source.
+
+use crate::ast::{Choice, Expr, Literal, Script, Stmt, TextPart, VarBindingData};
+
+/// Words that change how a line scans when they start it, followed by a space.
+const LINE_START_WORDS: &[&str] = &[
+    "temp",
+    "save",
+    "const",
+    "set",
+    "extern",
+    "once",
+    "debug_print",
+    "assert",
+    "namespace",
+    "-",
+    "*",
+    "+",
+];
+
+/// Keywords that are a directive when alone on a line.
+const DIRECTIVES: &[&str] = &["shuffle", "end"];
+
+/// Spaces per nesting level.
+const INDENT: &str = "    ";
+
+/// Render a syntax tree as Bobbin source.
+///
+/// The output uses one statement per line, four spaces per nesting level,
+/// and escapes wherever text would otherwise scan differently, so parsing it
+/// gives back the same tree. Leading comments are kept; blank lines and the
+/// original spacing are not. Lines starting with `*` or `+` are escaped even
+/// though only `-` is a marker by default, so the output reads the same under
+/// any [`crate::ScannerConfig`].
+pub fn emit(script: &Script) -> String {
+    let mut out = String::new();
+    emit_block(script, &script.statements, 0, &mut out);
+    out
+}
+
+fn emit_block(script: &Script, statements: &[Stmt], depth: usize, out: &mut String) {
+    for stmt in statements {
+        if let Stmt::ChoiceSet { choices, shuffle } = stmt {
+            for (index, choice) in choices.iter().enumerate() {
+                // Comments above `shuffle` belong to the first choice
+                emit_comments(script.choice_comments(choice), depth, out);
+                if index == 0 && *shuffle {
+                    push_line(out, depth, "shuffle");
+                }
+                push_line(out, depth, &choice_line(choice));
+                emit_block(script, &choice.nested, depth + 1, out);
+            }
+            continue;
+        }
+
+        emit_comments(script.leading_comments(stmt), depth, out);
+        let line = match stmt {
+            Stmt::Line {
+                parts,
+                once,
+                effect,
+                debug_note,
+                ..
+            } => {
+                let text = render_text(parts);
+                let mut line = if parts.is_empty() && !*once {
+                    // Only the blank marker parses to a line without text
+                    "~".to_string()
+                } else if *once {
+                    format!("once {}", text)
+                } else if needs_line_escape(&text) {
+                    format!("\\{}", text)
+                } else {
+                    text
+                };
+                if let Some(effect) = effect {
+                    line.push_str(&format!(" ~ {}", binding("set", effect)));
+                }
+                if let Some(note) = debug_note {
+                    line.push_str(&format!(" //! {}", note));
+                }
+                line
+            }
+            Stmt::TempDecl(data) => binding("temp", data),
+            Stmt::SaveDecl(data) => binding("save", data),
+            Stmt::ConstDecl(data) => binding("const", data),
+            Stmt::Assignment(data) => binding("set", data),
+            Stmt::ExternDecl(data) => {
+                let mut line = format!("extern {}", data.name);
+                if let Some(ty) = data.ty {
+                    line.push_str(&format!(": {}", ty.name()));
+                }
+                if let Some(default) = &data.default {
+                    line.push_str(&format!(" = {}", render_literal(default)));
+                }
+                line
+            }
+            Stmt::DebugPrint { message, .. } => format!("debug_print {}", quote(message)),
+            Stmt::Assert { condition, .. } => format!("assert {}", render_expr(condition)),
+            Stmt::Namespace { name, .. } => format!("namespace {}", name),
+            Stmt::End { .. } => "end".to_string(),
+            Stmt::ChoiceSet { .. } => unreachable!("handled above"),
+        };
+        push_line(out, depth, &line);
+    }
+}
+
+fn emit_comments(comments: &[String], depth: usize, out: &mut String) {
+    for comment in comments {
+        push_line(out, depth, format!("// {}", comment).trim_end());
+    }
+}
+
+fn push_line(out: &mut String, depth: usize, line: &str) {
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+    out.push_str(line);
+    out.push('\n');
+}
+
+fn choice_line(choice: &Choice) -> String {
+    let mut line = format!("{} ", choice.marker.symbol());
+    if let Some(label) = &choice.label {
+        line.push_str(&format!("({}) ", label));
+    }
+    // A `#` after a space would start the tags
+    let text = render_text(&choice.parts).replace(" #", " \\#");
+    // A leading parenthesis would read as a label, a leading `#` as a tag,
+    // and leading whitespace would be skipped
+    if text.starts_with(['(', '#', ' ', '\t']) {
+        line.push('\\');
+    }
+    line.push_str(&text);
+    for tag in &choice.tags {
+        line.push_str(&format!(" #{}", tag));
+    }
+    line
+}
+
+/// Whether a line's text would scan as something other than dialogue.
+fn needs_line_escape(text: &str) -> bool {
+    let starts_with_word = LINE_START_WORDS.iter().any(|word| {
+        text.strip_prefix(word)
+            .is_some_and(|rest| rest.starts_with(' '))
+    });
+    starts_with_word
+        || DIRECTIVES.contains(&text.trim_end())
+        || text.starts_with("//")
+        // Leading whitespace would be skipped
+        || text.starts_with([' ', '\t'])
+        // `\~` is a literal tilde, not an escaped line start
+        || (text.starts_with('\\') && !text.starts_with("\\~"))
+}
+
+fn binding(keyword: &str, data: &VarBindingData) -> String {
+    format!(
+        "{} {} {}= {}",
+        keyword,
+        data.name,
+        data.op.map_or("", |op| op.symbol()),
+        render_literal(&data.value)
+    )
+}
+
+pub(crate) fn render_text(parts: &[TextPart]) -> String {
+    let mut text = String::new();
+    for part in parts {
+        match part {
+            TextPart::Literal { text: literal, .. } => {
+                for c in literal.chars() {
+                    match c {
+                        '{' => text.push_str("{{"),
+                        '}' => text.push_str("}}"),
+                        '~' => text.push_str("\\~"),
+                        _ => text.push(c),
+                    }
+                }
+            }
+            TextPart::VarRef {
+                name, precision, ..
+            } => match precision {
+                Some(precision) => text.push_str(&format!("{{{}:.{}}}", name, precision)),
+                None => text.push_str(&format!("{{{}}}", name)),
+            },
+            TextPart::Conditional {
+                name,
+                then_parts,
+                else_parts,
+                ..
+            } => {
+                text.push_str(&format!("{{if {}}}", name));
+                text.push_str(&render_text(then_parts));
+                if !else_parts.is_empty() {
+                    text.push_str("{else}");
+                    text.push_str(&render_text(else_parts));
+                }
+                text.push_str("{endif}");
+            }
+        }
+    }
+    text
+}
+
+fn render_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Literal { value, .. } => render_literal(value),
+        Expr::Var { name, .. } => name.clone(),
+        Expr::Compare {
+            op, left, right, ..
+        } => format!(
+            "{} {} {}",
+            render_operand(left),
+            op.symbol(),
+            render_operand(right)
+        ),
+    }
+}
+
+/// An operand of a comparison, in parentheses if it is a comparison itself.
+fn render_operand(expr: &Expr) -> String {
+    match expr {
+        Expr::Compare { .. } => format!("({})", render_expr(expr)),
+        _ => render_expr(expr),
+    }
+}
+
+pub(crate) fn render_literal(literal: &Literal) -> String {
+    match literal {
+        Literal::String(s) => quote(s),
+        Literal::Number(n) => n.to_string(),
+        Literal::Bool(b) => b.to_string(),
+    }
+}
+
+/// A string literal, with the escapes `unescape_string` in the parser reverses.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
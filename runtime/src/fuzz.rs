@@ -0,0 +1,101 @@
+//! Entry points for fuzzing: arbitrary bytes in, no panics out.
+//!
+//! Each function takes raw fuzzer input, reads it as (lossy) UTF-8 and runs
+//! it through one stage of the pipeline. Errors are expected and ignored;
+//! only a panic is a finding. The targets in `fuzz/` call these, and the
+//! functions stay public so other harnesses can reuse them. Not part of the
+//! stable API.
+
+use std::sync::Arc;
+
+use crate::storage::ScratchStorage;
+use crate::{HostState, Runtime, Value, VariableStorage, emit, parse, scan, scanner};
+
+/// Steps taken per input before giving up, so looping scripts stay bounded.
+const MAX_STEPS: usize = 256;
+
+/// Scan the input and collect its comments.
+pub fn fuzz_scan(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    let (tokens, _errors) = scan(&source);
+    for token in tokens {
+        // Spans must stay on character boundaries
+        let _ = &source[token.span.start..token.span.end];
+    }
+    let _ = scanner::comments(&source);
+}
+
+/// Parse the input and, when it parses, emit it back to source and parse
+/// that again.
+pub fn fuzz_parse(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    match parse(&source) {
+        Ok(script) => {
+            let emitted = emit(&script);
+            assert!(
+                parse(&emitted).is_ok(),
+                "emitted source doesn't parse:\n{}",
+                emitted
+            );
+        }
+        Err(error) => {
+            let _ = error.render("fuzz", &source);
+        }
+    }
+}
+
+/// Create a runtime for the input and play it, always picking the first
+/// choice. Errors are rendered against the source, which exercises every
+/// span they carry.
+pub fn fuzz_runtime(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    let storage: Arc<dyn VariableStorage> = Arc::new(ScratchStorage::new(Arc::new(NoStorage)));
+    let host: Arc<dyn HostState> = Arc::new(NoHost);
+    let mut runtime = match Runtime::new(&source, storage, host) {
+        Ok(runtime) => runtime,
+        Err(error) => {
+            let _ = error.render("fuzz", &source);
+            return;
+        }
+    };
+    for _ in 0..MAX_STEPS {
+        if !runtime.has_more() {
+            break;
+        }
+        let result = if runtime.is_waiting_for_choice() {
+            runtime.select_choice(0)
+        } else {
+            runtime.advance()
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+}
+
+/// Storage with nothing in it, under a [`ScratchStorage`] that keeps writes.
+struct NoStorage;
+
+impl VariableStorage for NoStorage {
+    fn get(&self, _name: &str) -> Option<Value> {
+        None
+    }
+
+    fn set(&self, _name: &str, _value: Value) {}
+
+    fn initialize_if_absent(&self, _name: &str, _default: Value) {}
+
+    fn contains(&self, _name: &str) -> bool {
+        false
+    }
+}
+
+/// A host without variables; scripts reading an `extern` without a default
+/// stop with an error.
+struct NoHost;
+
+impl HostState for NoHost {
+    fn lookup(&self, _name: &str) -> Option<Value> {
+        None
+    }
+}
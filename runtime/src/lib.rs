@@ -1,26 +1,40 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use crate::ast::Script;
 use crate::compiler::{CompileError, Compiler};
 use crate::diagnostic::{DiagnosticContext, IntoDiagnostic, JaroWinklerMatcher};
 use crate::parser::{ParseError, Parser};
-use crate::resolver::{Resolver, SemanticError};
+use crate::resolver::{Resolver, SemanticError, SemanticWarning};
 use crate::scanner::Scanner;
+use crate::token::Token;
 use crate::vm::{StepResult, VM};
 
-pub use crate::chunk::Value;
-pub use crate::storage::{HostState, VariableStorage};
-pub use crate::vm::RuntimeError;
+pub use crate::chunk::{Chunk, ChunkStats, Instruction, Value, ValueKey};
+pub use crate::emitter::emit;
+pub use crate::resolver::{DEFAULT_MAX_CHOICES, Lint, LintConfig};
+pub use crate::scanner::{LexicalError, ScannerConfig};
+pub use crate::storage::{HostState, MemoryStorage, VariableStorage};
+pub use crate::vm::{MissingSavePolicy, RuntimeError};
 
-mod ast;
+pub mod ast;
 mod chunk;
 mod compiler;
 pub mod diagnostic;
+mod emitter;
+#[doc(hidden)]
+pub mod fuzz;
 mod parser;
 mod resolver;
+mod rng;
 mod scanner;
 mod storage;
+pub mod text;
 pub mod token;
+pub mod values;
+pub mod visit;
 mod vm;
 
 pub use diagnostic::{AriadneRenderer, Diagnostic, Renderer};
@@ -35,6 +49,11 @@ pub enum BobbinError {
     },
     Compile(CompileError),
     Runtime(RuntimeError),
+    /// An error in the prelude. Spans refer to the prelude's source, not the script's.
+    Prelude {
+        prelude: Prelude,
+        error: Box<BobbinError>,
+    },
 }
 
 impl From<Vec<ParseError>> for BobbinError {
@@ -43,6 +62,13 @@ impl From<Vec<ParseError>> for BobbinError {
     }
 }
 
+/// Lexical errors from [`scan`], so they can be rendered like any other error.
+impl From<Vec<LexicalError>> for BobbinError {
+    fn from(errors: Vec<LexicalError>) -> Self {
+        BobbinError::Parse(errors.into_iter().map(ParseError::from).collect())
+    }
+}
+
 impl From<(Vec<SemanticError>, Vec<String>)> for BobbinError {
     fn from((errors, known_variables): (Vec<SemanticError>, Vec<String>)) -> Self {
         BobbinError::Semantic {
@@ -79,6 +105,9 @@ impl fmt::Display for BobbinError {
             BobbinError::Runtime(err) => {
                 write!(f, "runtime error: {}", err)
             }
+            BobbinError::Prelude { prelude, error } => {
+                write!(f, "in prelude '{}': {}", prelude.source_id, error)
+            }
         }
     }
 }
@@ -115,6 +144,7 @@ impl BobbinError {
                 let ctx = DiagnosticContext::new(&[], &matcher);
                 vec![err.into_diagnostic(&ctx)]
             }
+            BobbinError::Prelude { error, .. } => error.into_diagnostics(),
         }
     }
 
@@ -152,6 +182,7 @@ impl BobbinError {
                 let ctx = DiagnosticContext::new(&[], &matcher);
                 vec![err.clone().into_diagnostic(&ctx)]
             }
+            BobbinError::Prelude { error, .. } => error.to_diagnostics(),
         }
     }
 
@@ -159,6 +190,11 @@ impl BobbinError {
     ///
     /// This is a convenience method that converts to diagnostics and renders them.
     pub fn render(&self, source_id: &str, source: &str) -> String {
+        // Prelude errors point into the prelude, which carries its own source
+        if let BobbinError::Prelude { prelude, error } = self {
+            return error.render(&prelude.source_id, &prelude.source);
+        }
+
         let diagnostics = self.to_diagnostics();
         let renderer = AriadneRenderer::new();
         // AriadneRenderer normalizes line endings internally
@@ -166,13 +202,543 @@ impl BobbinError {
     }
 }
 
+/// Scan a script into tokens, collecting every token and lexical error.
+///
+/// Tools that need both tokens (e.g. for highlighting) and the syntax tree can
+/// scan once and hand the tokens to [`parse_tokens`]. The final `Eof` token is
+/// not included. Spans are byte offsets into `script`; text the scanner skips
+/// (spaces between tokens, comment lines, a leading `\`) falls between them.
+pub fn scan(script: &str) -> (Vec<Token<'_>>, Vec<LexicalError>) {
+    scan_with_config(script, &ScannerConfig::default())
+}
+
+/// Like [`scan`], with non-default source conventions such as extra choice markers.
+pub fn scan_with_config<'a>(
+    script: &'a str,
+    config: &ScannerConfig,
+) -> (Vec<Token<'a>>, Vec<LexicalError>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for result in Scanner::with_config(script, config.clone()).tokens() {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(error) => errors.push(error),
+        }
+    }
+    (tokens, errors)
+}
+
+/// Parse tokens produced by [`scan`] for `script`.
+///
+/// Only pass tokens from a scan that reported no errors: the parser can't see
+/// where the lexical errors were, so it would report confusing follow-on errors.
+pub fn parse_tokens(script: &str, tokens: &[Token]) -> Result<Script, BobbinError> {
+    let mut ast = Parser::new(tokens.iter().copied().map(Ok)).parse()?;
+    ast.attach_comments(scanner::comments(script));
+    Ok(ast)
+}
+
+/// Parse a script into its syntax tree without resolving or compiling it.
+///
+/// Full-line `//` comments are attached to the statement that follows them;
+/// see [`Script::leading_comments`].
+pub fn parse(script: &str) -> Result<Script, BobbinError> {
+    parse_with_config(script, &ScannerConfig::default())
+}
+
+/// Like [`parse`], with non-default source conventions such as extra choice markers.
+pub fn parse_with_config(script: &str, config: &ScannerConfig) -> Result<Script, BobbinError> {
+    let tokens = Scanner::with_config(script, config.clone()).tokens();
+    let mut ast = Parser::new(tokens).parse()?;
+    ast.attach_comments(scanner::comments(script));
+    Ok(ast)
+}
+
+/// Parse a script and serialize its syntax tree as pretty-printed JSON.
+///
+/// Node ids are kept, so tools can cross-reference them; spans are byte
+/// offsets into `script`, like diagnostic spans.
+#[cfg(feature = "serde")]
+pub fn parse_to_json(script: &str) -> Result<String, BobbinError> {
+    let ast = parse(script)?;
+    Ok(serde_json::to_string_pretty(&ast).expect("syntax tree always serializes"))
+}
+
+/// Compile a script to bytecode without running it.
+///
+/// Useful for tooling that inspects a script statically, e.g. via
+/// [`Chunk::choice_points`].
+pub fn compile(script: &str) -> Result<Chunk, BobbinError> {
+    compile_with_lints(
+        script,
+        LintConfig::none(),
+        None,
+        &ScannerConfig::default(),
+        &BTreeMap::new(),
+        &BTreeMap::new(),
+        None,
+    )
+    .map(|(chunk, _)| chunk)
+}
+
+/// Compile a script to bytecode as a runtime built with `options` would,
+/// e.g. to check it against [`RuntimeOptions::max_choices`] or compile it
+/// with its prelude. Warnings are not reported.
+pub fn compile_with_options(script: &str, options: &RuntimeOptions) -> Result<Chunk, BobbinError> {
+    compile_with_lints(
+        script,
+        LintConfig::none(),
+        options.prelude.as_ref(),
+        &options.scanner,
+        &options.constants,
+        &options.initial_temps,
+        options.max_choices,
+    )
+    .map(|(chunk, _)| chunk)
+}
+
+/// A stable 64-bit hash of a script's source, for invalidating caches of
+/// compiled chunks or checking that saved state belongs to this script.
+///
+/// The source is normalized first: a leading byte order mark is dropped and
+/// `\r\n` and lone `\r` line endings count as `\n`, so checking a file out
+/// on another platform doesn't change the hash. Anything else, comments and
+/// spacing included, does. The hash is 64-bit FNV-1a and will stay the same
+/// across versions of this crate; it is not meant to resist deliberate
+/// collisions.
+pub fn source_hash(source: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let bytes = source.strip_prefix('\u{feff}').unwrap_or(source).as_bytes();
+    let mut hash = OFFSET_BASIS;
+    let mut index = 0;
+    while index < bytes.len() {
+        let mut byte = bytes[index];
+        if byte == b'\r' {
+            byte = b'\n';
+            if bytes.get(index + 1) == Some(&b'\n') {
+                index += 1;
+            }
+        }
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(PRIME);
+        index += 1;
+    }
+    hash
+}
+
+fn compile_with_lints(
+    script: &str,
+    lints: LintConfig,
+    prelude: Option<&Prelude>,
+    scanner: &ScannerConfig,
+    constants: &BTreeMap<String, Value>,
+    initial_temps: &BTreeMap<String, Value>,
+    max_choices: Option<usize>,
+) -> Result<(Chunk, Vec<SemanticWarning>), BobbinError> {
+    let prelude_ast = prelude.map(parse_prelude).transpose()?;
+
+    let tokens = Scanner::with_config(script, scanner.clone()).tokens();
+    let ast = Parser::new(tokens).parse()?;
+
+    let mut resolver = Resolver::new(&ast)
+        .with_lints(lints)
+        .with_constants(constants)
+        .with_initial_temps(initial_temps);
+    if let Some(limit) = max_choices {
+        resolver = resolver.with_max_choices(limit);
+    }
+    if let Some(prelude_ast) = &prelude_ast {
+        resolver = resolver.with_prelude(prelude_ast);
+    }
+    let mut symbols = resolver.analyze()?;
+
+    let mut compiler = Compiler::new(&ast, &symbols, script);
+    if let Some(prelude_ast) = &prelude_ast {
+        compiler = compiler.with_prelude(prelude_ast);
+    }
+    let chunk = compiler.compile()?;
+    Ok((chunk, std::mem::take(&mut symbols.warnings)))
+}
+
+/// Compile `script` as [`Runtime::with_options`] would, rendering its warnings.
+fn compile_for_runtime(
+    script: &str,
+    options: &RuntimeOptions,
+) -> Result<(Chunk, Vec<Diagnostic>), BobbinError> {
+    let (chunk, warnings) = compile_with_lints(
+        script,
+        options.lints.clone(),
+        options.prelude.as_ref(),
+        &options.scanner,
+        &options.constants,
+        &options.initial_temps,
+        options.max_choices,
+    )?;
+    let matcher = JaroWinklerMatcher::default();
+    let ctx = DiagnosticContext::new(&[], &matcher);
+    let warnings = warnings
+        .into_iter()
+        .map(|w| w.into_diagnostic(&ctx))
+        .collect();
+    Ok((chunk, warnings))
+}
+
+/// Apply the options that don't depend on where the VM is in the script.
+fn configure_vm(vm: &mut VM, options: RuntimeOptions) {
+    vm.set_missing_save_policy(options.missing_save_policy);
+    vm.set_host_snapshot(options.host_snapshot);
+    vm.set_track_seen_lines(options.track_seen_lines);
+    if let Some(flag) = options.cancel_flag {
+        vm.set_cancel_flag(flag);
+    }
+}
+
+/// Parse and check a prelude on its own, so its errors render against its own source.
+fn parse_prelude(prelude: &Prelude) -> Result<Script, BobbinError> {
+    let check = || -> Result<Script, BobbinError> {
+        let tokens = Scanner::new(&prelude.source).tokens();
+        let ast = Parser::new(tokens).parse()?;
+        Resolver::new(&ast).analyze_prelude()?;
+        Ok(ast)
+    };
+    check().map_err(|error| BobbinError::Prelude {
+        prelude: prelude.clone(),
+        error: Box::new(error),
+    })
+}
+
+/// Shared declarations loaded before a script (conventionally `globals.bobbin`).
+///
+/// The prelude may only contain `save` and `extern` declarations. They are
+/// visible in the script without redeclaring, and its `save` defaults are
+/// initialized before the script runs. The host loads the file however suits
+/// it (filesystem, Godot's `FileAccess`, embedded string) and passes the text.
+#[derive(Debug, Clone)]
+pub struct Prelude {
+    /// Name used when rendering errors, e.g. the file path.
+    pub source_id: String,
+    pub source: String,
+}
+
+impl Prelude {
+    pub fn new(source_id: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            source_id: source_id.into(),
+            source: source.into(),
+        }
+    }
+}
+
+/// Options controlling runtime behavior. `Default` matches `Runtime::new`.
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeOptions {
+    /// How to handle a declared save variable that is missing from storage.
+    pub missing_save_policy: MissingSavePolicy,
+    /// Seed for runtime randomness (e.g. `shuffle`d choices).
+    ///
+    /// The runtime never reads the clock or OS entropy, so the same seed always
+    /// produces the same session. Hosts wanting variety should pass their own seed.
+    pub seed: u64,
+    /// Which lints to run while compiling. Their findings are available from
+    /// [`Runtime::warnings`].
+    pub lints: LintConfig,
+    /// Share a cancellation flag with the host. If `None`, the runtime creates
+    /// its own; either way it is available from [`Runtime::cancel_flag`].
+    pub cancel_flag: Option<Arc<AtomicBool>>,
+    /// Shared declarations to load before the script.
+    pub prelude: Option<Prelude>,
+    /// Source conventions, such as which choice markers are recognized.
+    pub scanner: ScannerConfig,
+    /// Read all host variables at the start of each step instead of one at a time.
+    ///
+    /// Everything shown by one step (a line, or a set of choices) then sees a
+    /// single, consistent view of host state, even if the host changes it while
+    /// the step runs. Every `extern` the script reads is fetched through one
+    /// [`HostState::lookup_many`] call per step.
+    pub host_snapshot: bool,
+    /// Read-only values fixed for the whole conversation, such as difficulty
+    /// or language.
+    ///
+    /// Scripts read them by name like `extern` variables, without declaring
+    /// them, but they never go through [`HostState`] and can't change once
+    /// the runtime is created. Assigning to one, or declaring a variable with
+    /// the same name, is a compile error.
+    pub constants: BTreeMap<String, Value>,
+    /// Values that replace the declared value of top-level `temp` variables,
+    /// so a test can start a conversation in a particular state.
+    ///
+    /// `temp mood = "calm"` starts as the value given here for `mood` instead.
+    /// Only top-level temps are affected; a name that matches none is a
+    /// compile error, so a renamed temp doesn't silently lose its override.
+    pub initial_temps: BTreeMap<String, Value>,
+    /// Most choices a single choice set may have; a set with more is a
+    /// compile error. `None`, the default, means no limit;
+    /// [`DEFAULT_MAX_CHOICES`] is a reasonable one to opt into.
+    ///
+    /// Guards UIs against runaway sets, e.g. from a buggy script generator.
+    /// The Godot binding has no way to set it, so its scripts are unlimited.
+    pub max_choices: Option<usize>,
+    /// Count every line shown in storage, so replays can tell which lines the
+    /// player has already read ([`Runtime::current_line_seen`]) and skip them
+    /// ([`Runtime::set_skip_seen`]).
+    ///
+    /// Each line gets a `read:` key combining its source offset with its
+    /// text, so editing a line, or anything above it, makes it unread again.
+    pub track_seen_lines: bool,
+}
+
+/// Fluent construction of a [`Runtime`] with non-default options.
+///
+/// ```ignore
+/// let runtime = Runtime::builder(storage, host)
+///     .seed(42)
+///     .missing_save_policy(MissingSavePolicy::UseDeclaredDefault)
+///     .build(script)?;
+/// ```
+pub struct RuntimeBuilder {
+    storage: Arc<dyn VariableStorage>,
+    host: Arc<dyn HostState>,
+    options: RuntimeOptions,
+}
+
+impl RuntimeBuilder {
+    pub fn new(storage: Arc<dyn VariableStorage>, host: Arc<dyn HostState>) -> Self {
+        Self {
+            storage,
+            host,
+            options: RuntimeOptions::default(),
+        }
+    }
+
+    /// Replace all options at once, e.g. with a preset shared between runtimes.
+    pub fn options(mut self, options: RuntimeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// See [`RuntimeOptions::missing_save_policy`].
+    pub fn missing_save_policy(mut self, policy: MissingSavePolicy) -> Self {
+        self.options.missing_save_policy = policy;
+        self
+    }
+
+    /// See [`RuntimeOptions::seed`].
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.options.seed = seed;
+        self
+    }
+
+    /// See [`RuntimeOptions::lints`].
+    pub fn lints(mut self, lints: LintConfig) -> Self {
+        self.options.lints = lints;
+        self
+    }
+
+    /// See [`RuntimeOptions::cancel_flag`].
+    pub fn cancel_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.options.cancel_flag = Some(flag);
+        self
+    }
+
+    /// See [`RuntimeOptions::prelude`].
+    pub fn prelude(mut self, prelude: Prelude) -> Self {
+        self.options.prelude = Some(prelude);
+        self
+    }
+
+    /// See [`RuntimeOptions::scanner`].
+    pub fn scanner(mut self, config: ScannerConfig) -> Self {
+        self.options.scanner = config;
+        self
+    }
+
+    /// See [`RuntimeOptions::host_snapshot`].
+    pub fn host_snapshot(mut self, enabled: bool) -> Self {
+        self.options.host_snapshot = enabled;
+        self
+    }
+
+    /// See [`RuntimeOptions::track_seen_lines`].
+    pub fn track_seen_lines(mut self, enabled: bool) -> Self {
+        self.options.track_seen_lines = enabled;
+        self
+    }
+
+    /// Add a read-only constant; see [`RuntimeOptions::constants`].
+    pub fn constant(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.options.constants.insert(name.into(), value);
+        self
+    }
+
+    /// Start a top-level temp with `value`; see [`RuntimeOptions::initial_temps`].
+    pub fn initial_temp(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.options.initial_temps.insert(name.into(), value);
+        self
+    }
+
+    /// See [`RuntimeOptions::max_choices`].
+    pub fn max_choices(mut self, limit: usize) -> Self {
+        self.options.max_choices = Some(limit);
+        self
+    }
+
+    /// Compile `script` and start the runtime at its first line.
+    pub fn build(self, script: &str) -> Result<Runtime, BobbinError> {
+        Runtime::with_options(script, self.storage, self.host, self.options)
+    }
+}
+
+/// What the runtime is currently presenting, as returned by [`Runtime::state`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuntimeState<'a> {
+    /// A line of dialogue is showing. Call `advance()` to continue.
+    Line(&'a str),
+    /// Choices are showing. Call `select_choice()` to continue.
+    Choices(&'a [String]),
+    /// The dialogue has ended and the final line has been advanced past.
+    Done,
+}
+
+/// Something for the host to present, as returned by [`Runtime::next_event`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A line of dialogue. Call `advance()` to continue.
+    Line {
+        /// The text as `current_line()` returns it.
+        text: String,
+        /// 1-based line in the script's source.
+        line_number: usize,
+        /// How many choice branches enclose the line.
+        depth: usize,
+    },
+    /// A choice set, in displayed order. Call `select_choice()` to continue.
+    Choices { options: Vec<ChoiceOption> },
+    /// The dialogue has ended and the final line has been advanced past.
+    End,
+}
+
+/// One option of an [`Event::Choices`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChoiceOption {
+    /// The choice text as displayed.
+    pub text: String,
+    /// The choice's `(label)`, if the script gave it one.
+    pub label: Option<String>,
+    /// Tags written after the choice, without the `#`.
+    pub tags: Vec<String>,
+    /// Whether the player picked this choice before (see [`Runtime::choice_seen`]).
+    pub seen: bool,
+}
+
+/// A choice the player selected, as recorded by [`Runtime::choice_history`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChoiceRecord {
+    /// The choice text as displayed.
+    pub text: String,
+    /// The choice's `(label)`, if the script gave it one.
+    pub label: Option<String>,
+}
+
+/// Picks a displayed index given the displayed options, for [`ChoiceStrategy::Custom`].
+pub type ChoicePicker<'a> = Box<dyn FnMut(&[String]) -> usize + 'a>;
+
+/// Rewrites each line before it is shown; see [`Runtime::set_line_transform`].
+pub type LineTransform = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Decides whether a choice may be taken, given its index and displayed
+/// text; see [`Runtime::set_choice_validator`].
+pub type ChoiceValidator = Box<dyn Fn(usize, &str) -> bool + Send + Sync>;
+
+/// How [`Runtime::transcript`] picks an option at each choice.
+pub enum ChoiceStrategy<'a> {
+    /// Always take the first displayed option.
+    AlwaysFirst,
+    /// Always take the last displayed option.
+    AlwaysLast,
+    /// Call the closure with the displayed options and take the index it returns.
+    Custom(ChoicePicker<'a>),
+}
+
+impl ChoiceStrategy<'_> {
+    fn pick(&mut self, choices: &[String]) -> usize {
+        match self {
+            ChoiceStrategy::AlwaysFirst => 0,
+            ChoiceStrategy::AlwaysLast => choices.len().saturating_sub(1),
+            ChoiceStrategy::Custom(pick) => pick(choices),
+        }
+    }
+}
+
+/// One step of a conversation, as recorded by [`Runtime::transcript`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranscriptEntry {
+    /// A line of dialogue that was shown.
+    Line(String),
+    /// A choice set that was shown, and the displayed index that was taken.
+    Choice {
+        options: Vec<String>,
+        selected: usize,
+    },
+}
+
+/// What [`Runtime::reload`] did with the current position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReloadOutcome {
+    /// The runtime shows the same line or choices and continues in the new script.
+    Preserved,
+    /// The edit touched what had already run, so the new script started over.
+    Restarted,
+}
+
+/// A running conversation.
+///
+/// # Lifecycle
+///
+/// 1. **Created.** Constructing a runtime compiles the script and runs its
+///    first step, so the first line or choice set is already showing. If that
+///    step fails (say a missing extern variable or a failed `assert`), the
+///    constructor returns the error as [`BobbinError::Runtime`] and no runtime
+///    exists. [`Runtime::is_ready`] is therefore true for every runtime a
+///    constructor returns.
+/// 2. **Running.** [`Runtime::advance`] and [`Runtime::select_choice`] move
+///    from step to step. An error from a step is returned by that call.
+/// 3. **Done.** [`Runtime::has_more`] turns false once the final line is
+///    showing; advancing past it finishes the conversation.
 pub struct Runtime {
     vm: VM,
     storage: Arc<dyn VariableStorage>,
     host: Arc<dyn HostState>,
     current_line: Option<String>,
+    /// The current line as the script produced it, before `line_transform`.
+    untransformed_line: Option<String>,
+    line_transform: Option<LineTransform>,
+    choice_validator: Option<ChoiceValidator>,
+    current_debug_note: Option<String>,
+    current_template: Option<String>,
+    current_depth: usize,
+    current_line_number: Option<usize>,
+    current_line_seen: bool,
+    /// Step past lines shown before; see `set_skip_seen()`.
+    skip_seen: bool,
     current_choices: Option<Vec<String>>,
+    /// Set when the runtime moves to a new line, choice set or the end, and
+    /// cleared when `next_event()` reports it.
+    event_pending: bool,
     is_done: bool,
+    /// Set once the first step has run.
+    ready: bool,
+    warnings: Vec<Diagnostic>,
+    /// Set once `advance()` is called after the final line.
+    finished: bool,
+    revision: u64,
+    /// Counts the times the runtime stopped at a choice set.
+    choice_token: u64,
+    choice_history: Vec<ChoiceRecord>,
+    /// Source of the prelude compiled in front of the script, if any, so
+    /// `reload()` can tell when it changed.
+    prelude_source: Option<String>,
 }
 
 impl Runtime {
@@ -203,51 +769,493 @@ impl Runtime {
         storage: Arc<dyn VariableStorage>,
         host: Arc<dyn HostState>,
     ) -> Result<Self, BobbinError> {
-        let tokens = Scanner::new(script).tokens();
-        let ast = Parser::new(tokens).parse()?;
-        let symbols = Resolver::new(&ast).analyze()?;
-        let chunk = Compiler::new(&ast, &symbols).compile()?;
+        Self::with_options(script, storage, host, RuntimeOptions::default())
+    }
+
+    /// Start building a runtime with non-default options. See [`RuntimeBuilder`].
+    pub fn builder(storage: Arc<dyn VariableStorage>, host: Arc<dyn HostState>) -> RuntimeBuilder {
+        RuntimeBuilder::new(storage, host)
+    }
+
+    /// Create a new runtime with non-default options.
+    ///
+    /// See [`Runtime::new`] for how storage and host state are shared.
+    pub fn with_options(
+        script: &str,
+        storage: Arc<dyn VariableStorage>,
+        host: Arc<dyn HostState>,
+        options: RuntimeOptions,
+    ) -> Result<Self, BobbinError> {
+        let (chunk, warnings) = compile_for_runtime(script, &options)?;
+        let prelude_source = options.prelude.as_ref().map(|p| p.source.clone());
+
+        let mut vm = VM::new(chunk, Arc::clone(&storage), Arc::clone(&host));
+        vm.set_seed(options.seed);
+        configure_vm(&mut vm, options);
 
         let mut runtime = Self {
-            vm: VM::new(chunk, Arc::clone(&storage), Arc::clone(&host)),
+            vm,
             storage,
             host,
             current_line: None,
+            untransformed_line: None,
+            line_transform: None,
+            choice_validator: None,
+            current_debug_note: None,
+            current_template: None,
+            current_depth: 0,
+            current_line_number: None,
+            current_line_seen: false,
+            skip_seen: false,
             current_choices: None,
+            event_pending: false,
             is_done: false,
+            ready: false,
+            warnings,
+            finished: false,
+            revision: 0,
+            choice_token: 0,
+            choice_history: Vec::new(),
+            prelude_source,
         };
         runtime.step_vm()?;
         Ok(runtime)
     }
 
+    /// Swap in an edited version of the script, keeping the player in place
+    /// when possible.
+    ///
+    /// `old_source` must be the source this runtime is running. If nothing up
+    /// to the end of the current line (or of the last option of the pending
+    /// choice set) changed, the runtime keeps showing what it shows now and
+    /// continues in the new script from there; temps, choice history and the
+    /// random sequence carry over, so `options.seed` is not reapplied.
+    /// Otherwise the new script starts over, as if building a fresh runtime.
+    /// A prelude in `options` that differs from the one the runtime was built
+    /// with always starts over, since its declarations run before the script.
+    /// Storage is kept either way.
+    ///
+    /// If the new script doesn't compile, the runtime is left unchanged.
+    pub fn reload(
+        &mut self,
+        old_source: &str,
+        new_source: &str,
+        options: RuntimeOptions,
+    ) -> Result<ReloadOutcome, BobbinError> {
+        let (chunk, warnings) = compile_for_runtime(new_source, &options)?;
+        let prelude_changed =
+            self.prelude_source.as_deref() != options.prelude.as_ref().map(|p| p.source.as_str());
+        let ip = if self.finished || prelude_changed {
+            None
+        } else {
+            let old = self.vm.chunk();
+            old.remap_pause(old_source, &chunk, new_source, self.vm.ip())
+        };
+
+        let Some(ip) = ip else {
+            let storage = Arc::clone(&self.storage);
+            let host = Arc::clone(&self.host);
+            let transform = self.line_transform.take();
+            let validator = self.choice_validator.take();
+            let choice_token = self.choice_token;
+            *self = Self::with_options(new_source, storage, host, options)?;
+            if let Some(transform) = transform {
+                self.set_line_transform(transform);
+            }
+            self.choice_validator = validator;
+            // Tokens handed out before the restart must stay stale
+            self.choice_token += choice_token;
+            return Ok(ReloadOutcome::Restarted);
+        };
+
+        self.vm.replace_chunk(chunk, ip);
+        configure_vm(&mut self.vm, options);
+        self.warnings = warnings;
+        // Content may have been added or removed after the current line
+        if self.current_line.is_some() {
+            self.is_done = self.vm.is_at_end();
+        }
+        Ok(ReloadOutcome::Preserved)
+    }
+
     /// Get a reference to the storage for external access.
     pub fn storage(&self) -> &Arc<dyn VariableStorage> {
         &self.storage
     }
 
+    /// The compiled bytecode being run. Pass the script's source to
+    /// [`Chunk::disassemble`] for a listing when debugging.
+    pub fn chunk(&self) -> &Chunk {
+        self.vm.chunk()
+    }
+
+    /// Give every `save` variable in the script its declared default, unless
+    /// storage already has a value for it.
+    ///
+    /// Call after loading a save made by an older version of the script, so
+    /// variables it didn't know about exist before the dialogue reaches their
+    /// declarations. Unlike [`MissingSavePolicy::UseDeclaredDefault`], this
+    /// fills everything up front rather than on first read.
+    pub fn initialize_defaults(&self) {
+        self.vm.initialize_save_defaults();
+    }
+
+    /// Names of the variables in storage, sorted, without fetching their values.
+    ///
+    /// Internal entries such as `once` line counters are left out. Storage that
+    /// doesn't implement [`VariableStorage::names`] lists nothing.
+    pub fn variable_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .storage
+            .names()
+            .into_iter()
+            .filter(|name| !name.contains(':'))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Save variables the script wrote since the last [`Runtime::clear_dirty`]
+    /// (or since the runtime was created), sorted.
+    ///
+    /// Lets an autosave write only what changed. A variable counts once its
+    /// `save` declaration stores the default or a `set` assigns it, even to
+    /// the value it already had. Internal entries such as `once` line counters
+    /// and the host's own writes to storage are not tracked.
+    pub fn dirty_variables(&self) -> Vec<String> {
+        self.vm.dirty_variables()
+    }
+
+    /// Forget which variables were written, typically right after saving them.
+    pub fn clear_dirty(&mut self) {
+        self.vm.clear_dirty();
+    }
+
     /// Get a reference to the host state for external access.
     pub fn host(&self) -> &Arc<dyn HostState> {
         &self.host
     }
 
+    /// Flag the host can set (from any thread) to interrupt execution.
+    ///
+    /// While set, `advance()` and `select_choice()` return
+    /// [`RuntimeError::Cancelled`] without producing output. Clearing it lets the
+    /// runtime continue from where it stopped.
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(self.vm.cancel_flag())
+    }
+
+    /// Warnings found while compiling the script (see [`RuntimeOptions::lints`]).
+    ///
+    /// Render them with [`AriadneRenderer`] like errors.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
     pub fn current_line(&self) -> &str {
         self.current_line.as_deref().unwrap_or("")
     }
 
+    /// Rewrite every line before it becomes `current_line()`, e.g. to replace
+    /// `[player]` tokens or add rich-text markup.
+    ///
+    /// The transform sees the finished text: interpolation and conditional
+    /// text have already been applied, and the `//!` debug note is never part
+    /// of it. Choice text is not transformed. The line showing when the
+    /// transform is set is transformed right away, so it can be set just after
+    /// creating the runtime. Setting another replaces it.
+    ///
+    /// ```ignore
+    /// runtime.set_line_transform(Box::new(|line| line.replace("[player]", "Ada")));
+    /// ```
+    pub fn set_line_transform(&mut self, transform: LineTransform) {
+        if let Some(line) = &self.untransformed_line {
+            let transformed = transform(line);
+            if self.current_line.as_ref() != Some(&transformed) {
+                self.current_line = Some(transformed);
+                self.revision += 1;
+            }
+        }
+        self.line_transform = Some(transform);
+    }
+
+    /// Show lines as the script produces them again.
+    pub fn clear_line_transform(&mut self) {
+        self.line_transform = None;
+        if self.current_line.is_some() && self.current_line != self.untransformed_line {
+            self.current_line = self.untransformed_line.clone();
+            self.revision += 1;
+        }
+    }
+
+    /// The `//!` author note on the current line, if it has one.
+    ///
+    /// Notes are for development (`This needs VO. //! record later`) and are
+    /// never part of `current_line()`. Hosts decide whether to show them,
+    /// typically only in debug builds.
+    pub fn current_line_debug_note(&self) -> Option<&str> {
+        self.current_debug_note.as_deref()
+    }
+
+    /// The current line as written, before interpolation: `Hello, {name}!`
+    /// where `current_line()` is `Hello, World!`.
+    ///
+    /// For comparing authored text with rendered output, e.g. in localization
+    /// QA. Literal braces and tildes are shown escaped, as they are written in
+    /// source. `None` when no line is showing.
+    pub fn current_line_template(&self) -> Option<&str> {
+        self.current_template.as_deref()
+    }
+
+    /// The choice shown at `index` as written, before interpolation, like
+    /// [`Runtime::current_line_template`] for lines. `None` when not waiting
+    /// for a choice or when `index` is out of range.
+    pub fn current_choice_template(&self, index: usize) -> Option<&str> {
+        self.current_choices.as_ref()?;
+        self.vm.choice_template(index)
+    }
+
+    /// How many choice branches enclose the current line or the pending
+    /// choice set: 0 at the top level, 1 inside a choice, and so on.
+    ///
+    /// For debug views that indent output the way the script is indented.
+    /// 0 once the conversation is done.
+    pub fn current_depth(&self) -> usize {
+        self.current_depth
+    }
+
     pub fn current_choices(&self) -> &[String] {
         self.current_choices.as_deref().unwrap_or(&[])
     }
 
+    /// The current state as a single value, for hosts that prefer to `match`.
+    ///
+    /// Unlike `has_more()`, which turns false as soon as the final line is
+    /// showing, this reports `Line` for the final line and `Done` only after
+    /// `advance()` is called past it:
+    ///
+    /// ```ignore
+    /// loop {
+    ///     match runtime.state() {
+    ///         RuntimeState::Line(text) => {
+    ///             show(text);
+    ///             runtime.advance()?;
+    ///         }
+    ///         RuntimeState::Choices(choices) => {
+    ///             let index = pick(choices);
+    ///             runtime.select_choice(index)?;
+    ///         }
+    ///         RuntimeState::Done => break,
+    ///     }
+    /// }
+    /// ```
+    pub fn state(&self) -> RuntimeState<'_> {
+        if let Some(choices) = &self.current_choices {
+            return RuntimeState::Choices(choices);
+        }
+        match &self.current_line {
+            Some(line) if !self.finished => RuntimeState::Line(line),
+            _ => RuntimeState::Done,
+        }
+    }
+
+    /// What changed since the last call, as one event: the line or choice set
+    /// now showing, or [`Event::End`] once the final line is advanced past.
+    ///
+    /// Each step produces one event; calling again before `advance()` or
+    /// `select_choice()` returns `None`. The first call on a new runtime
+    /// reports its first line or choice set. The event gathers what the other
+    /// accessors (`current_line()`, `current_choice_tags()`, `choice_seen()`,
+    /// ...) report, which keep working alongside it:
+    ///
+    /// ```ignore
+    /// while let Some(event) = runtime.next_event() {
+    ///     match event {
+    ///         Event::Line { text, .. } => {
+    ///             show(&text);
+    ///             runtime.advance()?;
+    ///         }
+    ///         Event::Choices { options } => {
+    ///             let index = pick(&options);
+    ///             runtime.select_choice(index)?;
+    ///         }
+    ///         Event::End => break,
+    ///     }
+    /// }
+    /// ```
+    pub fn next_event(&mut self) -> Option<Event> {
+        if !std::mem::take(&mut self.event_pending) {
+            return None;
+        }
+        Some(match self.state() {
+            RuntimeState::Line(text) => Event::Line {
+                text: text.to_string(),
+                line_number: self.current_line_number.unwrap_or(0),
+                depth: self.current_depth,
+            },
+            RuntimeState::Choices(choices) => Event::Choices {
+                options: choices
+                    .iter()
+                    .enumerate()
+                    .map(|(index, text)| ChoiceOption {
+                        text: text.clone(),
+                        label: self.vm.choice_label(index).map(str::to_string),
+                        tags: self.vm.choice_tags(index).to_vec(),
+                        seen: self.vm.choice_seen(index),
+                    })
+                    .collect(),
+            },
+            RuntimeState::Done => Event::End,
+        })
+    }
+
+    /// The current choices paired with their position in the script's choice set.
+    ///
+    /// `current_choices()` and `select_choice(i)` always work in *displayed*
+    /// order: `select_choice(i)` picks the i-th entry returned here. The paired
+    /// index is the choice's position in the source, which differs from the
+    /// displayed position when choices are reordered (`shuffle`) and will skip
+    /// entries once choices can be conditionally hidden. Hosts can use it as a
+    /// stable key, e.g. for analytics or per-choice styling.
+    pub fn choices_with_indices(&self) -> Vec<(usize, &str)> {
+        self.vm
+            .choice_order()
+            .iter()
+            .copied()
+            .zip(self.current_choices().iter().map(String::as_str))
+            .collect()
+    }
+
     /// Advance to the next line of dialogue.
     ///
+    /// Does nothing while waiting for a choice; use `select_choice()` instead.
     /// Returns an error if a runtime error occurs (e.g., missing save variable).
     pub fn advance(&mut self) -> Result<(), RuntimeError> {
-        if !self.is_done {
+        if self.current_choices.is_some() {
+            return Ok(());
+        }
+        if self.is_done {
+            // Without a final line the VM has already returned, and the end
+            // was already reported
+            if !self.finished && self.current_line.is_some() {
+                // Run any trailing `debug_print`s; there is no content left to show
+                self.vm.step()?;
+                self.event_pending = true;
+            }
+            self.finished = true;
+        } else {
             self.step_vm()?;
+            self.skip_seen_lines()?;
+        }
+        Ok(())
+    }
+
+    /// Whether the current line had been shown before, in this conversation
+    /// or an earlier one sharing the same storage. Always false unless
+    /// [`RuntimeOptions::track_seen_lines`] is set.
+    pub fn current_line_seen(&self) -> bool {
+        self.current_line.is_some() && self.current_line_seen
+    }
+
+    /// Turn skip-seen mode on or off. While it is on, `advance()` and
+    /// `select_choice()` fast-forward past lines shown before, stopping at
+    /// the first unseen line, at choices, or at the final line, so players
+    /// replaying a conversation only read what is new. Skipped lines run as
+    /// usual, like with `skip_to_choice()`.
+    ///
+    /// Needs [`RuntimeOptions::track_seen_lines`]; without it no line counts
+    /// as seen. Turning it on while a seen line is showing skips right away.
+    pub fn set_skip_seen(&mut self, enabled: bool) -> Result<(), RuntimeError> {
+        self.skip_seen = enabled;
+        self.skip_seen_lines()
+    }
+
+    /// Advance past lines until choices are showing or the final line is reached.
+    ///
+    /// Skipped lines are discarded, but everything else along the way runs as
+    /// usual: assignments take effect and host state is read. Does nothing if
+    /// choices are already showing.
+    pub fn skip_to_choice(&mut self) -> Result<(), RuntimeError> {
+        while self.has_more() && !self.is_waiting_for_choice() {
+            self.advance()?;
         }
         Ok(())
     }
 
+    /// Advance up to `count` times and return the lines shown along the way,
+    /// ending with the one now current.
+    ///
+    /// Stops early once choices are showing or the final line is reached,
+    /// like `skip_to_choice()`, so a UI filling a screen never advances past
+    /// something that needs the player. Returns no lines if choices are
+    /// already showing or the final line is current.
+    pub fn advance_n(&mut self, count: usize) -> Result<Vec<String>, RuntimeError> {
+        let mut lines = Vec::new();
+        for _ in 0..count {
+            if !self.has_more() || self.is_waiting_for_choice() {
+                break;
+            }
+            self.advance()?;
+            if let Some(line) = &self.current_line {
+                lines.push(line.clone());
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Play the rest of the conversation, choosing with `strategy`, and record
+    /// everything shown.
+    ///
+    /// Starts from the current state: a showing line is recorded first, and
+    /// showing choices are answered first. Stops after the final line, leaving
+    /// it current; once `advance()` has gone past the end, there is nothing to
+    /// record. Assignments and host reads happen as they would in play, so
+    /// run this on a runtime with throwaway storage when only the text matters.
+    ///
+    /// Scripts can't loop yet, so every path reaches the end and there is no
+    /// step limit to hit. An index the strategy picks out of range fails like
+    /// `select_choice()` does.
+    pub fn transcript(
+        &mut self,
+        mut strategy: ChoiceStrategy,
+    ) -> Result<Vec<TranscriptEntry>, RuntimeError> {
+        let mut entries = Vec::new();
+        loop {
+            if let Some(options) = self.current_choices.clone() {
+                let selected = strategy.pick(&options);
+                self.select_choice(selected)?;
+                entries.push(TranscriptEntry::Choice { options, selected });
+            } else if let Some(line) = self.current_line.as_ref().filter(|_| !self.finished) {
+                entries.push(TranscriptEntry::Line(line.clone()));
+                if !self.has_more() {
+                    break;
+                }
+                self.advance()?;
+            } else {
+                break;
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Counter that increments whenever the runtime shows something new: every
+    /// line and choice set, even one whose text repeats the last, and the end
+    /// of the conversation.
+    ///
+    /// UIs can cache the last revision they rendered and skip redrawing when it
+    /// hasn't moved. Calls that show nothing new (e.g. advancing past the end,
+    /// or advancing while choices are up) don't increment it.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Whether the first step has run, so the current line, choices, and
+    /// [`Runtime::has_more`] reflect the script. See the lifecycle notes on
+    /// [`Runtime`].
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
     pub fn has_more(&self) -> bool {
         !self.is_done
     }
@@ -256,11 +1264,211 @@ impl Runtime {
         self.current_choices.is_some()
     }
 
+    /// Whether `select_choice(index)` would accept `index` right now: the
+    /// runtime is waiting for a choice, isn't cancelled, `index` is one of
+    /// the offered choices, and the choice validator (if any) allows it.
+    /// Choices can't be disabled yet; once conditional choices exist, a
+    /// disabled choice will report false here too.
+    pub fn can_select_choice(&self, index: usize) -> bool {
+        if self.vm.cancel_flag().load(Ordering::Relaxed) {
+            return false;
+        }
+        self.current_choices
+            .as_ref()
+            .and_then(|choices| choices.get(index))
+            .is_some_and(|text| {
+                self.choice_validator
+                    .as_ref()
+                    .is_none_or(|v| v(index, text))
+            })
+    }
+
+    /// Let game logic the script doesn't model (affordability, cooldowns)
+    /// reject a selection. The validator gets the index and displayed text of
+    /// the choice being selected; when it returns false, `select_choice`
+    /// fails with [`RuntimeError::ChoiceVetoed`] and the choices stay up.
+    /// Setting another replaces it. It is kept across [`Runtime::reload`].
+    ///
+    /// ```ignore
+    /// runtime.set_choice_validator(Box::new(move |_, text| text != "Buy the sword" || gold >= 50));
+    /// ```
+    pub fn set_choice_validator(&mut self, validator: ChoiceValidator) {
+        self.choice_validator = Some(validator);
+    }
+
+    /// Accept every valid selection again.
+    pub fn clear_choice_validator(&mut self) {
+        self.choice_validator = None;
+    }
+
     pub fn select_choice(&mut self, index: usize) -> Result<(), RuntimeError> {
-        if self.current_choices.is_some() {
-            self.current_choices = None;
-            let result = self.vm.select_and_continue(index)?;
-            self.handle_step_result(result);
+        if let Some(choices) = &self.current_choices {
+            if let (Some(text), Some(validator)) = (choices.get(index), &self.choice_validator)
+                && !validator(index, text)
+            {
+                return Err(RuntimeError::ChoiceVetoed { index });
+            }
+            let record = choices.get(index).map(|text| ChoiceRecord {
+                text: text.clone(),
+                label: self.vm.choice_label(index).map(str::to_string),
+            });
+            match self.vm.select_and_continue(index) {
+                Ok(result) => {
+                    self.current_choices = None;
+                    self.choice_history.extend(record);
+                    self.handle_step_result(result);
+                    self.skip_seen_lines()?;
+                }
+                Err(err) => {
+                    // Rejected selections (bad index, cancelled) leave the choices up;
+                    // an error inside the chosen branch means the choice was taken.
+                    if !self.vm.is_at_choice() {
+                        self.current_choices = None;
+                        self.choice_history.extend(record);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Identifies the pending choice set for [`Runtime::select_choice_checked`].
+    ///
+    /// Changes every time the runtime stops at a choice set, even when it
+    /// shows the same choices as the last one.
+    pub fn choice_token(&self) -> u64 {
+        self.choice_token
+    }
+
+    /// Like [`Runtime::select_choice`], but only if the choices on screen are
+    /// still the ones that were read along with `token` from
+    /// [`Runtime::choice_token`].
+    ///
+    /// Guards hosts whose UI can fall behind the runtime: an index picked from
+    /// an older choice set fails with [`RuntimeError::StaleChoice`] instead of
+    /// selecting whatever now sits at that position. Fails with
+    /// [`RuntimeError::NotAtChoice`] when no choices are showing.
+    pub fn select_choice_checked(&mut self, index: usize, token: u64) -> Result<(), RuntimeError> {
+        if self.current_choices.is_none() {
+            return Err(RuntimeError::NotAtChoice);
+        }
+        if token != self.choice_token {
+            return Err(RuntimeError::StaleChoice {
+                token,
+                current: self.choice_token,
+            });
+        }
+        self.select_choice(index)
+    }
+
+    /// Tags written after the choice shown at `index` (`- Attack #combat`
+    /// has the tag `combat`), for hosts to pick icons or styles. Tags are
+    /// never part of the displayed text. Empty when not waiting for a choice.
+    pub fn current_choice_tags(&self, index: usize) -> &[String] {
+        if self.current_choices.is_none() {
+            return &[];
+        }
+        self.vm.choice_tags(index)
+    }
+
+    /// Whether the player picked the choice shown at `index` before, in this
+    /// conversation or an earlier one sharing the same storage.
+    ///
+    /// Unlike `once`, a seen choice stays selectable; UIs can grey it out or
+    /// mark it. Only labelled choices (`- (label) text`) are tracked, under a
+    /// `seen:` key in storage, so renaming a label forgets it. False when not
+    /// waiting for a choice or `index` is out of range.
+    pub fn choice_seen(&self, index: usize) -> bool {
+        self.current_choices.is_some() && self.vm.choice_seen(index)
+    }
+
+    /// Every choice selected so far in this conversation, oldest first.
+    ///
+    /// Choices written as `- (label) text` carry their label, which stays the
+    /// same when the text is reworded, so prefer it for analytics and save data.
+    pub fn choice_history(&self) -> &[ChoiceRecord] {
+        &self.choice_history
+    }
+
+    /// Count the lines choice `index` would show before the next choice set
+    /// or the end of the dialogue, without selecting it.
+    ///
+    /// The branch is simulated on a copy of the runtime: `set` assignments
+    /// during simulation are discarded and storage is left untouched. Host
+    /// state is only read, so the count reflects the host's current values.
+    /// Host functions, once supported, would run for real during simulation,
+    /// so this must not be used on branches that call them.
+    pub fn peek_branch_length(&self, index: usize) -> Result<usize, RuntimeError> {
+        if self.current_choices.is_none() {
+            return Err(RuntimeError::NotAtChoice);
+        }
+
+        let mut vm = self.vm.fork();
+        let mut result = vm.select_and_continue(index)?;
+        let mut lines = 0;
+        while let StepResult::Line(_) = result {
+            lines += 1;
+            result = vm.step()?;
+        }
+        Ok(lines)
+    }
+
+    /// Whether choice `index` has nested statements of its own, rather than
+    /// going straight on to whatever follows the choice set.
+    ///
+    /// For UIs that mark choices that lead somewhere. Any nested statement
+    /// counts, even a lone `set`; use [`choice_previews`](Self::choice_previews)
+    /// to see whether the branch shows a line. Reads the compiled branch
+    /// without running it. False when not at a choice or `index` is out of
+    /// range.
+    pub fn choice_branch_has_content(&self, index: usize) -> bool {
+        self.current_choices.is_some()
+            && self.vm.choice_branch(index).is_some_and(|branch| {
+                // An empty branch is only its jump to the gather point
+                !matches!(
+                    self.vm.chunk().code.get(branch.start),
+                    Some(Instruction::Jump { .. })
+                )
+            })
+    }
+
+    /// Preview where each pending choice leads: the first line its branch
+    /// would show, in display order.
+    ///
+    /// A choice whose branch has no line of its own before its nested choices
+    /// or the gather point previews as `None`, as does one whose branch fails
+    /// during simulation. Like [`peek_branch_length`](Self::peek_branch_length),
+    /// each branch runs on a copy of the runtime and nothing is written back.
+    /// Host functions, once supported, must not run here; a branch that leads
+    /// with one should preview as `None`. Empty when not at a choice.
+    pub fn choice_previews(&self) -> Vec<Option<String>> {
+        let Some(choices) = &self.current_choices else {
+            return Vec::new();
+        };
+
+        (0..choices.len())
+            .map(|index| {
+                let branch = self.vm.choice_branch(index)?;
+                let mut vm = self.vm.fork();
+                match vm.select_and_continue(index) {
+                    // The line must come from the branch, not from past the gather
+                    Ok(StepResult::Line(text)) if branch.contains(&(vm.ip() - 1)) => Some(text),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// In skip-seen mode, step past lines shown before, stopping at an unseen
+    /// line, choices, or the final line.
+    fn skip_seen_lines(&mut self) -> Result<(), RuntimeError> {
+        while self.skip_seen
+            && self.current_line.is_some()
+            && self.current_line_seen
+            && !self.is_done
+        {
+            self.step_vm()?;
         }
         Ok(())
     }
@@ -272,18 +1480,43 @@ impl Runtime {
     }
 
     fn handle_step_result(&mut self, result: StepResult) {
+        self.ready = true;
+        self.event_pending = true;
+        // Every step shows something new, even a line repeating the last one
+        self.revision += 1;
+
         match result {
             StepResult::Line(text) => {
-                self.current_line = Some(text);
+                self.current_line = Some(match &self.line_transform {
+                    Some(transform) => transform(&text),
+                    None => text.clone(),
+                });
+                self.untransformed_line = Some(text);
+                self.current_debug_note = self.vm.line_debug_note().map(str::to_string);
+                self.current_template = self.vm.line_template().map(str::to_string);
+                self.current_depth = self.vm.line_nesting();
+                self.current_line_number = self.vm.line_number();
+                self.current_line_seen = self.vm.line_seen();
                 // Check if this was the last line (no more content after this)
                 self.is_done = self.vm.is_at_end();
             }
             StepResult::Choice(choices) => {
                 self.current_line = None;
+                self.untransformed_line = None;
+                self.current_debug_note = None;
+                self.current_template = None;
+                self.current_depth = self.vm.choice_nesting();
+                self.current_line_number = None;
                 self.current_choices = Some(choices);
+                self.choice_token += 1;
             }
             StepResult::Done => {
                 self.current_line = None;
+                self.untransformed_line = None;
+                self.current_debug_note = None;
+                self.current_template = None;
+                self.current_depth = 0;
+                self.current_line_number = None;
                 self.is_done = true;
             }
         }
@@ -1,10 +1,24 @@
 use std::iter::Peekable;
 
-use crate::ast::{Choice, ExternDeclData, Literal, NodeId, Script, Stmt, TextPart, VarBindingData};
+use crate::ast::{
+    ArithOp, Choice, ChoiceMarker, CompareOp, Expr, ExternDeclData, Literal, NodeId, Script, Stmt,
+    TextPart, ValueType, VarBindingData,
+};
 use crate::diagnostic::{Diagnostic, DiagnosticContext, IntoDiagnostic};
 use crate::scanner::LexicalError;
 use crate::token::{Span, Token, TokenKind};
 
+/// How many blocks can nest under a top-level choice, and how many parentheses
+/// or `{if}` blocks can nest inside one another. Parsing, resolving and compiling all recurse once
+/// per level, so this keeps pathological scripts off the stack limit.
+const MAX_NESTING_DEPTH: usize = 64;
+
+/// Most decimal places a format spec may ask for. For any number of 1 or
+/// more, 17 decimal places already go past the digits an f64 keeps, so more
+/// would only show rounding noise; the cap also stops a typo like `:.70000`
+/// from building a huge string.
+const MAX_PRECISION: usize = 17;
+
 #[derive(Debug, Clone)]
 pub enum ParseError {
     Lexical(LexicalError),
@@ -32,6 +46,12 @@ pub struct Parser<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> {
     tokens: Peekable<I>,
     errors: Vec<ParseError>,
     next_id: usize,
+    /// Blocks currently open around the statement being parsed
+    depth: usize,
+    /// Parentheses currently open around the operand being parsed
+    group_depth: usize,
+    /// `{if}` blocks currently open around the text being parsed
+    conditional_depth: usize,
 }
 
 impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
@@ -40,6 +60,9 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
             tokens: tokens.peekable(),
             errors: Vec::new(),
             next_id: 0,
+            depth: 0,
+            group_depth: 0,
+            conditional_depth: 0,
         }
     }
 
@@ -75,10 +98,28 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
             Some(Ok(t)) => match t.kind {
                 TokenKind::Temp => Some(self.temp_declaration()),
                 TokenKind::Save => Some(self.save_declaration()),
+                TokenKind::Const => Some(self.const_declaration()),
                 TokenKind::Extern => Some(self.extern_declaration()),
                 TokenKind::Set => Some(self.assignment()),
-                TokenKind::TextSegment | TokenKind::OpenBrace => Some(self.line_statement()),
-                TokenKind::Choice => Some(self.choice_set()),
+                TokenKind::TextSegment | TokenKind::OpenBrace | TokenKind::Tilde => {
+                    Some(self.line_statement())
+                }
+                TokenKind::Choice => Some(self.choice_set(false)),
+                TokenKind::Shuffle => Some(self.shuffled_choice_set()),
+                TokenKind::Once => Some(self.once_line()),
+                TokenKind::DebugPrint => Some(self.debug_print()),
+                TokenKind::Assert => Some(self.assertion()),
+                TokenKind::Namespace => Some(self.namespace()),
+                TokenKind::End => Some(Stmt::End {
+                    span: self.advance().span,
+                }),
+                TokenKind::Blank => Some(Stmt::Line {
+                    parts: Vec::new(),
+                    span: self.advance().span,
+                    once: false,
+                    effect: None,
+                    debug_note: None,
+                }),
                 _ => None,
             },
             _ => None,
@@ -129,7 +170,10 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
         }
 
         if self.errors.is_empty() {
-            Ok(Script { statements })
+            Ok(Script {
+                statements,
+                ..Default::default()
+            })
         } else {
             Err(self.errors)
         }
@@ -149,7 +193,14 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
         Stmt::SaveDecl(data)
     }
 
-    /// Parse an extern declaration: extern name (no initializer)
+    /// Parse a constant declaration: const name = value
+    fn const_declaration(&mut self) -> Stmt {
+        let start_token = self.advance(); // Consume 'const'
+        let data = self.parse_var_binding("const", start_token.span.start);
+        Stmt::ConstDecl(data)
+    }
+
+    /// Parse an extern declaration: extern name [: type] [= default]
     fn extern_declaration(&mut self) -> Stmt {
         let start_token = self.advance(); // Consume 'extern'
         let id = self.next_id();
@@ -168,13 +219,49 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
             (String::new(), start_token.span.end)
         };
 
+        // Optional type the host's value must have
+        let (ty, end) = if self.check(TokenKind::Colon) {
+            self.advance();
+            match self.tokens.peek() {
+                Some(Ok(token))
+                    if token.kind == TokenKind::Identifier
+                        && ValueType::from_name(token.lexeme).is_some() =>
+                {
+                    let token = self.advance();
+                    (ValueType::from_name(token.lexeme), token.span.end)
+                }
+                _ => {
+                    let span = self.current_span();
+                    self.errors.push(ParseError::Syntax {
+                        message: "Expected a type after ':' (number, string or bool)".to_string(),
+                        span,
+                    });
+                    self.synchronize();
+                    (None, end)
+                }
+            }
+        } else {
+            (None, end)
+        };
+
+        // Optional default, used when the host doesn't provide the variable
+        let (default, end) = if self.check(TokenKind::Equals) {
+            let equals = self.advance();
+            let (literal, end) = self.parse_value_after(equals);
+            (Some(literal), end)
+        } else {
+            (None, end)
+        };
+
         Stmt::ExternDecl(ExternDeclData {
             id,
             name,
+            default,
             span: Span {
                 start: start_token.span.start,
                 end,
             },
+            ty,
         })
     }
 
@@ -185,6 +272,205 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
         Stmt::Assignment(data)
     }
 
+    /// Parse a debug print: debug_print "message"
+    fn debug_print(&mut self) -> Stmt {
+        let keyword = self.advance(); // Consume 'debug_print'
+
+        if !self.check(TokenKind::String) {
+            let span = self.current_span();
+            self.errors.push(ParseError::Syntax {
+                message: "Expected a string after 'debug_print'".to_string(),
+                span,
+            });
+            self.synchronize();
+            return Stmt::DebugPrint {
+                message: String::new(),
+                span: keyword.span,
+            };
+        }
+
+        let (message, end) = match self.parse_literal() {
+            (Literal::String(message), end) => (message, end),
+            (_, end) => (String::new(), end),
+        };
+        Stmt::DebugPrint {
+            message,
+            span: Span {
+                start: keyword.span.start,
+                end,
+            },
+        }
+    }
+
+    /// Parse a namespace directive: namespace name
+    fn namespace(&mut self) -> Stmt {
+        let keyword = self.advance(); // Consume 'namespace'
+
+        if !self.check(TokenKind::Identifier) {
+            let span = self.current_span();
+            self.errors.push(ParseError::Syntax {
+                message: "Expected a name after 'namespace'".to_string(),
+                span,
+            });
+            self.synchronize();
+            return Stmt::Namespace {
+                name: String::new(),
+                span: keyword.span,
+            };
+        }
+
+        let name = self.advance();
+        Stmt::Namespace {
+            name: name.lexeme.to_string(),
+            span: Span {
+                start: keyword.span.start,
+                end: name.span.end,
+            },
+        }
+    }
+
+    /// Parse an assertion: assert <operand> [<comparison> <operand>]
+    fn assertion(&mut self) -> Stmt {
+        let keyword = self.advance(); // Consume 'assert'
+
+        let Some(condition) = self.comparison() else {
+            self.synchronize();
+            // Placeholder that always holds; the script won't compile anyway
+            let condition = Expr::Literal {
+                value: Literal::Bool(true),
+                span: keyword.span,
+            };
+            return Stmt::Assert {
+                condition,
+                span: keyword.span,
+            };
+        };
+
+        if let Some(Ok(token)) = self.tokens.peek()
+            && !matches!(
+                token.kind,
+                TokenKind::NewLine | TokenKind::Eof | TokenKind::Dedent
+            )
+        {
+            let message = if token.kind == TokenKind::Equals {
+                "Use '==' to compare values in an assertion".to_string()
+            } else if compare_op(token.kind).is_some() {
+                format!(
+                    "Unexpected '{}' - comparisons can't be chained; group one in parentheses, like '(a == b) == c'",
+                    token.lexeme
+                )
+            } else {
+                format!(
+                    "Unexpected '{}' - an assertion is a single value or one comparison",
+                    token.lexeme
+                )
+            };
+            let span = token.span;
+            self.errors.push(ParseError::Syntax { message, span });
+            self.synchronize();
+        }
+
+        Stmt::Assert {
+            span: Span {
+                start: keyword.span.start,
+                end: condition.span().end,
+            },
+            condition,
+        }
+    }
+
+    /// Parse an operand, optionally compared with a second one.
+    fn comparison(&mut self) -> Option<Expr> {
+        let left = self.operand()?;
+        let op = match self.tokens.peek() {
+            Some(Ok(token)) => match compare_op(token.kind) {
+                Some(op) => op,
+                None => return Some(left),
+            },
+            _ => return Some(left),
+        };
+        self.advance();
+        let right = self.operand()?;
+        Some(Expr::Compare {
+            op,
+            span: Span {
+                start: left.span().start,
+                end: right.span().end,
+            },
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    /// Parse a variable name, a literal value, or a comparison in parentheses.
+    fn operand(&mut self) -> Option<Expr> {
+        match self.tokens.peek() {
+            Some(Ok(token)) if token.kind == TokenKind::OpenParen => {
+                let open = self.advance();
+                if self.group_depth == MAX_NESTING_DEPTH {
+                    self.errors.push(ParseError::Syntax {
+                        message: format!(
+                            "Parentheses are nested too deeply - at most {} levels of nesting are supported",
+                            MAX_NESTING_DEPTH
+                        ),
+                        span: open.span,
+                    });
+                    return None;
+                }
+                self.group_depth += 1;
+                let inner = self.comparison();
+                self.group_depth -= 1;
+                let mut inner = inner?;
+                if !self.check(TokenKind::CloseParen) {
+                    let span = self.current_span();
+                    self.errors.push(ParseError::Syntax {
+                        message: "Expected ')' to close the group".to_string(),
+                        span,
+                    });
+                    return None;
+                }
+                let close = self.advance();
+                let (Expr::Literal { span, .. }
+                | Expr::Var { span, .. }
+                | Expr::Compare { span, .. }) = &mut inner;
+                *span = Span {
+                    start: open.span.start,
+                    end: close.span.end,
+                };
+                Some(inner)
+            }
+            Some(Ok(token)) if token.kind == TokenKind::Identifier => {
+                let token = self.advance();
+                Some(Expr::Var {
+                    id: self.next_id(),
+                    name: token.lexeme.to_string(),
+                    span: token.span,
+                })
+            }
+            Some(Ok(token))
+                if matches!(
+                    token.kind,
+                    TokenKind::String | TokenKind::Number | TokenKind::True | TokenKind::False
+                ) =>
+            {
+                let start = token.span.start;
+                let (value, end) = self.parse_literal();
+                Some(Expr::Literal {
+                    value,
+                    span: Span { start, end },
+                })
+            }
+            _ => {
+                let span = self.current_span();
+                self.errors.push(ParseError::Syntax {
+                    message: "Expected a variable or a value".to_string(),
+                    span,
+                });
+                None
+            }
+        }
+    }
+
     /// Parse a literal value (string, number, or boolean)
     fn parse_literal(&mut self) -> (Literal, usize) {
         match self.tokens.peek() {
@@ -240,9 +526,9 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
         let id = self.next_id();
 
         // Expect identifier
-        let name = if self.check(TokenKind::Identifier) {
+        let (name, name_end) = if self.check(TokenKind::Identifier) {
             let token = self.advance();
-            token.lexeme.to_string()
+            (token.lexeme.to_string(), token.span.end)
         } else {
             let span = self.current_span();
             self.errors.push(ParseError::Syntax {
@@ -255,16 +541,50 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
                 name: String::new(),
                 value: Literal::Bool(false),
                 span: Span { start, end: start },
+                op: None,
             };
         };
 
-        // Expect '='
-        if self.check(TokenKind::Equals) {
-            self.advance();
+        // Expect '=', or a compound operator like '+=' after `set`
+        let mut op = None;
+        let operator = if self.check(TokenKind::Equals) {
+            self.advance()
+        } else if let Some(compound) = match self.tokens.peek() {
+            Some(Ok(token)) => compound_op(token.kind),
+            _ => None,
+        } {
+            let token = self.advance();
+            if keyword == "set" {
+                op = Some(compound);
+            } else {
+                self.errors.push(ParseError::Syntax {
+                    message: format!(
+                        "'{}' only works with 'set'; a {} declaration needs '='",
+                        token.lexeme, keyword
+                    ),
+                    span: token.span,
+                });
+            }
+            token
         } else {
-            let span = self.current_span();
+            // At the end of the line, point just past the name
+            let (found, span) = match self.tokens.peek() {
+                Some(Ok(token)) if !ends_line(token.kind) => {
+                    (format!("'{}'", token.lexeme), token.span)
+                }
+                _ => (
+                    "the end of the line".to_string(),
+                    Span {
+                        start: name_end,
+                        end: name_end,
+                    },
+                ),
+            };
             self.errors.push(ParseError::Syntax {
-                message: format!("Expected '=' in {} statement", keyword),
+                message: format!(
+                    "Expected '=' after '{}' in {} statement, found {}",
+                    name, keyword, found
+                ),
                 span,
             });
             self.synchronize();
@@ -273,31 +593,196 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
                 name,
                 value: Literal::Bool(false),
                 span: Span { start, end: start },
+                op: None,
             };
-        }
+        };
 
-        // Parse literal value
-        let (value, end) = self.parse_literal();
+        let (value, end) = self.parse_value_after(operator);
 
         VarBindingData {
             id,
             name,
             value,
             span: Span { start, end },
+            op,
+        }
+    }
+
+    /// Parse the literal value after `=` or another operator. A missing value
+    /// is reported just past the operator, rather than at whatever starts the
+    /// next line.
+    fn parse_value_after(&mut self, operator: Token) -> (Literal, usize) {
+        match self.tokens.peek() {
+            Some(Ok(token)) if !is_literal(token.kind) => {
+                let token_kind = token.kind;
+                let (found, span) = if ends_line(token_kind) {
+                    (
+                        String::new(),
+                        Span {
+                            start: operator.span.end,
+                            end: operator.span.end,
+                        },
+                    )
+                } else {
+                    (format!(", found '{}'", token.lexeme), token.span)
+                };
+                self.errors.push(ParseError::Syntax {
+                    message: format!(
+                        "Expected a value after '{}'{} (a number, a \"string\", true or false)",
+                        operator.lexeme, found
+                    ),
+                    span,
+                });
+                if !ends_line(token_kind) {
+                    self.synchronize();
+                }
+                (Literal::Bool(false), span.end)
+            }
+            _ => self.parse_literal(),
         }
     }
 
     /// Parse a line statement (text content with possible interpolation)
     fn line_statement(&mut self) -> Stmt {
-        let (parts, span) = self.parse_text_parts();
-        Stmt::Line { parts, span }
+        let (mut parts, mut span) = self.parse_text_parts();
+        let debug_note = self.parse_debug_note(&mut parts, &mut span);
+        let effect = self.parse_effect(&mut parts, &mut span);
+        Stmt::Line {
+            parts,
+            span,
+            once: false,
+            effect,
+            debug_note,
+        }
+    }
+
+    /// Parse a line with the `once` modifier: once text
+    fn once_line(&mut self) -> Stmt {
+        let keyword = self.advance(); // Consume 'once'
+        let (mut parts, mut span) = self.parse_text_parts();
+        // A `//!` or `~` reports the missing text itself
+        if parts.is_empty() && !self.check(TokenKind::DebugNote) && !self.check(TokenKind::Tilde) {
+            self.errors.push(ParseError::Syntax {
+                message: "Expected text after 'once'".to_string(),
+                span: keyword.span,
+            });
+        }
+        let debug_note = self.parse_debug_note(&mut parts, &mut span);
+        let effect = self.parse_effect(&mut parts, &mut span);
+
+        Stmt::Line {
+            parts,
+            span: Span {
+                start: keyword.span.start,
+                end: span.end.max(keyword.span.end),
+            },
+            once: true,
+            effect,
+            debug_note,
+        }
+    }
+
+    /// Parse an optional trailing debug note: `//! note`.
+    /// Spaces before the `//!` are trimmed from the text.
+    fn parse_debug_note(&mut self, parts: &mut Vec<TextPart>, span: &mut Span) -> Option<String> {
+        if !self.check(TokenKind::DebugNote) {
+            return None;
+        }
+        let note = self.advance();
+        trim_trailing_spaces(parts, span);
+
+        if parts.is_empty() {
+            self.errors.push(ParseError::Syntax {
+                message: "Expected text before '//!'".to_string(),
+                span: note.span,
+            });
+        }
+        Some(note.lexeme.to_string())
+    }
+
+    /// Parse a choice's optional trailing tags: `#combat #red`.
+    /// Spaces before the first `#` are trimmed from the text.
+    fn parse_choice_tags(&mut self, parts: &mut Vec<TextPart>, span: &mut Span) -> Vec<String> {
+        if !self.check(TokenKind::Tags) {
+            return Vec::new();
+        }
+        let token = self.advance();
+        trim_trailing_spaces(parts, span);
+
+        if parts.is_empty() {
+            self.errors.push(ParseError::Syntax {
+                message: "Expected choice text before its tags".to_string(),
+                span: token.span,
+            });
+        }
+        let mut tags = Vec::new();
+        let mut start = token.span.start;
+        for word in token.lexeme.split(' ') {
+            let word_start = start;
+            start += word.len() + 1;
+            if word.is_empty() {
+                continue;
+            }
+            match word.strip_prefix('#') {
+                Some(tag) if !tag.is_empty() && !tag.contains('#') => tags.push(tag.to_string()),
+                _ => {
+                    self.errors.push(ParseError::Syntax {
+                        message: format!(
+                            "Expected a tag like '#name', found '{}' - tags go at the end of a choice (use '\\#' for a literal '#')",
+                            word
+                        ),
+                        span: Span {
+                            start: word_start,
+                            end: word_start + word.len(),
+                        },
+                    });
+                }
+            }
+        }
+        tags
+    }
+
+    /// Parse an optional trailing effect: `~ set name = value`.
+    /// Spaces before the `~` are trimmed from the text.
+    fn parse_effect(
+        &mut self,
+        parts: &mut Vec<TextPart>,
+        span: &mut Span,
+    ) -> Option<VarBindingData> {
+        if !self.check(TokenKind::Tilde) {
+            return None;
+        }
+        let tilde = self.advance();
+
+        if parts.is_empty() {
+            self.errors.push(ParseError::Syntax {
+                message: "Expected text before '~'".to_string(),
+                span: tilde.span,
+            });
+        }
+        trim_trailing_spaces(parts, span);
+
+        // The scanner only lets `set` through; anything else was a lexical error
+        if !self.check(TokenKind::Set) {
+            return None;
+        }
+        let keyword = self.advance();
+        Some(self.parse_var_binding("set", keyword.span.start))
     }
 
-    /// Parse text parts until newline (TextSegment, interpolations)
+    /// Parse text parts until newline (TextSegment, interpolations, conditional blocks)
     fn parse_text_parts(&mut self) -> (Vec<TextPart>, Span) {
+        let (parts, span, _) = self.parse_text_run(false);
+        (parts, span)
+    }
+
+    /// Parse text parts until the end of the line or, inside a conditional
+    /// block, until its `{else}` or `{endif}` (which is consumed).
+    fn parse_text_run(&mut self, in_conditional: bool) -> (Vec<TextPart>, Span, TextEnd) {
         let mut parts = Vec::new();
         let mut start: Option<usize> = None;
         let mut end: usize = 0;
+        let mut ending = TextEnd::Line;
 
         loop {
             match self.tokens.peek() {
@@ -315,15 +800,16 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
                     }
                     TokenKind::OpenBrace => {
                         let open = self.advance();
-                        if start.is_none() {
-                            start = Some(open.span.start);
-                        }
 
-                        // Expect identifier
+                        // Expect identifier or a conditional keyword
                         match self.tokens.peek() {
                             Some(Ok(t)) if t.kind == TokenKind::Identifier => {
+                                if start.is_none() {
+                                    start = Some(open.span.start);
+                                }
                                 let id_token = self.advance();
                                 let var_name = id_token.lexeme.to_string();
+                                let precision = self.format_precision();
 
                                 // Expect close brace
                                 match self.tokens.peek() {
@@ -333,6 +819,7 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
                                         parts.push(TextPart::VarRef {
                                             id: self.next_id(),
                                             name: var_name,
+                                            precision,
                                             span: Span {
                                                 start: open.span.start,
                                                 end: close.span.end,
@@ -348,12 +835,64 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
                                     }
                                 }
                             }
+                            Some(Ok(t)) if t.kind == TokenKind::If => {
+                                if start.is_none() {
+                                    start = Some(open.span.start);
+                                }
+                                let part = self.conditional_text(open.span);
+                                if let TextPart::Conditional { span, .. } = &part {
+                                    end = span.end;
+                                }
+                                parts.push(part);
+                            }
+                            Some(Ok(t)) if matches!(t.kind, TokenKind::Else | TokenKind::EndIf) => {
+                                let keyword = self.advance();
+                                let tag = Span {
+                                    start: open.span.start,
+                                    end: self.close_text_tag(
+                                        &format!("'{}'", keyword.lexeme),
+                                        keyword.span,
+                                    ),
+                                };
+                                if in_conditional {
+                                    ending = if keyword.kind == TokenKind::Else {
+                                        TextEnd::Else(tag)
+                                    } else {
+                                        TextEnd::EndIf(tag)
+                                    };
+                                    break;
+                                }
+                                self.errors.push(ParseError::Syntax {
+                                    message: format!(
+                                        "'{{{}}}' without a matching '{{if}}'",
+                                        keyword.lexeme
+                                    ),
+                                    span: tag,
+                                });
+                                end = tag.end;
+                            }
                             _ => {
+                                if start.is_none() {
+                                    start = Some(open.span.start);
+                                }
+                                // Point at whatever took the name's place, past
+                                // any spaces after the brace
+                                let span = match self.tokens.peek() {
+                                    Some(Ok(t)) if t.kind != TokenKind::NewLine => t.span,
+                                    _ => open.span,
+                                };
                                 self.errors.push(ParseError::Syntax {
                                     message: "Expected variable name after '{'".to_string(),
-                                    span: open.span,
+                                    span,
                                 });
                                 end = open.span.end;
+                                // Consume the brace of an empty `{}` so the rest of
+                                // the line isn't reported again
+                                if let Some(Ok(t)) = self.tokens.peek()
+                                    && t.kind == TokenKind::CloseBrace
+                                {
+                                    end = self.advance().span.end;
+                                }
                             }
                         }
                     }
@@ -380,43 +919,258 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
             start: start.unwrap_or(0),
             end,
         };
-        (parts, span)
+        (parts, span, ending)
+    }
+
+    /// Parse a conditional block after its opening `{`:
+    /// `if name}then{else}otherwise{endif}`, where `{else}` is optional.
+    fn conditional_text(&mut self, open: Span) -> TextPart {
+        let keyword = self.advance(); // Consume 'if'
+
+        if self.conditional_depth == MAX_NESTING_DEPTH {
+            self.errors.push(ParseError::Syntax {
+                message: format!(
+                    "'{{if}}' blocks are nested too deeply - at most {} levels of nesting are supported",
+                    MAX_NESTING_DEPTH
+                ),
+                span: Span {
+                    start: open.start,
+                    end: keyword.span.end,
+                },
+            });
+            let end = self.skip_conditional();
+            return TextPart::Conditional {
+                id: self.next_id(),
+                name: String::new(),
+                name_span: keyword.span,
+                then_parts: Vec::new(),
+                else_parts: Vec::new(),
+                span: Span {
+                    start: open.start,
+                    end: end.max(keyword.span.end),
+                },
+            };
+        }
+        self.conditional_depth += 1;
+        let part = self.conditional_body(open, keyword);
+        self.conditional_depth -= 1;
+        part
+    }
+
+    /// The rest of `conditional_text`, after the depth check.
+    fn conditional_body(&mut self, open: Span, keyword: Token<'a>) -> TextPart {
+        let (name, name_span) = match self.tokens.peek() {
+            Some(Ok(t)) if t.kind == TokenKind::Identifier => {
+                let token = self.advance();
+                (token.lexeme.to_string(), token.span)
+            }
+            _ => {
+                self.errors.push(ParseError::Syntax {
+                    message: "Expected variable name after 'if'".to_string(),
+                    span: keyword.span,
+                });
+                (String::new(), keyword.span)
+            }
+        };
+        let mut end = self.close_text_tag("variable name", name_span);
+
+        let (then_parts, then_span, mut ending) = self.parse_text_run(true);
+        end = end.max(then_span.end);
+
+        let mut else_parts = Vec::new();
+        if matches!(ending, TextEnd::Else(_)) {
+            loop {
+                let (parts, span, next) = self.parse_text_run(true);
+                else_parts.extend(parts);
+                end = end.max(span.end);
+                ending = next;
+                let TextEnd::Else(extra) = ending else {
+                    break;
+                };
+                self.errors.push(ParseError::Syntax {
+                    message: "Only one '{else}' is allowed in an '{if}' block".to_string(),
+                    span: extra,
+                });
+            }
+        }
+
+        match ending {
+            TextEnd::EndIf(tag) => end = tag.end,
+            TextEnd::Else(_) => unreachable!("extra '{{else}}' tags are consumed above"),
+            TextEnd::Line => {
+                self.errors.push(ParseError::Syntax {
+                    message: "Unclosed '{if}' - expected '{endif}' before the end of the line"
+                        .to_string(),
+                    span: Span {
+                        start: open.start,
+                        end: keyword.span.end,
+                    },
+                });
+            }
+        }
+
+        TextPart::Conditional {
+            id: self.next_id(),
+            name,
+            name_span,
+            then_parts,
+            else_parts,
+            span: Span {
+                start: open.start,
+                end,
+            },
+        }
+    }
+
+    /// Skip an `{if}` block without parsing it, up to and including the
+    /// `{endif}` that closes it or to the end of the line. Returns where the
+    /// skipped text ends.
+    fn skip_conditional(&mut self) -> usize {
+        let mut open = 1;
+        let mut end = 0;
+        while let Some(Ok(token)) = self.tokens.peek() {
+            match token.kind {
+                kind if ends_line(kind) => break,
+                TokenKind::If => open += 1,
+                TokenKind::EndIf => open -= 1,
+                _ => {}
+            }
+            end = self.advance().span.end;
+            if open == 0 {
+                if self.check(TokenKind::CloseBrace) {
+                    end = self.advance().span.end;
+                }
+                break;
+            }
+        }
+        end
     }
 
-    fn choice_set(&mut self) -> Stmt {
+    /// Consume the `}` that closes `{if name}`, `{else}` or `{endif}`, reporting
+    /// it missing after `what` (at `span`). Returns where the tag ends.
+    fn close_text_tag(&mut self, what: &str, span: Span) -> usize {
+        if self.check(TokenKind::CloseBrace) {
+            return self.advance().span.end;
+        }
+        self.errors.push(ParseError::Syntax {
+            message: format!("Expected '}}' after {}", what),
+            span,
+        });
+        span.end
+    }
+
+    /// Parse a `shuffle` directive and the choice set it applies to.
+    fn shuffled_choice_set(&mut self) -> Stmt {
+        let directive = self.advance(); // Consume 'shuffle'
+
+        if self.check(TokenKind::NewLine) {
+            self.advance();
+        }
+
+        if !self.check(TokenKind::Choice) {
+            self.errors.push(ParseError::Syntax {
+                message: "Expected choices after 'shuffle'".to_string(),
+                span: directive.span,
+            });
+            return Stmt::ChoiceSet {
+                choices: Vec::new(),
+                shuffle: true,
+            };
+        }
+
+        self.choice_set(true)
+    }
+
+    fn choice_set(&mut self, shuffle: bool) -> Stmt {
         let mut choices = Vec::new();
 
         loop {
             // Consume the Choice token ("- ")
             let choice_token = self.advance();
             let start = choice_token.span.start;
+            let marker = ChoiceMarker::from_symbol(choice_token.lexeme)
+                .expect("scanner emits known markers");
 
-            // Parse the choice text (may contain interpolation)
-            let (parts, text_span) = self.parse_text_parts();
-            let end = if text_span.end > 0 {
-                text_span.end
+            // Optional `(label)` before the text
+            let mut text_start = choice_token.span.end;
+            let label = if self.check(TokenKind::ChoiceLabel) {
+                let token = self.advance();
+                text_start = token.span.end;
+                Some(token.lexeme[1..token.lexeme.len() - 1].to_string())
             } else {
-                choice_token.span.end
+                None
             };
 
-            // Expect newline after choice text
-            if !matches!(self.tokens.peek(), Some(Ok(t)) if t.kind == TokenKind::NewLine) {
+            // Parse the choice text (may contain interpolation)
+            let (mut parts, mut text_span) = self.parse_text_parts();
+            let tags = self.parse_choice_tags(&mut parts, &mut text_span);
+            if label.is_some() && parts.is_empty() && tags.is_empty() {
                 self.errors.push(ParseError::Syntax {
-                    message: "Expected newline after choice".to_string(),
-                    span: Span { start, end },
+                    message: "Expected choice text after its label".to_string(),
+                    span: Span {
+                        start: text_start,
+                        end: text_start,
+                    },
                 });
-                self.synchronize();
-                break;
             }
+            let end = if text_span.end > 0 {
+                text_span.end
+            } else {
+                text_start
+            };
+            // The scanner skips spaces after the marker and label, so the
+            // text span starts at the first visible character.
+            let text_span = if parts.is_empty() {
+                Span { start: end, end }
+            } else {
+                text_span
+            };
 
-            self.advance(); // Consume the NewLine
+            // Expect newline after choice text. A choice on the last line of the
+            // file has none: the scanner goes straight to closing dedents or EOF.
+            match self.tokens.peek() {
+                Some(Ok(t)) if t.kind == TokenKind::Tilde => {
+                    self.errors.push(ParseError::Syntax {
+                        message: "Effects ('~') are only allowed on lines, not choices".to_string(),
+                        span: t.span,
+                    });
+                    self.synchronize();
+                    break;
+                }
+                Some(Ok(t)) if t.kind == TokenKind::DebugNote => {
+                    self.errors.push(ParseError::Syntax {
+                        message: "Debug notes ('//!') are only allowed on lines, not choices"
+                            .to_string(),
+                        span: t.span,
+                    });
+                    self.synchronize();
+                    break;
+                }
+                Some(Ok(t)) if t.kind == TokenKind::NewLine => {
+                    self.advance(); // Consume the NewLine
+                }
+                Some(Ok(t)) if matches!(t.kind, TokenKind::Dedent | TokenKind::Eof) => {}
+                None => {}
+                _ => {
+                    self.errors.push(ParseError::Syntax {
+                        message: "Expected newline after choice".to_string(),
+                        span: Span { start, end },
+                    });
+                    self.synchronize();
+                    break;
+                }
+            }
 
             // Parse any nested content under this choice
             let nested = self.parse_nested_content();
 
             choices.push(Choice {
+                marker,
+                label,
                 parts,
                 span: Span { start, end },
+                text_span,
+                tags,
                 nested,
             });
 
@@ -424,7 +1178,7 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
                 break;
             }
         }
-        Stmt::ChoiceSet { choices }
+        Stmt::ChoiceSet { choices, shuffle }
     }
 
     /// Parse nested content under a choice (after Indent, before Dedent).
@@ -437,6 +1191,20 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
 
         self.advance(); // Consume the Indent
 
+        if self.depth == MAX_NESTING_DEPTH {
+            let span = self.current_span();
+            self.errors.push(ParseError::Syntax {
+                message: format!(
+                    "Choices are nested too deeply - at most {} levels of nesting are supported",
+                    MAX_NESTING_DEPTH
+                ),
+                span,
+            });
+            self.skip_block();
+            return Vec::new();
+        }
+        self.depth += 1;
+
         let mut statements = Vec::new();
 
         loop {
@@ -463,7 +1231,8 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
                         self.advance(); // Consume Dedent
                         break;
                     }
-                    TokenKind::NewLine | TokenKind::Indent => {
+                    TokenKind::Indent => self.unexpected_indent(),
+                    TokenKind::NewLine => {
                         self.advance();
                     }
                     TokenKind::Eof => break,
@@ -476,9 +1245,79 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
             }
         }
 
+        self.depth -= 1;
         statements
     }
 
+    /// Skip the rest of a block whose Indent was just consumed, including any
+    /// blocks nested in it, without recursing.
+    fn skip_block(&mut self) {
+        let mut open = 1;
+        while open > 0 && !self.check(TokenKind::Eof) {
+            match self.tokens.next() {
+                None => return,
+                Some(Err(e)) => self.errors.push(e.into()),
+                Some(Ok(token)) => match token.kind {
+                    TokenKind::Indent => open += 1,
+                    TokenKind::Dedent => open -= 1,
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    /// Parse an optional format spec after an interpolated variable. Only a
+    /// literal precision (`:.2`) is supported.
+    fn format_precision(&mut self) -> Option<usize> {
+        if !self.check(TokenKind::FormatSpec) {
+            return None;
+        }
+        let spec = self.advance();
+        let precision = spec
+            .lexeme
+            .strip_prefix('.')
+            .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse().ok());
+        if precision.is_none() {
+            let message = if spec.lexeme.contains('{') {
+                "Format specs can't contain variables - write the precision as a number, like ':.2'"
+                    .to_string()
+            } else {
+                format!(
+                    "Unsupported format spec ':{}' - only a precision like ':.2' is supported",
+                    spec.lexeme
+                )
+            };
+            self.errors.push(ParseError::Syntax {
+                message,
+                span: spec.span,
+            });
+        } else if let Some(places) = precision.filter(|&places| places > MAX_PRECISION) {
+            self.errors.push(ParseError::Syntax {
+                message: format!(
+                    "Precision ':{}' asks for {} decimal places - at most {} are supported",
+                    spec.lexeme, places, MAX_PRECISION
+                ),
+                span: spec.span,
+            });
+            return None;
+        }
+        precision
+    }
+
+    /// Report a line in a choice body indented deeper than the one above it
+    /// when that line isn't a choice. The over-indented lines are parsed as a block and dropped, so
+    /// the block's closing dedent doesn't end the enclosing choice early.
+    fn unexpected_indent(&mut self) {
+        let span = self.current_span();
+        self.errors.push(ParseError::Syntax {
+            message: "Unexpected indentation - only the content under a choice is indented"
+                .to_string(),
+            span,
+        });
+        self.parse_nested_content();
+    }
+
     fn synchronize(&mut self) {
         loop {
             match self.tokens.peek() {
@@ -503,6 +1342,73 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, LexicalError>>> Parser<'a, I> {
     }
 }
 
+/// How a run of text parts ended, as returned by `parse_text_run`.
+enum TextEnd {
+    /// End of the line, or a token that can't be part of text
+    Line,
+    /// `{else}`, with the span of the whole tag
+    Else(Span),
+    /// `{endif}`, with the span of the whole tag
+    EndIf(Span),
+}
+
+/// Drop spaces at the end of the text before a trailing `~` or `//!`.
+fn trim_trailing_spaces(parts: &mut Vec<TextPart>, span: &mut Span) {
+    if let Some(TextPart::Literal {
+        text,
+        span: part_span,
+    }) = parts.last_mut()
+    {
+        let trimmed = text.trim_end_matches(' ').len();
+        part_span.end -= text.len() - trimmed;
+        span.end = part_span.end;
+        text.truncate(trimmed);
+        if text.is_empty() {
+            parts.pop();
+        }
+    }
+}
+
+/// The arithmetic operator a compound assignment token applies, if it is one.
+fn compound_op(kind: TokenKind) -> Option<ArithOp> {
+    match kind {
+        TokenKind::PlusEqual => Some(ArithOp::Add),
+        TokenKind::MinusEqual => Some(ArithOp::Subtract),
+        TokenKind::StarEqual => Some(ArithOp::Multiply),
+        TokenKind::SlashEqual => Some(ArithOp::Divide),
+        _ => None,
+    }
+}
+
+/// Whether a token starts a literal value.
+fn is_literal(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::String | TokenKind::Number | TokenKind::True | TokenKind::False
+    )
+}
+
+/// Whether a token means nothing else is on the line.
+fn ends_line(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::NewLine | TokenKind::Eof | TokenKind::Indent | TokenKind::Dedent
+    )
+}
+
+/// The comparison operator a token spells, if it is one.
+fn compare_op(kind: TokenKind) -> Option<CompareOp> {
+    match kind {
+        TokenKind::EqualEqual => Some(CompareOp::Equal),
+        TokenKind::BangEqual => Some(CompareOp::NotEqual),
+        TokenKind::Less => Some(CompareOp::Less),
+        TokenKind::LessEqual => Some(CompareOp::LessEqual),
+        TokenKind::Greater => Some(CompareOp::Greater),
+        TokenKind::GreaterEqual => Some(CompareOp::GreaterEqual),
+        _ => None,
+    }
+}
+
 /// Unescape a string literal (handle \n, \t, \", \\)
 fn unescape_string(s: &str) -> String {
     let mut result = String::with_capacity(s.len());
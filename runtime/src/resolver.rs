@@ -1,8 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::ast::{Choice, ExternDeclData, NodeId, Script, Stmt, TextPart, VarBindingData};
-use crate::diagnostic::{Diagnostic, DiagnosticContext, IntoDiagnostic};
-use crate::token::Span;
+use crate::ast::{
+    Choice, Expr, ExternDeclData, Literal, NodeId, Script, Stmt, TextPart, ValueType,
+    VarBindingData,
+};
+use crate::chunk::Value;
+use crate::compiler::literal_value;
+use crate::diagnostic::{Diagnostic, DiagnosticContext, IntoDiagnostic, Severity};
+use crate::emitter::render_literal;
+use crate::token::{self, Span};
 
 #[derive(Debug, Clone)]
 pub enum SemanticError {
@@ -18,6 +24,64 @@ pub enum SemanticError {
     AssignmentToExtern {
         name: String,
         span: Span,
+        /// The `extern` declaration, when it is in the same file, and the
+        /// value a `save` replacing it would start with
+        declared: Option<(Span, Literal)>,
+    },
+    /// Two choices in the same set share a `(label)`
+    DuplicateChoiceLabel {
+        label: String,
+        span: Span,
+        original: Span,
+    },
+    /// A declaration clashes with one in the prelude (`globals.bobbin`)
+    ConflictsWithPrelude {
+        name: String,
+        span: Span,
+    },
+    /// The prelude may only contain `save` and `extern` declarations
+    NotAllowedInPrelude {
+        span: Span,
+    },
+    /// A declaration uses a keyword or reserved word as its name
+    ReservedName {
+        name: String,
+        span: Span,
+    },
+    /// `namespace` anywhere but the first statement of the file
+    MisplacedNamespace {
+        span: Span,
+    },
+    /// `set` on a `const`, or on a constant given to the runtime at creation
+    AssignmentToConstant {
+        name: String,
+        span: Span,
+        /// The `const` declaration, when the constant is declared in the script
+        declared: Option<Span>,
+    },
+    /// A declaration reuses the name of a runtime constant
+    ConflictsWithConstant {
+        name: String,
+        span: Span,
+    },
+    /// A choice set has more choices than the configured limit
+    TooManyChoices {
+        count: usize,
+        limit: usize,
+        /// The first choice over the limit
+        span: Span,
+    },
+    /// An `extern` default doesn't have the declared type
+    ExternDefaultType {
+        name: String,
+        expected: ValueType,
+        found: ValueType,
+        span: Span,
+    },
+    /// The host gave an initial value for a temp the script doesn't declare
+    /// at the top level. Comes from the host, so it has no span.
+    UnknownInitialTemp {
+        name: String,
     },
 }
 
@@ -53,15 +117,348 @@ impl IntoDiagnostic for SemanticError {
             )
             .with_secondary(original, "previously declared here")
             .with_note("Bobbin does not allow shadowing to prevent confusion in dialogue scripts"),
-            SemanticError::AssignmentToExtern { name, span } => Diagnostic::error(
-                format!("cannot assign to extern variable '{}'", name),
+            SemanticError::AssignmentToExtern {
+                name,
+                span,
+                declared,
+            } => {
+                let diag = Diagnostic::error(
+                    format!("cannot assign to extern variable '{}'", name),
+                    span,
+                    "extern variables are read-only",
+                )
+                .with_note(
+                    "Extern variables are provided by the host game and cannot be modified by scripts",
+                );
+
+                match declared {
+                    Some((declared, value)) => {
+                        let replacement = format!("save {} = {}", name, render_literal(&value));
+                        diag.with_secondary(declared, "declared as extern here")
+                            .with_suggestion(
+                                format!(
+                                    "if the script owns this value, declare it with 'save' instead: {}",
+                                    replacement
+                                ),
+                                declared,
+                                replacement,
+                            )
+                    }
+                    None => {
+                        diag.with_note("Use 'save' or 'temp' to declare a mutable variable instead")
+                    }
+                }
+            }
+            SemanticError::DuplicateChoiceLabel {
+                label,
+                span,
+                original,
+            } => Diagnostic::error(
+                format!("choice label '{}' is used twice in this choice set", label),
+                span,
+                "duplicate label",
+            )
+            .with_secondary(original, "first used here")
+            .with_note("Labels identify a choice in history and save data, so each must be unique"),
+            SemanticError::ConflictsWithPrelude { name, span } => Diagnostic::error(
+                format!("variable '{}' is already declared in the prelude", name),
+                span,
+                "conflicts with a prelude declaration",
+            )
+            .with_note("Prelude variables are available in every file without redeclaring them")
+            .with_note("Only 'extern' may be repeated, and only as another 'extern'"),
+            SemanticError::NotAllowedInPrelude { span } => Diagnostic::error(
+                "only 'save' and 'extern' declarations are allowed in the prelude",
+                span,
+                "not a declaration",
+            )
+            .with_note("The prelude declares shared variables; put dialogue in a regular file"),
+            SemanticError::ReservedName { name, span } => Diagnostic::error(
+                format!(
+                    "'{}' is a reserved word and can't be used as a variable name",
+                    name
+                ),
+                span,
+                "reserved word",
+            )
+            .with_note("Keywords like 'temp', 'set', 'true' and 'if' are reserved"),
+            SemanticError::MisplacedNamespace { span } => Diagnostic::error(
+                "'namespace' must be the first statement in the file",
                 span,
-                "extern variables are read-only",
+                "not at the top of the file",
             )
-            .with_note(
-                "Extern variables are provided by the host game and cannot be modified by scripts",
+            .with_note("A file has one namespace, and it applies to every 'save' variable in it"),
+            SemanticError::AssignmentToConstant {
+                name,
+                span,
+                declared,
+            } => {
+                let diag = Diagnostic::error(
+                    format!("cannot assign to constant '{}'", name),
+                    span,
+                    "constants are read-only",
+                );
+                match declared {
+                    Some(declared) => diag
+                        .with_secondary(declared, "declared as const here")
+                        .with_suggestion(
+                            "if the value needs to change, declare it with 'temp' or 'save' instead",
+                            Span {
+                                start: declared.start,
+                                end: declared.start + "const".len(),
+                            },
+                            "temp",
+                        ),
+                    None => {
+                        diag.with_note("Constants are fixed by the game when the conversation starts")
+                    }
+                }
+            }
+            SemanticError::ConflictsWithConstant { name, span } => Diagnostic::error(
+                format!(
+                    "variable '{}' has the same name as a runtime constant",
+                    name
+                ),
+                span,
+                "conflicts with a constant",
             )
-            .with_note("Use 'save' or 'temp' to declare a mutable variable instead"),
+            .with_note("Constants are available in every file without declaring them"),
+            SemanticError::TooManyChoices { count, limit, span } => Diagnostic::error(
+                format!(
+                    "choice set has {} choices, more than the limit of {}",
+                    count, limit
+                ),
+                span,
+                "over the limit from here",
+            )
+            .with_note("Split the set, or raise `RuntimeOptions::max_choices` if it is intended"),
+            SemanticError::ExternDefaultType {
+                name,
+                expected,
+                found,
+                span,
+            } => Diagnostic::error(
+                format!(
+                    "default of extern '{}' is a {}, but it is declared as {}",
+                    name,
+                    found.name(),
+                    expected.name()
+                ),
+                span,
+                format!("expected a {}", expected.name()),
+            ),
+            SemanticError::UnknownInitialTemp { name } => {
+                let mut notes = vec![
+                    "Only top-level 'temp' declarations can be given an initial value".to_string(),
+                ];
+                if let Some(similar) = ctx.find_similar_variable(&name) {
+                    notes.push(format!("did you mean '{}'?", similar));
+                }
+                Diagnostic {
+                    severity: Severity::Error,
+                    message: format!("initial value given for unknown temp '{}'", name),
+                    labels: vec![],
+                    notes,
+                    suggestions: vec![],
+                }
+            }
+        }
+    }
+}
+
+/// A sensible value for [`RuntimeOptions::max_choices`](crate::RuntimeOptions::max_choices),
+/// which is off unless a host sets it.
+pub const DEFAULT_MAX_CHOICES: usize = 32;
+
+/// A static check that produces warnings rather than errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// Two choices in the same set have identical text.
+    DuplicateChoice,
+    /// A line interpolates a variable declared with a boolean value.
+    BoolInterpolation,
+    /// Statements follow an `end` in the same block.
+    Unreachable,
+    /// A line is a single interpolation with no text around it.
+    BareInterpolation,
+    /// A line or choice is longer than the configured character budget.
+    TextLength,
+}
+
+impl Lint {
+    /// Stable name for configuration and display.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::DuplicateChoice => "duplicate_choice",
+            Lint::BoolInterpolation => "bool_interpolation",
+            Lint::Unreachable => "unreachable",
+            Lint::BareInterpolation => "bare_interpolation",
+            Lint::TextLength => "text_length",
+        }
+    }
+}
+
+/// Which lints the resolver runs. `Default` enables the default-on lints.
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    enabled: HashSet<Lint>,
+    max_line_length: Option<usize>,
+    max_choice_length: Option<usize>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            enabled: HashSet::from([Lint::DuplicateChoice, Lint::Unreachable]),
+            max_line_length: None,
+            max_choice_length: None,
+        }
+    }
+}
+
+impl LintConfig {
+    /// A config with every lint turned off.
+    pub fn none() -> Self {
+        Self {
+            enabled: HashSet::new(),
+            max_line_length: None,
+            max_choice_length: None,
+        }
+    }
+
+    /// Warn about lines with more than `limit` characters of text. Enables
+    /// [`Lint::TextLength`].
+    pub fn max_line_length(mut self, limit: usize) -> Self {
+        self.max_line_length = Some(limit);
+        self.enable(Lint::TextLength)
+    }
+
+    /// Warn about choices with more than `limit` characters of text. Enables
+    /// [`Lint::TextLength`].
+    pub fn max_choice_length(mut self, limit: usize) -> Self {
+        self.max_choice_length = Some(limit);
+        self.enable(Lint::TextLength)
+    }
+
+    pub fn enable(mut self, lint: Lint) -> Self {
+        self.enabled.insert(lint);
+        self
+    }
+
+    pub fn disable(mut self, lint: Lint) -> Self {
+        self.enabled.remove(&lint);
+        self
+    }
+
+    pub fn is_enabled(&self, lint: Lint) -> bool {
+        self.enabled.contains(&lint)
+    }
+}
+
+/// A problem found during semantic analysis that doesn't prevent the script from running.
+#[derive(Debug, Clone)]
+pub enum SemanticWarning {
+    DuplicateChoice {
+        text: String,
+        span: Span,
+        original: Span,
+    },
+    BoolInterpolation {
+        name: String,
+        span: Span,
+        declared: Span,
+    },
+    Unreachable {
+        /// The first statement after the `end`
+        span: Span,
+        end: Span,
+    },
+    BareInterpolation {
+        name: String,
+        span: Span,
+    },
+    TextLength {
+        /// Whether the text is a choice rather than a line
+        choice: bool,
+        length: usize,
+        limit: usize,
+        span: Span,
+    },
+}
+
+impl SemanticWarning {
+    /// The lint that produced this warning.
+    pub fn lint(&self) -> Lint {
+        match self {
+            SemanticWarning::DuplicateChoice { .. } => Lint::DuplicateChoice,
+            SemanticWarning::BoolInterpolation { .. } => Lint::BoolInterpolation,
+            SemanticWarning::Unreachable { .. } => Lint::Unreachable,
+            SemanticWarning::BareInterpolation { .. } => Lint::BareInterpolation,
+            SemanticWarning::TextLength { .. } => Lint::TextLength,
+        }
+    }
+}
+
+impl IntoDiagnostic for SemanticWarning {
+    fn into_diagnostic(self, _ctx: &DiagnosticContext) -> Diagnostic {
+        let lint = self.lint().name();
+        match self {
+            SemanticWarning::DuplicateChoice {
+                text,
+                span,
+                original,
+            } => Diagnostic::warning(
+                format!("duplicate choice '{}'", text),
+                span,
+                "same text as an earlier choice",
+            )
+            .with_secondary(original, "first used here")
+            .with_note("Players cannot tell identical choices apart")
+            .with_note(format!("`{}` is on by default", lint)),
+            SemanticWarning::BoolInterpolation {
+                name,
+                span,
+                declared,
+            } => Diagnostic::warning(
+                format!("'{}' is a boolean and will display as 'true' or 'false'", name),
+                span,
+                "interpolated here",
+            )
+            .with_secondary(declared, "declared with a boolean value here")
+            .with_note("To show words instead, keep them in a string variable (ternaries like {flag ? \"yes\" : \"no\"} are planned)")
+            .with_note(format!("`{}` is off by default", lint)),
+            SemanticWarning::Unreachable { span, end } => Diagnostic::warning(
+                "unreachable statement",
+                span,
+                "never runs",
+            )
+            .with_secondary(end, "the conversation ends here")
+            .with_note("Nothing after 'end' in the same block runs; move it above or into another branch")
+            .with_note(format!("`{}` is on by default", lint)),
+            SemanticWarning::BareInterpolation { name, span } => Diagnostic::warning(
+                format!("line is only '{{{}}}'", name),
+                span,
+                "no text around the variable",
+            )
+            .with_note("The line shows the value alone; check the rest of the line wasn't left out")
+            .with_note(format!("`{}` is off by default", lint)),
+            SemanticWarning::TextLength {
+                choice,
+                length,
+                limit,
+                span,
+            } => Diagnostic::warning(
+                format!(
+                    "{} is {} characters, over the limit of {}",
+                    if choice { "choice" } else { "line" },
+                    length,
+                    limit
+                ),
+                span,
+                "too long",
+            )
+            .with_note("Interpolated values aren't counted, so the displayed text may be longer still")
+            .with_note(format!("`{}` is off by default", lint)),
         }
     }
 }
@@ -76,6 +473,19 @@ pub struct SymbolTable {
     pub save_bindings: HashMap<NodeId, String>,
     /// Extern variable bindings: NodeId -> variable name
     pub extern_bindings: HashMap<NodeId, String>,
+    /// Constant bindings: NodeId -> the constant's value
+    pub constant_bindings: HashMap<NodeId, Value>,
+    /// Top-level temp declarations whose value the host overrides: NodeId -> value
+    pub initial_temps: HashMap<NodeId, Value>,
+    /// Warnings from enabled lints
+    pub warnings: Vec<SemanticWarning>,
+}
+
+/// Kind of a variable declared in the prelude
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PreludeVar {
+    Save,
+    Extern,
 }
 
 /// Information about a declared temp variable
@@ -83,18 +493,34 @@ pub struct SymbolTable {
 struct VarInfo {
     slot: usize,
     span: Span, // for error messages
+    /// Declared with `true` or `false` (for the `bool_interpolation` lint)
+    is_bool: bool,
 }
 
 /// Information about a declared save variable
 #[derive(Debug)]
 struct SaveVarInfo {
     span: Span, // for error messages (no slot - uses external storage)
+    /// Declared with `true` or `false` (for the `bool_interpolation` lint)
+    is_bool: bool,
+}
+
+/// Information about a declared constant
+#[derive(Debug)]
+struct ConstVarInfo {
+    span: Span, // for error messages (no slot - the value is inlined)
+    value: Literal,
 }
 
 /// Information about a declared extern variable
 #[derive(Debug)]
 struct ExternVarInfo {
     span: Span, // for error messages (no slot - uses host state)
+    /// Starting value suggested when a script assigns to it: the default,
+    /// or a placeholder of the declared type
+    save_value: Literal,
+    /// From `extern name: type`; the host's value is checked against it at runtime
+    ty: Option<ValueType>,
 }
 
 /// A lexical scope containing variable declarations
@@ -110,18 +536,36 @@ pub struct Resolver<'a> {
     ast: &'a Script,
     /// Temp variable scopes (block-scoped)
     scopes: Vec<Scope>,
+    /// Variables declared in the prelude (shared across files)
+    prelude_vars: HashMap<String, PreludeVar>,
+    /// Read-only values fixed when the runtime is created
+    constants: HashMap<String, Value>,
+    /// Host-supplied values replacing the declared value of top-level temps
+    initial_temp_values: HashMap<String, Value>,
     /// Save variables (file-global)
     save_vars: HashMap<String, SaveVarInfo>,
+    /// Constants declared with `const` (file-global, read-only)
+    const_vars: HashMap<String, ConstVarInfo>,
     /// Extern variables (file-global, read-only)
     extern_vars: HashMap<String, ExternVarInfo>,
     next_slot: usize,
     /// Temp variable bindings: NodeId -> slot
     bindings: HashMap<NodeId, usize>,
-    /// Save variable bindings: NodeId -> name
+    /// Save variable bindings: NodeId -> storage key
     save_bindings: HashMap<NodeId, String>,
     /// Extern variable bindings: NodeId -> name
     extern_bindings: HashMap<NodeId, String>,
+    /// Constant bindings: NodeId -> value
+    constant_bindings: HashMap<NodeId, Value>,
+    /// Overridden temp declarations: NodeId -> value
+    initial_temps: HashMap<NodeId, Value>,
+    /// From a leading `namespace` directive; prefixes the file's save variables in storage
+    namespace: Option<String>,
+    /// Most choices a single choice set may have, if limited
+    max_choices: Option<usize>,
     errors: Vec<SemanticError>,
+    lints: LintConfig,
+    warnings: Vec<SemanticWarning>,
 }
 
 impl<'a> Resolver<'a> {
@@ -132,27 +576,126 @@ impl<'a> Resolver<'a> {
                 variables: HashMap::new(),
                 start_slot: 0,
             }], // Start with global scope
+            prelude_vars: HashMap::new(),
+            constants: HashMap::new(),
+            initial_temp_values: HashMap::new(),
             save_vars: HashMap::new(),
+            const_vars: HashMap::new(),
             extern_vars: HashMap::new(),
             next_slot: 0,
             bindings: HashMap::new(),
             save_bindings: HashMap::new(),
             extern_bindings: HashMap::new(),
+            constant_bindings: HashMap::new(),
+            initial_temps: HashMap::new(),
+            namespace: None,
+            max_choices: None,
             errors: Vec::new(),
+            lints: LintConfig::default(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Choose which lints to run (default: [`LintConfig::default`]).
+    pub fn with_lints(mut self, lints: LintConfig) -> Self {
+        self.lints = lints;
+        self
+    }
+
+    /// Reject choice sets with more than `limit` choices (default: no limit).
+    pub fn with_max_choices(mut self, limit: usize) -> Self {
+        self.max_choices = Some(limit);
+        self
+    }
+
+    /// Make the `save` and `extern` declarations of a prelude visible to this script.
+    ///
+    /// The prelude itself should already have passed [`Resolver::analyze_prelude`].
+    pub fn with_prelude(mut self, prelude: &Script) -> Self {
+        for stmt in &prelude.statements {
+            match stmt {
+                Stmt::SaveDecl(data) => {
+                    self.prelude_vars
+                        .insert(data.name.clone(), PreludeVar::Save);
+                }
+                Stmt::ExternDecl(data) => {
+                    self.prelude_vars
+                        .insert(data.name.clone(), PreludeVar::Extern);
+                }
+                _ => {}
+            }
+        }
+        self
+    }
+
+    /// Make constants visible to this script. References read the value given
+    /// here, and assigning to one is an error.
+    pub fn with_constants<'c>(
+        mut self,
+        constants: impl IntoIterator<Item = (&'c String, &'c Value)>,
+    ) -> Self {
+        for (name, value) in constants {
+            self.constants.insert(name.clone(), value.clone());
+        }
+        self
+    }
+
+    /// Start top-level temps with these values instead of their declared ones.
+    /// Names that match no top-level `temp` are ignored.
+    pub fn with_initial_temps<'t>(
+        mut self,
+        values: impl IntoIterator<Item = (&'t String, &'t Value)>,
+    ) -> Self {
+        for (name, value) in values {
+            self.initial_temp_values.insert(name.clone(), value.clone());
         }
+        self
+    }
+
+    /// Analyze a prelude: like [`Resolver::analyze`], but only declarations are allowed.
+    pub fn analyze_prelude(mut self) -> Result<SymbolTable, (Vec<SemanticError>, Vec<String>)> {
+        for stmt in &self.ast.statements {
+            if !matches!(stmt, Stmt::SaveDecl(_) | Stmt::ExternDecl(_)) {
+                self.errors
+                    .push(SemanticError::NotAllowedInPrelude { span: stmt.span() });
+            }
+        }
+        self.analyze()
     }
 
     pub fn analyze(mut self) -> Result<SymbolTable, (Vec<SemanticError>, Vec<String>)> {
+        let mut statements = self.ast.statements.iter().peekable();
+        if let Some(Stmt::Namespace { name, .. }) = statements.peek() {
+            self.namespace = Some(name.clone());
+            statements.next();
+        }
+
         // Walk the AST
-        for stmt in &self.ast.statements {
+        for stmt in statements {
             self.resolve_stmt(stmt);
         }
+        self.check_unreachable(&self.ast.statements);
+
+        // Declaring a top-level temp takes its initial value; any left over
+        // matched nothing
+        let mut unknown: Vec<String> = self
+            .initial_temp_values
+            .drain()
+            .map(|(name, _)| name)
+            .collect();
+        unknown.sort();
+        for name in unknown {
+            self.errors.push(SemanticError::UnknownInitialTemp { name });
+        }
 
         if self.errors.is_empty() {
             Ok(SymbolTable {
                 bindings: self.bindings,
                 save_bindings: self.save_bindings,
                 extern_bindings: self.extern_bindings,
+                constant_bindings: self.constant_bindings,
+                initial_temps: self.initial_temps,
+                warnings: self.warnings,
             })
         } else {
             let known_vars = self.known_variables();
@@ -172,34 +715,105 @@ impl<'a> Resolver<'a> {
         // Collect save variables
         vars.extend(self.save_vars.keys().cloned());
 
+        // Collect script constants
+        vars.extend(self.const_vars.keys().cloned());
+
         // Collect extern variables
         vars.extend(self.extern_vars.keys().cloned());
 
+        // Collect prelude variables
+        vars.extend(self.prelude_vars.keys().cloned());
+
+        // Collect constants
+        vars.extend(self.constants.keys().cloned());
+
         vars
     }
 
     fn resolve_stmt(&mut self, stmt: &Stmt) {
         match stmt {
-            Stmt::TempDecl(VarBindingData { id, name, span, .. }) => {
-                self.declare_temp(*id, name, *span);
+            Stmt::TempDecl(VarBindingData {
+                id,
+                name,
+                value,
+                span,
+                ..
+            }) => {
+                self.declare_temp(*id, name, value, *span);
             }
-            Stmt::SaveDecl(VarBindingData { id, name, span, .. }) => {
-                self.declare_save(*id, name, *span);
+            Stmt::SaveDecl(VarBindingData {
+                id,
+                name,
+                value,
+                span,
+                ..
+            }) => {
+                self.declare_save(*id, name, value, *span);
             }
-            Stmt::ExternDecl(ExternDeclData { id, name, span }) => {
-                self.declare_extern(*id, name, *span);
+            Stmt::ConstDecl(VarBindingData {
+                name, value, span, ..
+            }) => {
+                self.declare_const(name, value, *span);
             }
+            Stmt::ExternDecl(data) => self.declare_extern(data),
             Stmt::Assignment(VarBindingData { id, name, span, .. }) => {
                 self.resolve_reference(*id, name, *span, true); // for_write = true
             }
-            Stmt::Line { parts, .. } => {
+            Stmt::Line {
+                parts,
+                effect,
+                span: line_span,
+                ..
+            } => {
                 self.resolve_text_parts(parts);
+                if self.lints.is_enabled(Lint::BoolInterpolation) {
+                    self.check_bool_interpolation(parts);
+                }
+                if self.lints.is_enabled(Lint::BareInterpolation)
+                    && let [TextPart::VarRef { name, span, .. }] = parts.as_slice()
+                {
+                    self.warnings.push(SemanticWarning::BareInterpolation {
+                        name: name.clone(),
+                        span: *span,
+                    });
+                }
+                if let Some(VarBindingData { id, name, span, .. }) = effect {
+                    self.resolve_reference(*id, name, *span, true); // for_write = true
+                }
+                if let Some(limit) = self.lints.max_line_length {
+                    self.check_text_length(parts, limit, false, *line_span);
+                }
+            }
+            Stmt::DebugPrint { .. } | Stmt::End { .. } => {}
+            // A leading namespace is taken by `analyze` and never gets here
+            Stmt::Namespace { span, .. } => {
+                self.errors
+                    .push(SemanticError::MisplacedNamespace { span: *span });
             }
-            Stmt::ChoiceSet { choices } => {
+            Stmt::Assert { condition, .. } => self.resolve_expr(condition),
+            Stmt::ChoiceSet { choices, .. } => {
                 // Resolve variable references in choice text
                 for choice in choices {
                     self.resolve_text_parts(&choice.parts);
                 }
+                if let Some(limit) = self.max_choices
+                    && let Some(extra) = choices.get(limit)
+                {
+                    self.errors.push(SemanticError::TooManyChoices {
+                        count: choices.len(),
+                        limit,
+                        span: extra.span,
+                    });
+                }
+                self.check_choice_labels(choices);
+                if self.lints.is_enabled(Lint::DuplicateChoice) {
+                    self.check_duplicate_choices(choices);
+                }
+                if let Some(limit) = self.lints.max_choice_length {
+                    for choice in choices {
+                        self.check_text_length(&choice.parts, limit, true, choice.text_span);
+                    }
+                }
                 // Each choice branch gets its own scope
                 for choice in choices {
                     self.resolve_choice_branch(choice);
@@ -208,18 +822,166 @@ impl<'a> Resolver<'a> {
         }
     }
 
+    /// Warn about choices whose text is identical to an earlier one in the set.
+    /// Choices with interpolation are skipped since their text may differ at runtime.
+    fn check_duplicate_choices(&mut self, choices: &[Choice]) {
+        let mut seen: HashMap<String, Span> = HashMap::new();
+        for choice in choices {
+            let Some(text) = literal_text(&choice.parts) else {
+                continue;
+            };
+            if let Some(&original) = seen.get(&text) {
+                self.warnings.push(SemanticWarning::DuplicateChoice {
+                    text,
+                    span: choice.text_span,
+                    original,
+                });
+            } else {
+                seen.insert(text, choice.text_span);
+            }
+        }
+    }
+
+    /// Warn about text longer than `limit` characters.
+    fn check_text_length(&mut self, parts: &[TextPart], limit: usize, choice: bool, span: Span) {
+        if !self.lints.is_enabled(Lint::TextLength) {
+            return;
+        }
+        let length = literal_length(parts);
+        if length > limit {
+            self.warnings.push(SemanticWarning::TextLength {
+                choice,
+                length,
+                limit,
+                span,
+            });
+        }
+    }
+
+    /// Warn about `{var}` where `var` was declared with a boolean literal.
+    /// A heuristic: a later `set` may store another type, and only externs
+    /// declared `: bool` are known.
+    /// Testing a boolean with `{if var}` is fine; only displayed values are flagged.
+    fn check_bool_interpolation(&mut self, parts: &[TextPart]) {
+        for part in parts {
+            match part {
+                TextPart::Literal { .. } => {}
+                TextPart::VarRef { name, span, .. } => {
+                    if let Some(declared) = self.bool_declaration(name) {
+                        self.warnings.push(SemanticWarning::BoolInterpolation {
+                            name: name.clone(),
+                            span: *span,
+                            declared,
+                        });
+                    }
+                }
+                TextPart::Conditional {
+                    then_parts,
+                    else_parts,
+                    ..
+                } => {
+                    self.check_bool_interpolation(then_parts);
+                    self.check_bool_interpolation(else_parts);
+                }
+            }
+        }
+    }
+
+    /// The declaration span of the variable `name` refers to, if it was declared as a boolean.
+    fn bool_declaration(&self, name: &str) -> Option<Span> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(info) = scope.variables.get(name) {
+                return info.is_bool.then_some(info.span);
+            }
+        }
+        if let Some(info) = self.save_vars.get(name) {
+            return info.is_bool.then_some(info.span);
+        }
+        if let Some(info) = self.const_vars.get(name) {
+            return matches!(info.value, Literal::Bool(_)).then_some(info.span);
+        }
+        let info = self.extern_vars.get(name)?;
+        (info.ty == Some(ValueType::Bool)).then_some(info.span)
+    }
+
+    fn check_choice_labels(&mut self, choices: &[Choice]) {
+        let mut seen: HashMap<&str, Span> = HashMap::new();
+        for choice in choices {
+            let Some(label) = &choice.label else {
+                continue;
+            };
+            if let Some(&original) = seen.get(label.as_str()) {
+                self.errors.push(SemanticError::DuplicateChoiceLabel {
+                    label: label.clone(),
+                    span: choice.span,
+                    original,
+                });
+            } else {
+                seen.insert(label, choice.span);
+            }
+        }
+    }
+
     fn resolve_choice_branch(&mut self, choice: &Choice) {
         self.push_scope();
         for stmt in &choice.nested {
             self.resolve_stmt(stmt);
         }
         self.pop_scope();
+        self.check_unreachable(&choice.nested);
+    }
+
+    /// Warn about the first statement after an `end` in a block.
+    fn check_unreachable(&mut self, statements: &[Stmt]) {
+        if !self.lints.is_enabled(Lint::Unreachable) {
+            return;
+        }
+        let Some(index) = statements
+            .iter()
+            .position(|stmt| matches!(stmt, Stmt::End { .. }))
+        else {
+            return;
+        };
+        if let Some(next) = statements.get(index + 1) {
+            self.warnings.push(SemanticWarning::Unreachable {
+                span: next.span(),
+                end: statements[index].span(),
+            });
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { .. } => {}
+            Expr::Var { id, name, span } => {
+                self.resolve_reference(*id, name, *span, false); // for_write = false
+            }
+            Expr::Compare { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+        }
     }
 
     fn resolve_text_parts(&mut self, parts: &[TextPart]) {
         for part in parts {
-            if let TextPart::VarRef { id, name, span } = part {
-                self.resolve_reference(*id, name, *span, false); // for_write = false
+            match part {
+                TextPart::Literal { .. } => {}
+                TextPart::VarRef { id, name, span, .. } => {
+                    self.resolve_reference(*id, name, *span, false); // for_write = false
+                }
+                TextPart::Conditional {
+                    id,
+                    name,
+                    name_span,
+                    then_parts,
+                    else_parts,
+                    ..
+                } => {
+                    self.resolve_reference(*id, name, *name_span, false);
+                    self.resolve_text_parts(then_parts);
+                    self.resolve_text_parts(else_parts);
+                }
             }
         }
     }
@@ -238,18 +1000,61 @@ impl<'a> Resolver<'a> {
         }
     }
 
-    /// Check if a variable name conflicts with save or extern variables.
+    /// Check if a variable name conflicts with save, const or extern variables.
     /// Returns the span of the conflicting declaration, if any.
     fn find_global_conflict(&self, name: &str) -> Option<Span> {
         if let Some(info) = self.save_vars.get(name) {
             return Some(info.span);
         }
+        if let Some(info) = self.const_vars.get(name) {
+            return Some(info.span);
+        }
         if let Some(info) = self.extern_vars.get(name) {
             return Some(info.span);
         }
         None
     }
 
+    /// Report a declaration named after a reserved word. Returns true if reported.
+    fn check_reserved_name(&mut self, name: &str, span: Span) -> bool {
+        if !token::is_reserved(name) {
+            return false;
+        }
+        self.errors.push(SemanticError::ReservedName {
+            name: name.to_string(),
+            span,
+        });
+        true
+    }
+
+    /// Report a declaration that clashes with the prelude. Returns true if reported.
+    /// Redeclaring a prelude `extern` as `extern` is allowed (idempotent).
+    fn check_prelude_conflict(&mut self, name: &str, span: Span, is_extern: bool) -> bool {
+        match self.prelude_vars.get(name) {
+            None => false,
+            Some(PreludeVar::Extern) if is_extern => false,
+            Some(_) => {
+                self.errors.push(SemanticError::ConflictsWithPrelude {
+                    name: name.to_string(),
+                    span,
+                });
+                true
+            }
+        }
+    }
+
+    /// Report a declaration named like a runtime constant. Returns true if it is.
+    fn check_constant_conflict(&mut self, name: &str, span: Span) -> bool {
+        if !self.constants.contains_key(name) {
+            return false;
+        }
+        self.errors.push(SemanticError::ConflictsWithConstant {
+            name: name.to_string(),
+            span,
+        });
+        true
+    }
+
     /// Check if a variable name conflicts with any temp variable in the given scopes.
     /// Returns the span of the conflicting declaration, if any.
     fn find_temp_conflict<'b>(
@@ -266,7 +1071,16 @@ impl<'a> Resolver<'a> {
     }
 
     /// Declare a temp variable in the current (innermost) scope
-    fn declare_temp(&mut self, id: NodeId, name: &str, span: Span) {
+    fn declare_temp(&mut self, id: NodeId, name: &str, value: &Literal, span: Span) {
+        if self.check_reserved_name(name, span) {
+            return;
+        }
+        if self.check_prelude_conflict(name, span, false)
+            || self.check_constant_conflict(name, span)
+        {
+            return;
+        }
+
         // Check for conflict with save/extern variables (file-global)
         if let Some(original) = self.find_global_conflict(name) {
             self.errors.push(SemanticError::Shadowing {
@@ -303,16 +1117,36 @@ impl<'a> Resolver<'a> {
         self.next_slot += 1;
 
         // Record in current scope
-        current_scope
-            .variables
-            .insert(name.to_string(), VarInfo { slot, span });
+        current_scope.variables.insert(
+            name.to_string(),
+            VarInfo {
+                slot,
+                span,
+                is_bool: matches!(value, Literal::Bool(_)),
+            },
+        );
 
         // Record binding for this declaration
         self.bindings.insert(id, slot);
+
+        if self.scopes.len() == 1
+            && let Some(value) = self.initial_temp_values.remove(name)
+        {
+            self.initial_temps.insert(id, value);
+        }
     }
 
     /// Declare a save variable (file-global, uses external storage)
-    fn declare_save(&mut self, id: NodeId, name: &str, span: Span) {
+    fn declare_save(&mut self, id: NodeId, name: &str, value: &Literal, span: Span) {
+        if self.check_reserved_name(name, span) {
+            return;
+        }
+        if self.check_prelude_conflict(name, span, false)
+            || self.check_constant_conflict(name, span)
+        {
+            return;
+        }
+
         // Check for conflict with save/extern variables (file-global)
         if let Some(original) = self.find_global_conflict(name) {
             self.errors.push(SemanticError::Shadowing {
@@ -334,15 +1168,86 @@ impl<'a> Resolver<'a> {
         }
 
         // Register the save variable (file-global)
-        self.save_vars
-            .insert(name.to_string(), SaveVarInfo { span });
+        self.save_vars.insert(
+            name.to_string(),
+            SaveVarInfo {
+                span,
+                is_bool: matches!(value, Literal::Bool(_)),
+            },
+        );
 
         // Record binding for this declaration
-        self.save_bindings.insert(id, name.to_string());
+        let key = self.storage_key(name);
+        self.save_bindings.insert(id, key);
+    }
+
+    /// Declare a constant (file-global, read-only, inlined where it is read)
+    fn declare_const(&mut self, name: &str, value: &Literal, span: Span) {
+        if self.check_reserved_name(name, span) {
+            return;
+        }
+        if self.check_prelude_conflict(name, span, false)
+            || self.check_constant_conflict(name, span)
+        {
+            return;
+        }
+
+        // Check for conflict with save/const/extern variables (file-global)
+        if let Some(original) = self.find_global_conflict(name) {
+            self.errors.push(SemanticError::Shadowing {
+                name: name.to_string(),
+                span,
+                original,
+            });
+            return;
+        }
+
+        // Check for conflict with any temp variable in any scope
+        if let Some(original) = self.find_temp_conflict(name, self.scopes.iter()) {
+            self.errors.push(SemanticError::Shadowing {
+                name: name.to_string(),
+                span,
+                original,
+            });
+            return;
+        }
+
+        // No binding for the declaration itself: it emits no code
+        self.const_vars.insert(
+            name.to_string(),
+            ConstVarInfo {
+                span,
+                value: value.clone(),
+            },
+        );
+    }
+
+    /// Where a save variable declared in this file is kept in storage.
+    fn storage_key(&self, name: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}.{}", namespace, name),
+            None => name.to_string(),
+        }
     }
 
     /// Declare an extern variable (file-global, read-only, host-provided)
-    fn declare_extern(&mut self, _id: NodeId, name: &str, span: Span) {
+    fn declare_extern(&mut self, data: &ExternDeclData) {
+        let ExternDeclData {
+            name,
+            default,
+            span,
+            ty,
+            ..
+        } = data;
+        let span = *span;
+        if self.check_reserved_name(name, span) {
+            return;
+        }
+        if self.check_prelude_conflict(name, span, true) || self.check_constant_conflict(name, span)
+        {
+            return;
+        }
+
         // Check for conflict with save/extern variables (file-global)
         if let Some(original) = self.find_global_conflict(name) {
             self.errors.push(SemanticError::Shadowing {
@@ -363,10 +1268,33 @@ impl<'a> Resolver<'a> {
             return;
         }
 
+        if let (Some(expected), Some(default)) = (ty, default) {
+            let found = default.value_type();
+            if found != *expected {
+                self.errors.push(SemanticError::ExternDefaultType {
+                    name: name.clone(),
+                    expected: *expected,
+                    found,
+                    span,
+                });
+            }
+        }
+
         // Register the extern variable (file-global)
         // Note: No binding recorded for the declaration itself - only for references
-        self.extern_vars
-            .insert(name.to_string(), ExternVarInfo { span });
+        let save_value = default.clone().unwrap_or(match ty {
+            Some(ValueType::String) => Literal::String(String::new()),
+            Some(ValueType::Bool) => Literal::Bool(false),
+            Some(ValueType::Number) | None => Literal::Number(0.0),
+        });
+        self.extern_vars.insert(
+            name.to_string(),
+            ExternVarInfo {
+                span,
+                ty: *ty,
+                save_value,
+            },
+        );
     }
 
     /// Resolve a variable reference - search temp scopes, save variables, then extern variables.
@@ -383,16 +1311,33 @@ impl<'a> Resolver<'a> {
 
         // Check save variables (file-global)
         if self.save_vars.contains_key(name) {
-            self.save_bindings.insert(id, name.to_string());
+            let key = self.storage_key(name);
+            self.save_bindings.insert(id, key);
+            return;
+        }
+
+        // Check script constants (file-global, inlined)
+        if let Some(info) = self.const_vars.get(name) {
+            if for_write {
+                self.errors.push(SemanticError::AssignmentToConstant {
+                    name: name.to_string(),
+                    span,
+                    declared: Some(info.span),
+                });
+            } else {
+                self.constant_bindings
+                    .insert(id, literal_value(&info.value));
+            }
             return;
         }
 
         // Check extern variables (file-global, read-only)
-        if self.extern_vars.contains_key(name) {
+        if let Some(info) = self.extern_vars.get(name) {
             if for_write {
                 self.errors.push(SemanticError::AssignmentToExtern {
                     name: name.to_string(),
                     span,
+                    declared: Some((info.span, info.save_value.clone())),
                 });
                 return;
             }
@@ -400,6 +1345,41 @@ impl<'a> Resolver<'a> {
             return;
         }
 
+        // Check prelude variables (shared across files)
+        match self.prelude_vars.get(name) {
+            Some(PreludeVar::Save) => {
+                self.save_bindings.insert(id, name.to_string());
+                return;
+            }
+            Some(PreludeVar::Extern) if for_write => {
+                self.errors.push(SemanticError::AssignmentToExtern {
+                    name: name.to_string(),
+                    span,
+                    declared: None,
+                });
+                return;
+            }
+            Some(PreludeVar::Extern) => {
+                self.extern_bindings.insert(id, name.to_string());
+                return;
+            }
+            None => {}
+        }
+
+        // Check constants (fixed when the runtime is created)
+        if let Some(value) = self.constants.get(name) {
+            if for_write {
+                self.errors.push(SemanticError::AssignmentToConstant {
+                    name: name.to_string(),
+                    span,
+                    declared: None,
+                });
+            } else {
+                self.constant_bindings.insert(id, value.clone());
+            }
+            return;
+        }
+
         // Not found in any scope
         self.errors.push(SemanticError::UndefinedVariable {
             name: name.to_string(),
@@ -407,3 +1387,32 @@ impl<'a> Resolver<'a> {
         });
     }
 }
+
+/// Characters of literal text, counting interpolations as empty and the
+/// longer branch of each conditional.
+fn literal_length(parts: &[TextPart]) -> usize {
+    parts
+        .iter()
+        .map(|part| match part {
+            TextPart::Literal { text, .. } => text.chars().count(),
+            TextPart::VarRef { .. } => 0,
+            TextPart::Conditional {
+                then_parts,
+                else_parts,
+                ..
+            } => literal_length(then_parts).max(literal_length(else_parts)),
+        })
+        .sum()
+}
+
+/// The text of `parts` if it contains no interpolation.
+fn literal_text(parts: &[TextPart]) -> Option<String> {
+    let mut text = String::new();
+    for part in parts {
+        match part {
+            TextPart::Literal { text: literal, .. } => text.push_str(literal),
+            TextPart::VarRef { .. } | TextPart::Conditional { .. } => return None,
+        }
+    }
+    Some(text)
+}
@@ -1,5 +1,6 @@
+use crate::ast::ChoiceMarker;
 use crate::diagnostic::{Diagnostic, DiagnosticContext, IntoDiagnostic};
-use crate::token::{Span, Token, TokenKind};
+use crate::token::{self, Span, Token, TokenKind};
 
 #[derive(Debug, Clone)]
 pub enum LexicalError {
@@ -18,6 +19,10 @@ impl IntoDiagnostic for LexicalError {
                     diag = diag.with_note("Bobbin uses spaces for indentation, not tabs");
                 } else if message.contains("Unterminated string") {
                     diag = diag.with_note("Strings cannot span multiple lines");
+                } else if message.contains("Inconsistent indentation") {
+                    diag = diag.with_note(
+                        "A line must line up with the line above it or with one of the blocks it is nested in",
+                    );
                 } else if message.contains("Unexpected '}'") {
                     diag = diag.with_suggestion("use '}}' for a literal brace in text", span, "}}");
                 }
@@ -28,6 +33,23 @@ impl IntoDiagnostic for LexicalError {
     }
 }
 
+/// Source conventions a team can choose between. `Default` is standard Bobbin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScannerConfig {
+    /// Markers that start a choice line. Default: only `-`.
+    ///
+    /// A line starting with a marker that isn't listed is ordinary dialogue.
+    pub choice_markers: Vec<ChoiceMarker>,
+}
+
+impl Default for ScannerConfig {
+    fn default() -> Self {
+        Self {
+            choice_markers: vec![ChoiceMarker::Dash],
+        }
+    }
+}
+
 /// Scanning mode determines what tokens we expect next
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ScanMode {
@@ -37,10 +59,15 @@ enum ScanMode {
     LineStart,
     /// After a keyword (temp/save/set), expect: identifier = literal
     Declaration,
-    /// After extern keyword, expect: identifier only (no initializer)
+    /// After extern keyword, expect: identifier, then an optional `: type` and
+    /// `= literal` default
     ExternDeclaration,
+    /// Right after a choice marker, check for a `(label)` before the text
+    ChoiceStart,
     /// Scanning text content (dialogue lines, choice text)
     Text,
+    /// After a `~` in text, expect the effect's `set` keyword
+    Effect,
     /// Inside an interpolation {}, expect identifier
     Interpolation,
 }
@@ -56,17 +83,33 @@ pub struct Scanner<'a> {
     pending_dedents: usize,
     /// Current scanning mode
     mode: ScanMode,
+    /// Whether the current line is a choice, where ` #` starts its tags
+    in_choice: bool,
+    config: ScannerConfig,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
+        Self::with_config(source, ScannerConfig::default())
+    }
+
+    pub fn with_config(source: &'a str, config: ScannerConfig) -> Self {
+        // Skip a leading UTF-8 byte order mark. Offsets stay relative to the
+        // original source so diagnostic spans line up with the file on disk.
+        let offset = if source.starts_with('\u{feff}') {
+            '\u{feff}'.len_utf8()
+        } else {
+            0
+        };
         Self {
             source,
-            start: 0,
-            current: 0,
+            start: offset,
+            current: offset,
             indent_stack: vec![0],
             pending_dedents: 0,
             mode: ScanMode::Indentation,
+            in_choice: false,
+            config,
         }
     }
 
@@ -81,11 +124,20 @@ impl<'a> Scanner<'a> {
     }
 
     fn scan_token(&mut self) -> Result<Token<'a>, LexicalError> {
+        // A dedent across several levels emits one Dedent per level, all
+        // before the line's first token, so the line isn't read as part of
+        // the blocks being closed
+        if self.pending_dedents > 0 {
+            self.pending_dedents -= 1;
+            self.start = self.current;
+            return Ok(self.make_token(TokenKind::Dedent));
+        }
+
         // Handle indentation when in Indentation mode
-        if self.mode == ScanMode::Indentation {
-            if let Some(token) = self.handle_indentation()? {
-                return Ok(token);
-            }
+        if self.mode == ScanMode::Indentation
+            && let Some(token) = self.handle_indentation()?
+        {
+            return Ok(token);
         }
 
         self.start = self.current;
@@ -97,6 +149,7 @@ impl<'a> Scanner<'a> {
         // Handle newlines - transition to Indentation mode
         if self.consume_newline() {
             self.mode = ScanMode::Indentation;
+            self.in_choice = false;
             return Ok(self.make_token(TokenKind::NewLine));
         }
 
@@ -106,66 +159,140 @@ impl<'a> Scanner<'a> {
             ScanMode::LineStart => self.scan_line_start(),
             ScanMode::Declaration => self.scan_declaration_content(),
             ScanMode::ExternDeclaration => self.scan_extern_declaration(),
+            ScanMode::ChoiceStart => self.scan_choice_start(),
             ScanMode::Text => self.scan_text_content(),
+            ScanMode::Effect => self.scan_effect_start(),
             ScanMode::Interpolation => self.scan_interpolation_content(),
         }
     }
 
     /// Scan at the start of a line - check for keywords, choice marker, or text
     fn scan_line_start(&mut self) -> Result<Token<'a>, LexicalError> {
+        // Escaped line start: `\` forces the rest of the line to be text,
+        // e.g. `\- not a choice` or `\save the day`. `\~` is left for the
+        // text scanner, which handles it anywhere in a line.
+        if self.peek() == Some('\\') && !matches!(self.peek_next(), None | Some('\n' | '\r')) {
+            if self.peek_next() != Some('~') {
+                self.advance();
+            }
+            self.mode = ScanMode::Text;
+            return self.scan_text_content();
+        }
+
         // Declaration keywords
-        if let Some(tok) = self.try_keyword("temp", TokenKind::Temp, ScanMode::Declaration) {
+        if let Some(tok) = self.try_keyword(TokenKind::Temp, ScanMode::Declaration) {
             return Ok(tok);
         }
-        if let Some(tok) = self.try_keyword("save", TokenKind::Save, ScanMode::Declaration) {
+        if let Some(tok) = self.try_keyword(TokenKind::Save, ScanMode::Declaration) {
             return Ok(tok);
         }
-        if let Some(tok) = self.try_keyword("set", TokenKind::Set, ScanMode::Declaration) {
+        if let Some(tok) = self.try_keyword(TokenKind::Const, ScanMode::Declaration) {
             return Ok(tok);
         }
-        if let Some(tok) =
-            self.try_keyword("extern", TokenKind::Extern, ScanMode::ExternDeclaration)
-        {
+        if let Some(tok) = self.try_keyword(TokenKind::Set, ScanMode::Declaration) {
+            return Ok(tok);
+        }
+        if let Some(tok) = self.try_keyword(TokenKind::Extern, ScanMode::ExternDeclaration) {
             return Ok(tok);
         }
 
-        // Choice marker
-        if let Some(tok) = self.try_keyword("-", TokenKind::Choice, ScanMode::Text) {
+        // Line modifiers
+        if let Some(tok) = self.try_keyword(TokenKind::Once, ScanMode::Text) {
+            return Ok(tok);
+        }
+
+        // Debug output: `debug_print "message"`; the string scans like a declaration value
+        if let Some(tok) = self.try_keyword(TokenKind::DebugPrint, ScanMode::Declaration) {
             return Ok(tok);
         }
 
+        // Assertion: `assert gold >= 0`; operands scan like declaration values
+        if let Some(tok) = self.try_keyword(TokenKind::Assert, ScanMode::Declaration) {
+            return Ok(tok);
+        }
+
+        // File-level storage prefix: `namespace quest_intro`
+        if let Some(tok) = self.try_keyword(TokenKind::Namespace, ScanMode::Declaration) {
+            return Ok(tok);
+        }
+
+        // Directives (keyword alone on its line)
+        if let Some(tok) = self.try_directive(TokenKind::Shuffle) {
+            return Ok(tok);
+        }
+        if let Some(tok) = self.try_directive(TokenKind::End) {
+            return Ok(tok);
+        }
+        if let Some(tok) = self.try_alone("~", TokenKind::Blank) {
+            return Ok(tok);
+        }
+
+        // Choice marker
+        for i in 0..self.config.choice_markers.len() {
+            let marker = self.config.choice_markers[i].symbol();
+            if let Some(tok) = self.try_word(marker, TokenKind::Choice, ScanMode::ChoiceStart) {
+                return Ok(tok);
+            }
+        }
+
         // Otherwise it's text content
         self.mode = ScanMode::Text;
         self.scan_text_content()
     }
 
-    /// Try to match a keyword followed by space. Returns token if matched.
-    /// The token lexeme contains only the keyword (not the trailing space).
-    fn try_keyword(
-        &mut self,
-        keyword: &str,
-        kind: TokenKind,
-        next_mode: ScanMode,
-    ) -> Option<Token<'a>> {
+    /// Try to match the keyword for `kind` followed by space. Returns token if matched.
+    fn try_keyword(&mut self, kind: TokenKind, next_mode: ScanMode) -> Option<Token<'a>> {
+        let keyword = kind.keyword().expect("keyword token");
+        self.try_word(keyword, kind, next_mode)
+    }
+
+    /// Try to match `word` followed by space. Returns token if matched.
+    /// The token lexeme contains only the word (not the trailing space).
+    fn try_word(&mut self, word: &str, kind: TokenKind, next_mode: ScanMode) -> Option<Token<'a>> {
         let remaining = &self.source[self.current..];
 
-        // Must match keyword
-        if !remaining.starts_with(keyword) {
+        // Must match word
+        if !remaining.starts_with(word) {
             return None;
         }
 
         // Must be followed by space (word boundary)
-        if !remaining[keyword.len()..].starts_with(' ') {
+        if !remaining[word.len()..].starts_with(' ') {
             return None;
         }
 
-        self.advance_n(keyword.len());
+        self.advance_n(word.len());
         let token = self.make_token(kind);
         self.skip_spaces();
         self.mode = next_mode;
         Some(token)
     }
 
+    /// Try to match a keyword that makes up the whole line (trailing spaces allowed).
+    fn try_directive(&mut self, kind: TokenKind) -> Option<Token<'a>> {
+        let keyword = kind.keyword().expect("keyword token");
+        self.try_alone(keyword, kind)
+    }
+
+    /// Try to match `word` as the whole line (trailing spaces allowed).
+    fn try_alone(&mut self, word: &str, kind: TokenKind) -> Option<Token<'a>> {
+        let remaining = &self.source[self.current..];
+
+        if !remaining.starts_with(word) {
+            return None;
+        }
+
+        let rest = remaining[word.len()..].trim_start_matches(' ');
+        if !(rest.is_empty() || rest.starts_with('\n') || rest.starts_with('\r')) {
+            return None;
+        }
+
+        self.advance_n(word.len());
+        let token = self.make_token(kind);
+        self.skip_spaces();
+        Some(token)
+    }
+
     /// Scan declaration content: identifier = literal
     fn scan_declaration_content(&mut self) -> Result<Token<'a>, LexicalError> {
         self.skip_spaces();
@@ -177,10 +304,34 @@ impl<'a> Scanner<'a> {
 
         let c = self.peek().unwrap();
 
-        // Equals
-        if c == '=' {
+        // Equals, a compound assignment, or a comparison
+        let operator = match (c, self.peek_next()) {
+            ('+', Some('=')) => Some(TokenKind::PlusEqual),
+            ('-', Some('=')) => Some(TokenKind::MinusEqual),
+            ('*', Some('=')) => Some(TokenKind::StarEqual),
+            ('/', Some('=')) => Some(TokenKind::SlashEqual),
+            ('=', Some('=')) => Some(TokenKind::EqualEqual),
+            ('!', Some('=')) => Some(TokenKind::BangEqual),
+            ('<', Some('=')) => Some(TokenKind::LessEqual),
+            ('>', Some('=')) => Some(TokenKind::GreaterEqual),
+            _ => None,
+        };
+        if let Some(kind) = operator {
+            self.advance_n(2);
+            return Ok(self.make_token(kind));
+        }
+        let symbol = match c {
+            '=' => Some(TokenKind::Equals),
+            ':' => Some(TokenKind::Colon),
+            '<' => Some(TokenKind::Less),
+            '>' => Some(TokenKind::Greater),
+            '(' => Some(TokenKind::OpenParen),
+            ')' => Some(TokenKind::CloseParen),
+            _ => None,
+        };
+        if let Some(kind) = symbol {
             self.advance();
-            return Ok(self.make_token(TokenKind::Equals));
+            return Ok(self.make_token(kind));
         }
 
         // String literal
@@ -204,7 +355,8 @@ impl<'a> Scanner<'a> {
         Err(self.error("Unexpected character in declaration"))
     }
 
-    /// Scan extern declaration content: identifier only (no initializer)
+    /// Scan extern declaration content: the identifier. An optional `: type` and
+    /// `= default` follow and are scanned like any other declaration.
     fn scan_extern_declaration(&mut self) -> Result<Token<'a>, LexicalError> {
         self.skip_spaces();
         self.start = self.current;
@@ -215,8 +367,12 @@ impl<'a> Scanner<'a> {
 
         let c = self.peek().unwrap();
         if c.is_ascii_alphabetic() || c == '_' {
-            self.mode = ScanMode::LineStart;
-            return self.scan_identifier();
+            let token = self.scan_identifier();
+            self.skip_spaces();
+            if !(self.is_at_end() || self.is_at_newline()) {
+                self.mode = ScanMode::Declaration;
+            }
+            return token;
         }
 
         // Error recovery: advance past the invalid character to avoid infinite loop
@@ -224,6 +380,41 @@ impl<'a> Scanner<'a> {
         Err(self.error("Expected identifier after 'extern'"))
     }
 
+    /// Scan an optional `(label)` at the start of choice text, then the text itself.
+    /// `\(` escapes a parenthesis that should be shown, and `\ ` a leading space.
+    fn scan_choice_start(&mut self) -> Result<Token<'a>, LexicalError> {
+        self.mode = ScanMode::Text;
+        self.in_choice = true;
+        let remaining = &self.source[self.current..];
+
+        if starts_with_choice_escape(remaining) {
+            self.advance();
+            return self.scan_text_content();
+        }
+
+        let Some(inner) = remaining.strip_prefix('(') else {
+            return self.scan_text_content();
+        };
+        let name_len = inner
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(inner.len());
+        let name = &inner[..name_len];
+        let is_label = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && inner[name_len..].starts_with(')');
+        if !is_label {
+            return self.scan_text_content();
+        }
+
+        self.advance_n(name_len + 2);
+        let token = self.make_token(TokenKind::ChoiceLabel);
+        self.skip_spaces();
+        // The text after a label takes the same escapes
+        if starts_with_choice_escape(&self.source[self.current..]) {
+            self.advance();
+        }
+        Ok(token)
+    }
+
     /// Scan text content with interpolation support
     fn scan_text_content(&mut self) -> Result<Token<'a>, LexicalError> {
         self.start = self.current;
@@ -277,10 +468,80 @@ impl<'a> Scanner<'a> {
             return Err(self.error("Unexpected '}' - use '}}' for literal brace"));
         }
 
-        // Scan text segment until { or } or newline
+        // Effect separator; `\~` is a literal tilde
+        if c == '~' {
+            self.advance();
+            let token = self.make_token(TokenKind::Tilde);
+            self.skip_spaces();
+            self.mode = ScanMode::Effect;
+            return Ok(token);
+        }
+        if c == '\\' && self.peek_next() == Some('~') {
+            self.advance_n(2);
+            return Ok(Token {
+                kind: TokenKind::TextSegment,
+                lexeme: "~",
+                span: Span {
+                    start: self.start,
+                    end: self.current,
+                },
+            });
+        }
+
+        // Choice tags: ` #tag` and everything after it on the line; `\#` is a literal `#`
+        if self.at_tags() {
+            while !self.is_at_end() && !self.is_at_newline() {
+                self.advance();
+            }
+            let lexeme = self.source[self.start..self.current].trim_end();
+            return Ok(Token {
+                kind: TokenKind::Tags,
+                lexeme,
+                span: Span {
+                    start: self.start,
+                    end: self.start + lexeme.len(),
+                },
+            });
+        }
+        if self.in_choice && c == '\\' && self.peek_next() == Some('#') {
+            self.advance_n(2);
+            return Ok(Token {
+                kind: TokenKind::TextSegment,
+                lexeme: "#",
+                span: Span {
+                    start: self.start,
+                    end: self.current,
+                },
+            });
+        }
+
+        // Debug note: ` //! note` runs to the end of the line
+        if self.at_debug_note() {
+            while !self.is_at_end() && !self.is_at_newline() {
+                self.advance();
+            }
+            let lexeme = self.source[self.start + 3..self.current].trim();
+            return Ok(Token {
+                kind: TokenKind::DebugNote,
+                lexeme,
+                span: Span {
+                    start: self.start,
+                    end: self.current,
+                },
+            });
+        }
+
+        // Scan text segment until {, }, ~, a debug note, choice tags, or newline
         while !self.is_at_end() && !self.is_at_newline() {
             let c = self.peek().unwrap();
-            if c == '{' || c == '}' {
+            if c == '{'
+                || c == '}'
+                || c == '~'
+                || (c == '\\' && self.peek_next() == Some('~'))
+                || (self.in_choice && c == '\\' && self.peek_next() == Some('#'))
+                || self.at_debug_note()
+                || self.at_tags()
+            {
                 break;
             }
             self.advance();
@@ -289,6 +550,33 @@ impl<'a> Scanner<'a> {
         Ok(self.make_token(TokenKind::TextSegment))
     }
 
+    /// Whether a choice's tags start here: a `#` after a space.
+    fn at_tags(&self) -> bool {
+        self.in_choice
+            && self.source[self.current..].starts_with('#')
+            && self.source[..self.current].ends_with(' ')
+    }
+
+    /// Whether a `//!` debug note starts here. It must follow a space, so
+    /// `\\//!` at the start of a line and `http://!` stay text.
+    fn at_debug_note(&self) -> bool {
+        self.source[self.current..].starts_with("//!") && self.source[..self.current].ends_with(' ')
+    }
+
+    /// Scan the start of an effect after `~`: only `set` is allowed for now
+    fn scan_effect_start(&mut self) -> Result<Token<'a>, LexicalError> {
+        if let Some(tok) = self.try_keyword(TokenKind::Set, ScanMode::Declaration) {
+            return Ok(tok);
+        }
+
+        // Error recovery: skip the rest of the line
+        while !self.is_at_end() && !self.is_at_newline() {
+            self.advance();
+        }
+        self.mode = ScanMode::Text;
+        Err(self.error("Expected 'set' after '~'"))
+    }
+
     /// Scan inside an interpolation - expect identifier then }
     fn scan_interpolation_content(&mut self) -> Result<Token<'a>, LexicalError> {
         self.skip_spaces();
@@ -308,9 +596,40 @@ impl<'a> Scanner<'a> {
             return Ok(self.make_token(TokenKind::CloseBrace));
         }
 
-        // Identifier
+        // Format spec, up to the closing brace. Braces inside it are kept in the
+        // lexeme for the parser to reject, so `{price:.{decimals}}` still ends at
+        // its final '}'
+        if c == ':' {
+            self.advance();
+            let mut depth = 0;
+            while !self.is_at_end() && !self.is_at_newline() {
+                match self.peek().unwrap() {
+                    '}' if depth == 0 => break,
+                    '}' => depth -= 1,
+                    '{' => depth += 1,
+                    _ => {}
+                }
+                self.advance();
+            }
+            return Ok(Token {
+                kind: TokenKind::FormatSpec,
+                lexeme: &self.source[self.start + 1..self.current],
+                span: Span {
+                    start: self.start,
+                    end: self.current,
+                },
+            });
+        }
+
+        // Identifier, or a conditional text keyword (`{if x}`, `{else}`, `{endif}`)
         if c.is_ascii_alphabetic() || c == '_' {
-            return self.scan_identifier();
+            let mut token = self.scan_identifier()?;
+            if let Some(kind @ (TokenKind::If | TokenKind::Else | TokenKind::EndIf)) =
+                token::keyword(token.lexeme)
+            {
+                token.kind = kind;
+            }
+            return Ok(token);
         }
 
         // Error recovery: advance past the invalid character to avoid infinite loop
@@ -342,9 +661,9 @@ impl<'a> Scanner<'a> {
         }
 
         let lexeme = &self.source[self.start..self.current];
-        let kind = match lexeme {
-            "true" => TokenKind::True,
-            "false" => TokenKind::False,
+        let kind = match token::keyword(lexeme) {
+            Some(kind @ (TokenKind::True | TokenKind::False)) => kind,
+            // Other reserved words are left for the resolver to reject by name
             _ => TokenKind::Identifier,
         };
 
@@ -406,14 +725,7 @@ impl<'a> Scanner<'a> {
     /// Returns Some(token) if an indent-related token should be emitted.
     /// Returns None to continue with normal scanning (transitions to LineStart).
     fn handle_indentation(&mut self) -> Result<Option<Token<'a>>, LexicalError> {
-        // 1. Emit pending dedents first
-        if self.pending_dedents > 0 {
-            self.pending_dedents -= 1;
-            self.start = self.current;
-            return Ok(Some(self.make_token(TokenKind::Dedent)));
-        }
-
-        // 2. Process line start: skip blank lines and count leading spaces
+        // Process line start: skip blank lines and count leading spaces
         let spaces = match self.process_line_start()? {
             Some(count) => count,
             None => {
@@ -449,7 +761,9 @@ impl<'a> Scanner<'a> {
                 self.pending_dedents += 1;
             }
             if self.indent_stack.last().copied() != Some(spaces) {
-                return Err(self.error("Inconsistent indentation"));
+                return Err(self.error(
+                    "Inconsistent indentation - this line doesn't line up with any enclosing block",
+                ));
             }
             self.pending_dedents -= 1; // We emit one now
             Ok(Some(self.make_token(TokenKind::Dedent)))
@@ -472,6 +786,14 @@ impl<'a> Scanner<'a> {
             if self.consume_newline() {
                 continue;
             }
+            if self.source[self.current..].starts_with("//") {
+                // Comment line: skipped like a blank line, so its indentation
+                // doesn't matter. `comments()` recovers the text for tooling.
+                while !self.is_at_end() && !self.is_at_newline() {
+                    self.advance();
+                }
+                continue;
+            }
             if self.peek() == Some('\t') {
                 // Advance past the tab and skip to end of line to avoid infinite loop
                 while !self.is_at_end() && !self.is_at_newline() {
@@ -498,7 +820,7 @@ impl<'a> Scanner<'a> {
         matches!(self.peek(), Some('\n') | Some('\r'))
     }
 
-    /// Consumes a newline (\n or \r\n) if present. Returns true if consumed.
+    /// Consumes a newline (\n, \r\n, or a lone \r) if present. Returns true if consumed.
     fn consume_newline(&mut self) -> bool {
         match self.peek() {
             Some('\n') => {
@@ -565,3 +887,39 @@ impl<'a> Scanner<'a> {
         }
     }
 }
+
+/// Full-line `//` comments in `source`, as (byte offset of the line, text after `//`).
+///
+/// The scanner skips comment lines entirely; this recovers them for tooling.
+/// Comments followed by a blank line are left out, so only those directly
+/// above a statement get attached to it. Lines can't span multiple physical lines, so a line-by-line pass is exact.
+pub(crate) fn comments(source: &str) -> Vec<(usize, String)> {
+    let mut comments = Vec::new();
+    let mut pending = Vec::new();
+    let mut offset = 0;
+    let mut after_cr = false;
+    // A `\r\n` splits into two pieces; the bare `\n` is never a comment
+    for line in source.split_inclusive(['\n', '\r']) {
+        let trimmed = line.trim_start_matches([' ', '\u{feff}']);
+        if let Some(text) = trimmed.strip_prefix("//") {
+            pending.push((offset, text.trim().to_string()));
+        } else if trimmed.trim().is_empty() {
+            // A blank line cuts the comments above it off from what follows
+            if !(after_cr && line == "\n") {
+                pending.clear();
+            }
+        } else {
+            comments.append(&mut pending);
+        }
+        after_cr = line.ends_with('\r');
+        offset += line.len();
+    }
+    comments
+}
+
+/// Whether choice text starts with an escape the scanner drops: `\(`, so
+/// it isn't read as a label, or `\` before a space or tab that would
+/// otherwise be skipped.
+fn starts_with_choice_escape(text: &str) -> bool {
+    text.starts_with("\\(") || text.starts_with("\\ ") || text.starts_with("\\\t")
+}
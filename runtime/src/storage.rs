@@ -1,5 +1,8 @@
 //! Variable storage interfaces for dialogue globals and host state.
 
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
 use crate::Value;
 
 /// Storage interface for dialogue globals (`save` variables).
@@ -28,11 +31,11 @@ use crate::Value;
 /// use bobbin_runtime::{Value, VariableStorage};
 ///
 /// #[derive(Debug, Default)]
-/// struct MemoryStorage {
+/// struct MapStorage {
 ///     values: RwLock<HashMap<String, Value>>,
 /// }
 ///
-/// impl VariableStorage for MemoryStorage {
+/// impl VariableStorage for MapStorage {
 ///     fn get(&self, name: &str) -> Option<Value> {
 ///         self.values.read().unwrap().get(name).cloned()
 ///     }
@@ -72,6 +75,105 @@ pub trait VariableStorage: Send + Sync {
 
     /// Check if a variable exists in storage.
     fn contains(&self, name: &str) -> bool;
+
+    /// Names of every variable currently in storage, in no particular order.
+    ///
+    /// Used for listing variables without cloning their values. The default
+    /// returns an empty list for storage that can't enumerate its keys.
+    fn names(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// In-memory [`VariableStorage`] that lists variables in the order they were
+/// first stored, so listings and snapshots are stable.
+///
+/// Suits tests, tools, and games that copy variables into their own save
+/// files with [`MemoryStorage::get_all`].
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    values: RwLock<OrderedValues>,
+}
+
+/// Values plus the order their names were first inserted.
+#[derive(Debug, Default)]
+struct OrderedValues {
+    index: HashMap<String, usize>,
+    entries: Vec<(String, Value)>,
+}
+
+impl OrderedValues {
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.index.get(name).map(|&i| &self.entries[i].1)
+    }
+
+    fn insert(&mut self, name: &str, value: Value) {
+        match self.index.get(name) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.index.insert(name.to_string(), self.entries.len());
+                self.entries.push((name.to_string(), value));
+            }
+        }
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some(i) = self.index.remove(name) {
+            self.entries.remove(i);
+            for position in self.index.values_mut() {
+                if *position > i {
+                    *position -= 1;
+                }
+            }
+        }
+    }
+}
+
+impl MemoryStorage {
+    /// Create a new empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove a variable. A later read sees it as never stored.
+    pub fn remove(&self, name: &str) {
+        self.values.write().unwrap().remove(name);
+    }
+
+    /// Every variable with its value, in the order they were first stored.
+    pub fn get_all(&self) -> Vec<(String, Value)> {
+        self.values.read().unwrap().entries.clone()
+    }
+}
+
+impl VariableStorage for MemoryStorage {
+    fn get(&self, name: &str) -> Option<Value> {
+        self.values.read().unwrap().get(name).cloned()
+    }
+
+    fn set(&self, name: &str, value: Value) {
+        self.values.write().unwrap().insert(name, value);
+    }
+
+    fn initialize_if_absent(&self, name: &str, default: Value) {
+        let mut values = self.values.write().unwrap();
+        if values.get(name).is_none() {
+            values.insert(name, default);
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.values.read().unwrap().index.contains_key(name)
+    }
+
+    fn names(&self) -> Vec<String> {
+        let values = self.values.read().unwrap();
+        values
+            .entries
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
 }
 
 /// Interface for host-provided variables (read-only from Bobbin's perspective).
@@ -115,10 +217,83 @@ pub trait VariableStorage: Send + Sync {
 /// // Game can update values anytime:
 /// // game_state.player_health.store(50, Ordering::Relaxed);
 /// ```
+///
+/// # Time and Other Environment Values
+///
+/// Bobbin has no clock of its own. A script that branches on the hour or the
+/// date declares it as an `extern` (`extern current_hour`), and the host
+/// answers from whatever clock the game keeps, in-game or real.
 pub trait HostState: Send + Sync {
     /// Look up a host variable by name.
     ///
     /// Returns `Some(value)` if the variable exists, `None` otherwise.
     /// A `None` return will cause `RuntimeError::MissingExternVariable` at runtime.
     fn lookup(&self, name: &str) -> Option<Value>;
+
+    /// Look up several host variables at once, in the order given.
+    ///
+    /// Used by [`RuntimeOptions::host_snapshot`](crate::RuntimeOptions::host_snapshot)
+    /// to read every variable a step might need in one go. The default calls
+    /// `lookup` for each name; hosts whose state can change concurrently should
+    /// override it to read all values under one lock, so they agree.
+    fn lookup_many(&self, names: &[&str]) -> Vec<Option<Value>> {
+        names.iter().map(|name| self.lookup(name)).collect()
+    }
+
+    /// Receive a script's `debug_print "message"` output, e.g. for the host's log.
+    ///
+    /// This output is for script authors and never reaches the dialogue. The
+    /// default discards it.
+    fn debug_print(&self, _message: &str) {}
+}
+
+/// Copy-on-write view of another storage, used for side-effect-free simulation.
+///
+/// Reads fall through to the underlying storage until a variable is written;
+/// writes stay local and are discarded with the overlay.
+pub(crate) struct ScratchStorage {
+    base: Arc<dyn VariableStorage>,
+    writes: RwLock<HashMap<String, Value>>,
+}
+
+impl ScratchStorage {
+    pub(crate) fn new(base: Arc<dyn VariableStorage>) -> Self {
+        Self {
+            base,
+            writes: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl VariableStorage for ScratchStorage {
+    fn get(&self, name: &str) -> Option<Value> {
+        match self.writes.read().unwrap().get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.base.get(name),
+        }
+    }
+
+    fn set(&self, name: &str, value: Value) {
+        self.writes.write().unwrap().insert(name.to_string(), value);
+    }
+
+    fn initialize_if_absent(&self, name: &str, default: Value) {
+        if !self.contains(name) {
+            self.set(name, default);
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.writes.read().unwrap().contains_key(name) || self.base.contains(name)
+    }
+
+    fn names(&self) -> Vec<String> {
+        let mut names = self.base.names();
+        for name in self.writes.read().unwrap().keys() {
+            if !self.base.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
 }
@@ -0,0 +1,98 @@
+//! Text helpers for tools that display dialogue outside the runtime.
+
+/// Shorten `line` to at most `max_chars` characters for a one-line preview.
+///
+/// Lines that fit are returned unchanged. Longer lines are cut and end with
+/// `…`, which counts toward `max_chars`. The cut never separates a character
+/// from the combining marks, variation selectors, or zero-width joiners that
+/// follow it, so accented letters and emoji sequences are kept whole or
+/// dropped whole.
+pub fn preview(line: &str, max_chars: usize) -> String {
+    if line.chars().count() <= max_chars {
+        return line.to_string();
+    }
+    if max_chars == 0 {
+        return String::new();
+    }
+
+    // Byte offset of the last safe cut that leaves room for the ellipsis
+    let mut cut = 0;
+    let mut previous = None;
+    for (count, (offset, c)) in line.char_indices().enumerate() {
+        if count >= max_chars - 1 {
+            // Only a cluster boundary is a safe place to stop
+            if is_boundary(previous, c) {
+                cut = offset;
+            }
+            break;
+        }
+        if is_boundary(previous, c) {
+            cut = offset;
+        }
+        previous = Some(c);
+    }
+
+    let mut preview = line[..cut].trim_end().to_string();
+    preview.push('…');
+    preview
+}
+
+/// True if a cut between `previous` and `c` doesn't split a visible character.
+fn is_boundary(previous: Option<char>, c: char) -> bool {
+    !(is_extender(c) || previous == Some('\u{200D}'))
+}
+
+/// Characters that attach to the one before them.
+fn is_extender(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'     // combining diacritical marks
+        | '\u{1AB0}'..='\u{1AFF}'   // combining diacritical marks extended
+        | '\u{1DC0}'..='\u{1DFF}'   // combining diacritical marks supplement
+        | '\u{20D0}'..='\u{20FF}'   // combining marks for symbols
+        | '\u{FE20}'..='\u{FE2F}'   // combining half marks
+        | '\u{FE00}'..='\u{FE0F}'   // variation selectors
+        | '\u{200D}'                // zero-width joiner
+        | '\u{1F3FB}'..='\u{1F3FF}' // emoji skin tone modifiers
+        | '\u{E0020}'..='\u{E007F}' // emoji tag sequences
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_lines_are_unchanged() {
+        assert_eq!(preview("Hello there.", 12), "Hello there.");
+        assert_eq!(preview("", 5), "");
+    }
+
+    #[test]
+    fn long_lines_end_with_ellipsis() {
+        assert_eq!(preview("Hello there, traveler.", 12), "Hello there…");
+        assert_eq!(preview("Hello", 0), "");
+        assert_eq!(preview("Hello", 1), "…");
+    }
+
+    #[test]
+    fn multibyte_characters_are_not_split() {
+        assert_eq!(preview("Ça va très bien", 8), "Ça va t…");
+        assert_eq!(preview("🐉🐉🐉🐉", 3), "🐉🐉…");
+    }
+
+    #[test]
+    fn combining_marks_stay_with_their_base() {
+        // "e" + combining acute would be cut after the "e"; drop both instead
+        let line = "cafe\u{0301} au lait";
+        assert_eq!(preview(line, 5), "caf…");
+        assert_eq!(preview(line, 7), "cafe\u{0301}…");
+    }
+
+    #[test]
+    fn emoji_sequences_stay_whole() {
+        // Woman + ZWJ + laptop, and thumbs up + skin tone
+        assert_eq!(preview("Hi 👩\u{200D}💻 there", 5), "Hi…");
+        assert_eq!(preview("Hi 👍\u{1F3FD} there", 5), "Hi…");
+        assert_eq!(preview("Hi 👍\u{1F3FD} there", 7), "Hi 👍\u{1F3FD}…");
+    }
+}
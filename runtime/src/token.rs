@@ -10,8 +10,18 @@ pub enum TokenKind {
     // Keywords
     Temp,
     Save,
+    Const,
     Set,
     Extern,
+    Shuffle,
+    Once,
+    DebugPrint,
+    Assert,
+    Namespace,
+    End,
+    If,    // Only inside braces: `{if x}`
+    Else,  // `{else}`
+    EndIf, // `{endif}`
 
     // Identifiers and Literals
     Identifier,
@@ -22,21 +32,95 @@ pub enum TokenKind {
 
     // Symbols
     Equals,
+    // Type annotation: `extern gold: number`
+    Colon,
+    // Compound assignment: `set gold += 5`
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    // Comparisons, in `assert` conditions
+    EqualEqual,
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
     OpenBrace,
     CloseBrace,
+    // Grouping in `assert` conditions: `(a == b) == c`
+    OpenParen,
+    CloseParen,
+    // Format spec after a variable in an interpolation: `{price:.2}`; lexeme is the text after ':'
+    FormatSpec,
+    Tilde, // Separates a line from its effect: `text ~ set x = 1`
+    Blank, // `~` alone on its line: an intentionally empty line
+
+    // Author note at the end of a line: `text //! note`; lexeme is the note text
+    DebugNote,
+
+    // Choice id: `(name)` right after the choice marker
+    ChoiceLabel,
+
+    // Trailing tags on a choice: ` #combat #red`; lexeme runs from the first `#` to the end of the line
+    Tags,
 
     // Text (dialogue content between interpolations)
     TextSegment,
 
     // Structure
-    Choice, // Just the "- " marker
+    Choice, // Just the marker ("-", or "*"/"+" if configured)
     Indent,
     Dedent,
     NewLine,
     Eof,
 }
 
+/// Keyword spellings and the tokens they scan to.
+const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("temp", TokenKind::Temp),
+    ("save", TokenKind::Save),
+    ("const", TokenKind::Const),
+    ("set", TokenKind::Set),
+    ("extern", TokenKind::Extern),
+    ("shuffle", TokenKind::Shuffle),
+    ("once", TokenKind::Once),
+    ("debug_print", TokenKind::DebugPrint),
+    ("assert", TokenKind::Assert),
+    ("namespace", TokenKind::Namespace),
+    ("end", TokenKind::End),
+    ("true", TokenKind::True),
+    ("false", TokenKind::False),
+    ("if", TokenKind::If),
+    ("else", TokenKind::Else),
+    ("endif", TokenKind::EndIf),
+];
+
+impl TokenKind {
+    /// How this keyword is spelled in source, or `None` if it isn't a keyword.
+    pub fn keyword(self) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .find(|(_, kind)| *kind == self)
+            .map(|(word, _)| *word)
+    }
+}
+
+/// The keyword token spelled `word`, if there is one.
+pub fn keyword(word: &str) -> Option<TokenKind> {
+    KEYWORDS
+        .iter()
+        .find(|(spelling, _)| *spelling == word)
+        .map(|(_, kind)| *kind)
+}
+
+/// Whether `word` is reserved by the language and so can't name a variable.
+pub fn is_reserved(word: &str) -> bool {
+    keyword(word).is_some()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Span {
     pub start: usize,
     pub end: usize,
@@ -0,0 +1,195 @@
+//! Arithmetic and comparison operations on [`Value`]s.
+//!
+//! These functions define the semantics the VM uses when combining values.
+//! They are public so hosts implementing custom storage or game logic can
+//! compare and combine values exactly the way dialogue scripts do.
+
+use std::cmp::Ordering;
+
+use crate::chunk::Value;
+use crate::vm::RuntimeError;
+
+/// Add two values.
+///
+/// Numbers are summed; strings are concatenated. Any other combination is a
+/// [`RuntimeError::TypeMismatch`].
+pub fn value_add(a: &Value, b: &Value) -> Result<Value, RuntimeError> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => Ok(Value::Number(x + y)),
+        (Value::String(x), Value::String(y)) => Ok(Value::String(format!("{}{}", x, y))),
+        _ => Err(type_mismatch("+", a, b)),
+    }
+}
+
+/// Subtract `b` from `a`. Both operands must be numbers.
+pub fn value_sub(a: &Value, b: &Value) -> Result<Value, RuntimeError> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => Ok(Value::Number(x - y)),
+        _ => Err(type_mismatch("-", a, b)),
+    }
+}
+
+/// Multiply two values. Both operands must be numbers.
+pub fn value_mul(a: &Value, b: &Value) -> Result<Value, RuntimeError> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => Ok(Value::Number(x * y)),
+        _ => Err(type_mismatch("*", a, b)),
+    }
+}
+
+/// Divide `a` by `b`. Both operands must be numbers and `b` must be nonzero.
+pub fn value_div(a: &Value, b: &Value) -> Result<Value, RuntimeError> {
+    match (a, b) {
+        (Value::Number(_), Value::Number(y)) if *y == 0.0 => Err(RuntimeError::DivisionByZero),
+        (Value::Number(x), Value::Number(y)) => Ok(Value::Number(x / y)),
+        _ => Err(type_mismatch("/", a, b)),
+    }
+}
+
+/// Test two values for equality.
+///
+/// Values of different types are never equal (`1` is not equal to `"1"`).
+pub fn value_eq(a: &Value, b: &Value) -> bool {
+    a == b
+}
+
+/// Order two values.
+///
+/// Numbers compare numerically and strings compare lexicographically.
+/// Booleans and mixed-type operands cannot be ordered and produce a
+/// [`RuntimeError::TypeMismatch`] naming `operator`, the comparison the
+/// script asked for.
+pub fn value_cmp(operator: &'static str, a: &Value, b: &Value) -> Result<Ordering, RuntimeError> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x
+            .partial_cmp(y)
+            .ok_or_else(|| type_mismatch(operator, a, b)),
+        (Value::String(x), Value::String(y)) => Ok(x.cmp(y)),
+        _ => Err(type_mismatch(operator, a, b)),
+    }
+}
+
+/// Test whether `haystack` contains `needle` (the `contains` operator).
+///
+/// For strings this is a case-sensitive substring test; every string contains
+/// `""`. Lists will test membership once they exist. Any other combination is
+/// a [`RuntimeError::TypeMismatch`].
+pub fn value_contains(haystack: &Value, needle: &Value) -> Result<bool, RuntimeError> {
+    match (haystack, needle) {
+        (Value::String(h), Value::String(n)) => Ok(h.contains(n.as_str())),
+        _ => Err(type_mismatch("contains", haystack, needle)),
+    }
+}
+
+fn type_mismatch(operator: &'static str, a: &Value, b: &Value) -> RuntimeError {
+    RuntimeError::TypeMismatch {
+        operator,
+        left: a.type_name(),
+        right: b.type_name(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(n: f64) -> Value {
+        Value::Number(n)
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn adds_numbers_and_concatenates_strings() {
+        assert_eq!(value_add(&num(2.0), &num(3.5)).unwrap(), num(5.5));
+        assert_eq!(
+            value_add(&string("foo"), &string("bar")).unwrap(),
+            string("foobar")
+        );
+    }
+
+    #[test]
+    fn rejects_mixed_addition() {
+        let err = value_add(&num(1.0), &string("1")).unwrap_err();
+        assert!(matches!(
+            err,
+            RuntimeError::TypeMismatch {
+                operator: "+",
+                left: "number",
+                right: "string"
+            }
+        ));
+    }
+
+    #[test]
+    fn arithmetic_requires_numbers() {
+        assert_eq!(value_sub(&num(5.0), &num(3.0)).unwrap(), num(2.0));
+        assert_eq!(value_mul(&num(4.0), &num(2.5)).unwrap(), num(10.0));
+        assert!(value_sub(&string("a"), &num(1.0)).is_err());
+        assert!(value_mul(&Value::Bool(true), &num(1.0)).is_err());
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        assert_eq!(value_div(&num(9.0), &num(2.0)).unwrap(), num(4.5));
+        assert!(matches!(
+            value_div(&num(1.0), &num(0.0)),
+            Err(RuntimeError::DivisionByZero)
+        ));
+    }
+
+    #[test]
+    fn equality_is_type_strict() {
+        assert!(value_eq(&num(1.0), &num(1.0)));
+        assert!(value_eq(&string("a"), &string("a")));
+        assert!(!value_eq(&num(1.0), &string("1")));
+        assert!(!value_eq(&Value::Bool(false), &num(0.0)));
+    }
+
+    #[test]
+    fn contains_tests_substrings() {
+        let tags = string("angry,tired");
+        assert!(value_contains(&tags, &string("angry")).unwrap());
+        assert!(!value_contains(&tags, &string("Angry")).unwrap());
+        assert!(value_contains(&tags, &string("")).unwrap());
+        assert!(!value_contains(&string(""), &string("a")).unwrap());
+    }
+
+    #[test]
+    fn contains_requires_strings() {
+        assert!(matches!(
+            value_contains(&string("12"), &num(1.0)),
+            Err(RuntimeError::TypeMismatch {
+                operator: "contains",
+                left: "string",
+                right: "number"
+            })
+        ));
+        assert!(value_contains(&num(12.0), &string("1")).is_err());
+    }
+
+    #[test]
+    fn orders_numbers_and_strings() {
+        assert_eq!(
+            value_cmp("<", &num(1.0), &num(2.0)).unwrap(),
+            Ordering::Less
+        );
+        assert_eq!(
+            value_cmp("<", &string("b"), &string("a")).unwrap(),
+            Ordering::Greater
+        );
+        assert!(value_cmp("<", &Value::Bool(true), &Value::Bool(false)).is_err());
+        assert!(value_cmp("<", &num(1.0), &string("1")).is_err());
+    }
+
+    #[test]
+    fn order_mismatch_names_the_operator() {
+        let err = value_cmp(">=", &num(1.0), &string("1")).unwrap_err();
+        assert!(matches!(
+            err,
+            RuntimeError::TypeMismatch { operator: ">=", .. }
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+//! Walking a syntax tree without matching every statement by hand.
+//!
+//! Implement [`Visitor`] with the hooks an analysis needs; the others do
+//! nothing. [`walk`] calls them in source order and descends into the
+//! statements nested under each choice.
+//!
+//! ```
+//! use bobbin_runtime::ast::Choice;
+//! use bobbin_runtime::visit::{Visitor, walk};
+//!
+//! #[derive(Default)]
+//! struct Labels(Vec<String>);
+//!
+//! impl Visitor for Labels {
+//!     fn visit_choice(&mut self, choice: &Choice) {
+//!         self.0.extend(choice.label.clone());
+//!     }
+//! }
+//!
+//! let script = bobbin_runtime::parse("- (left) Go left\n- Go right\n").unwrap();
+//! let mut labels = Labels::default();
+//! walk(&script, &mut labels);
+//! assert_eq!(labels.0, ["left"]);
+//! ```
+
+use crate::ast::{Choice, Expr, ExternDeclData, Script, Stmt, TextPart, VarBindingData};
+use crate::token::Span;
+
+/// Hooks called by [`walk`]. Every method does nothing by default.
+pub trait Visitor {
+    /// Any statement, before the hook for its kind. A choice set is one
+    /// statement; its choices come after.
+    fn visit_stmt(&mut self, _stmt: &Stmt) {}
+
+    /// A line of dialogue. Its effect, if any, is visited next as an assignment.
+    fn visit_line(&mut self, _parts: &[TextPart], _span: Span) {}
+
+    fn visit_temp_decl(&mut self, _data: &VarBindingData) {}
+
+    fn visit_save_decl(&mut self, _data: &VarBindingData) {}
+
+    fn visit_const_decl(&mut self, _data: &VarBindingData) {}
+
+    fn visit_extern_decl(&mut self, _data: &ExternDeclData) {}
+
+    /// A `set` statement, or the `~ set` effect of a line.
+    fn visit_assignment(&mut self, _data: &VarBindingData) {}
+
+    fn visit_assert(&mut self, _condition: &Expr, _span: Span) {}
+
+    /// A choice set, before any of its choices.
+    fn visit_choice_set(&mut self, _choices: &[Choice], _shuffle: bool) {}
+
+    /// A choice, before the statements nested under it.
+    fn visit_choice(&mut self, _choice: &Choice) {}
+
+    /// A choice, after the statements nested under it.
+    fn leave_choice(&mut self, _choice: &Choice) {}
+}
+
+/// Visit every statement and choice of `script`, depth first.
+pub fn walk<V: Visitor + ?Sized>(script: &Script, visitor: &mut V) {
+    walk_block(&script.statements, visitor);
+}
+
+fn walk_block<V: Visitor + ?Sized>(statements: &[Stmt], visitor: &mut V) {
+    for stmt in statements {
+        visitor.visit_stmt(stmt);
+        match stmt {
+            Stmt::Line {
+                parts,
+                span,
+                effect,
+                ..
+            } => {
+                visitor.visit_line(parts, *span);
+                if let Some(effect) = effect {
+                    visitor.visit_assignment(effect);
+                }
+            }
+            Stmt::TempDecl(data) => visitor.visit_temp_decl(data),
+            Stmt::SaveDecl(data) => visitor.visit_save_decl(data),
+            Stmt::ConstDecl(data) => visitor.visit_const_decl(data),
+            Stmt::ExternDecl(data) => visitor.visit_extern_decl(data),
+            Stmt::Assignment(data) => visitor.visit_assignment(data),
+            Stmt::Assert { condition, span } => visitor.visit_assert(condition, *span),
+            Stmt::ChoiceSet { choices, shuffle } => {
+                visitor.visit_choice_set(choices, *shuffle);
+                for choice in choices {
+                    visitor.visit_choice(choice);
+                    walk_block(&choice.nested, visitor);
+                    visitor.leave_choice(choice);
+                }
+            }
+            // Only seen through `visit_stmt`
+            Stmt::DebugPrint { .. } | Stmt::End { .. } | Stmt::Namespace { .. } => {}
+        }
+    }
+}
@@ -1,7 +1,13 @@
+use crate::ast::{ArithOp, CompareOp};
 use crate::chunk::{Chunk, Instruction, Value};
 use crate::diagnostic::{Diagnostic, DiagnosticContext, IntoDiagnostic, Severity};
-use crate::storage::{HostState, VariableStorage};
+use crate::rng::Rng;
+use crate::storage::{HostState, ScratchStorage, VariableStorage};
+use crate::values::{value_add, value_cmp, value_div, value_eq, value_mul, value_sub};
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(Debug, Clone)]
 pub enum RuntimeError {
@@ -9,10 +15,35 @@ pub enum RuntimeError {
     NotAtChoice,
     /// Choice index out of bounds
     InvalidChoiceIndex { index: usize, count: usize },
+    /// A checked selection named a choice set that is no longer showing
+    StaleChoice { token: u64, current: u64 },
+    /// The host's choice validator rejected the selection
+    ChoiceVetoed { index: usize },
     /// Save variable not found in storage (storage may be corrupted or cleared)
     MissingSaveVariable { name: String },
     /// Extern variable not found in host state
     MissingExternVariable { name: String },
+    /// The host provided an extern variable with a different type than declared
+    ExternTypeMismatch {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// Operator applied to values of incompatible types
+    TypeMismatch {
+        operator: &'static str,
+        left: &'static str,
+        right: &'static str,
+    },
+    /// Division with a zero divisor
+    DivisionByZero,
+    /// The host set the cancellation flag while the VM was running
+    Cancelled,
+    /// An `assert` in the script found its condition false.
+    AssertionFailed { message: String, line: usize },
+    /// The stack held a different number of values at `Return` than the
+    /// compiler predicted: a compiler bug. Only checked in debug builds.
+    InternalStackImbalance { expected: usize, found: usize },
 }
 
 impl std::fmt::Display for RuntimeError {
@@ -31,12 +62,52 @@ impl std::fmt::Display for RuntimeError {
                     index, count
                 )
             }
+            RuntimeError::StaleChoice { token, current } => {
+                write!(
+                    f,
+                    "choice token {} is stale (current choice set is {})",
+                    token, current
+                )
+            }
+            RuntimeError::ChoiceVetoed { index } => {
+                write!(f, "choice {} was rejected by the choice validator", index)
+            }
             RuntimeError::MissingSaveVariable { name } => {
                 write!(f, "save variable '{}' not found in storage", name)
             }
             RuntimeError::MissingExternVariable { name } => {
                 write!(f, "extern variable '{}' not found in host state", name)
             }
+            RuntimeError::ExternTypeMismatch {
+                name,
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "extern variable '{}' is a {}, but it is declared as {}",
+                    name, found, expected
+                )
+            }
+            RuntimeError::TypeMismatch {
+                operator,
+                left,
+                right,
+            } => {
+                write!(f, "cannot apply '{}' to {} and {}", operator, left, right)
+            }
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::Cancelled => write!(f, "execution cancelled by host"),
+            RuntimeError::AssertionFailed { message, line } => {
+                write!(f, "assertion failed on line {}: {}", line, message)
+            }
+            RuntimeError::InternalStackImbalance { expected, found } => {
+                write!(
+                    f,
+                    "internal error: {} values on the stack at the end, expected {}",
+                    found, expected
+                )
+            }
         }
     }
 }
@@ -65,6 +136,26 @@ impl IntoDiagnostic for RuntimeError {
                 notes: vec!["Check that the choice index is within the valid range".to_string()],
                 suggestions: vec![],
             },
+            RuntimeError::StaleChoice { token, current } => Diagnostic {
+                severity: Severity::Error,
+                message: format!(
+                    "choice token {} is stale (current choice set is {})",
+                    token, current
+                ),
+                labels: vec![],
+                notes: vec![
+                    "The choices changed after they were read; read them again before selecting"
+                        .to_string(),
+                ],
+                suggestions: vec![],
+            },
+            RuntimeError::ChoiceVetoed { index } => Diagnostic {
+                severity: Severity::Error,
+                message: format!("choice {} was rejected by the choice validator", index),
+                labels: vec![],
+                notes: vec!["The choices are still showing; select another one".to_string()],
+                suggestions: vec![],
+            },
             RuntimeError::MissingSaveVariable { name } => Diagnostic {
                 severity: Severity::Error,
                 message: format!("save variable '{}' not found in storage", name),
@@ -85,10 +176,84 @@ impl IntoDiagnostic for RuntimeError {
                 ],
                 suggestions: vec![],
             },
+            RuntimeError::ExternTypeMismatch {
+                name,
+                expected,
+                found,
+            } => Diagnostic {
+                severity: Severity::Error,
+                message: format!(
+                    "extern variable '{}' is a {}, but it is declared as {}",
+                    name, found, expected
+                ),
+                labels: vec![],
+                notes: vec![format!(
+                    "Check that your game's HostState implementation returns a {} for this variable",
+                    expected
+                )],
+                suggestions: vec![],
+            },
+            RuntimeError::TypeMismatch {
+                operator,
+                left,
+                right,
+            } => Diagnostic {
+                severity: Severity::Error,
+                message: format!("cannot apply '{}' to {} and {}", operator, left, right),
+                labels: vec![],
+                notes: vec!["Both operands must have compatible types".to_string()],
+                suggestions: vec![],
+            },
+            RuntimeError::DivisionByZero => Diagnostic {
+                severity: Severity::Error,
+                message: "division by zero".to_string(),
+                labels: vec![],
+                notes: vec![],
+                suggestions: vec![],
+            },
+            RuntimeError::Cancelled => Diagnostic {
+                severity: Severity::Error,
+                message: "execution cancelled by host".to_string(),
+                labels: vec![],
+                notes: vec!["Clear the cancellation flag to resume stepping".to_string()],
+                suggestions: vec![],
+            },
+            RuntimeError::AssertionFailed { message, line } => Diagnostic {
+                severity: Severity::Error,
+                message: format!("assertion failed on line {}: {}", line, message),
+                labels: vec![],
+                notes: vec![],
+                suggestions: vec![],
+            },
+            RuntimeError::InternalStackImbalance { expected, found } => Diagnostic {
+                severity: Severity::Error,
+                message: format!(
+                    "internal error: {} values on the stack at the end, expected {}",
+                    found, expected
+                ),
+                labels: vec![],
+                notes: vec!["This is a bug in the Bobbin compiler - please report it along with the script".to_string()],
+                suggestions: vec![],
+            },
         }
     }
 }
 
+/// What the VM does when a declared save variable is absent from storage at read time.
+///
+/// This happens when storage was populated by an older version of a script
+/// (e.g. a save file that predates a newly added flag) or was cleared externally.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingSavePolicy {
+    /// Fail with `RuntimeError::MissingSaveVariable`.
+    #[default]
+    Error,
+    /// Re-run the `save` declaration: store its default value and use it.
+    UseDeclaredDefault,
+    /// Use `Value::Null` without touching storage.
+    Null,
+}
+
 pub(crate) enum StepResult {
     Line(String),
     Choice(Vec<String>),
@@ -96,11 +261,32 @@ pub(crate) enum StepResult {
 }
 
 pub struct VM {
-    chunk: Chunk,
+    /// Shared with forks, which only read it.
+    chunk: Arc<Chunk>,
     ip: usize,
     stack: Vec<Value>,
     storage: Arc<dyn VariableStorage>,
     host: Arc<dyn HostState>,
+    missing_save_policy: MissingSavePolicy,
+    rng: Rng,
+    /// Presentation order of the pending choice set: displayed index -> choice index.
+    choice_order: Vec<usize>,
+    /// Checked before each instruction; when set, `run` stops with `Cancelled`.
+    cancel: Arc<AtomicBool>,
+    /// Set on forks, which run ahead speculatively and so stay quiet in the host's log.
+    forked: bool,
+    /// When set, host variables are read once at the start of each step.
+    snapshot_host: bool,
+    /// Every host variable the chunk reads, from `Chunk::host_names`, for snapshots.
+    host_names: Vec<String>,
+    /// Host values read at the start of the current step, in snapshot mode.
+    host_snapshot: HashMap<String, Option<Value>>,
+    /// Save variables written since the host last cleared the set.
+    dirty: BTreeSet<String>,
+    /// When set, every line shown is counted in storage under a `read:` key.
+    track_seen_lines: bool,
+    /// Whether the line just returned by a step had been shown before.
+    line_seen: bool,
 }
 
 impl std::fmt::Debug for VM {
@@ -109,21 +295,241 @@ impl std::fmt::Debug for VM {
             .field("chunk", &self.chunk)
             .field("ip", &self.ip)
             .field("stack", &self.stack)
+            .field("missing_save_policy", &self.missing_save_policy)
             .finish_non_exhaustive()
     }
 }
 
 impl VM {
     pub fn new(chunk: Chunk, storage: Arc<dyn VariableStorage>, host: Arc<dyn HostState>) -> Self {
+        let host_names = chunk.host_names().into_iter().map(str::to_string).collect();
         Self {
-            chunk,
+            chunk: Arc::new(chunk),
             ip: 0,
             stack: Vec::new(),
             storage,
             host,
+            missing_save_policy: MissingSavePolicy::default(),
+            rng: Rng::new(0),
+            choice_order: Vec::new(),
+            cancel: Arc::new(AtomicBool::new(false)),
+            forked: false,
+            snapshot_host: false,
+            host_names,
+            host_snapshot: HashMap::new(),
+            dirty: BTreeSet::new(),
+            track_seen_lines: false,
+            line_seen: false,
+        }
+    }
+
+    pub(crate) fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel = flag;
+    }
+
+    pub(crate) fn cancel_flag(&self) -> &Arc<AtomicBool> {
+        &self.cancel
+    }
+
+    /// Reseed the random number generator used for shuffling.
+    pub(crate) fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub(crate) fn set_missing_save_policy(&mut self, policy: MissingSavePolicy) {
+        self.missing_save_policy = policy;
+    }
+
+    /// Copy of this VM whose storage writes go to a throwaway overlay, so it
+    /// can be run ahead without affecting the real session.
+    pub(crate) fn fork(&self) -> VM {
+        VM {
+            chunk: Arc::clone(&self.chunk),
+            ip: self.ip,
+            stack: self.stack.clone(),
+            storage: Arc::new(ScratchStorage::new(Arc::clone(&self.storage))),
+            host: Arc::clone(&self.host),
+            missing_save_policy: self.missing_save_policy,
+            rng: self.rng.clone(),
+            choice_order: self.choice_order.clone(),
+            cancel: Arc::clone(&self.cancel),
+            forked: true,
+            snapshot_host: self.snapshot_host,
+            host_names: self.host_names.clone(),
+            host_snapshot: self.host_snapshot.clone(),
+            // Its writes never reach the session's storage
+            dirty: BTreeSet::new(),
+            track_seen_lines: self.track_seen_lines,
+            line_seen: self.line_seen,
+        }
+    }
+
+    pub(crate) fn set_host_snapshot(&mut self, enabled: bool) {
+        self.snapshot_host = enabled;
+    }
+
+    pub(crate) fn set_track_seen_lines(&mut self, enabled: bool) {
+        self.track_seen_lines = enabled;
+    }
+
+    /// Read every host variable the script uses, for the step about to run.
+    fn take_host_snapshot(&mut self) {
+        let names: Vec<&str> = self.host_names.iter().map(String::as_str).collect();
+        let values = self.host.lookup_many(&names);
+        self.host_snapshot = self.host_names.iter().cloned().zip(values).collect();
+    }
+
+    /// Save variables written since the last `clear_dirty`, sorted.
+    pub(crate) fn dirty_variables(&self) -> Vec<String> {
+        self.dirty.iter().cloned().collect()
+    }
+
+    pub(crate) fn clear_dirty(&mut self) {
+        self.dirty.clear();
+    }
+
+    pub(crate) fn chunk(&self) -> &Chunk {
+        &self.chunk
+    }
+
+    pub(crate) fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// Continue from `ip` in `chunk` instead, keeping the stack and everything
+    /// else. `ip` must be a pause point whose stack layout matches the current one.
+    pub(crate) fn replace_chunk(&mut self, chunk: Chunk, ip: usize) {
+        self.host_names = chunk.host_names().into_iter().map(str::to_string).collect();
+        self.chunk = Arc::new(chunk);
+        self.ip = ip;
+    }
+
+    /// Source index of each displayed choice in the pending choice set.
+    pub(crate) fn choice_order(&self) -> &[usize] {
+        &self.choice_order
+    }
+
+    /// The `//!` note of the line just returned by a step, if it has one.
+    pub(crate) fn line_debug_note(&self) -> Option<&str> {
+        let line = self.ip.checked_sub(1)?;
+        self.chunk.debug_notes.get(&line).map(String::as_str)
+    }
+
+    /// The un-interpolated text of the line just returned by a step.
+    pub(crate) fn line_template(&self) -> Option<&str> {
+        let line = self.ip.checked_sub(1)?;
+        self.chunk.templates.get(&line).map(String::as_str)
+    }
+
+    /// 1-based source line of the line just returned by a step.
+    pub(crate) fn line_number(&self) -> Option<usize> {
+        let line = self.ip.checked_sub(1)?;
+        self.chunk.line_numbers.get(&line).copied()
+    }
+
+    /// How many choice branches enclose the line just returned by a step.
+    pub(crate) fn line_nesting(&self) -> usize {
+        let Some(line) = self.ip.checked_sub(1) else {
+            return 0;
+        };
+        self.chunk.nesting.get(&line).copied().unwrap_or(0)
+    }
+
+    /// How many choice branches enclose the pending choice set.
+    pub(crate) fn choice_nesting(&self) -> usize {
+        self.chunk.nesting.get(&self.ip).copied().unwrap_or(0)
+    }
+
+    /// Label of the pending choice shown at displayed position `index`, if it has one.
+    pub(crate) fn choice_label(&self, index: usize) -> Option<&str> {
+        let choice = *self.choice_order.get(index)?;
+        match self.chunk.code.get(self.ip) {
+            Some(Instruction::ChoiceSet { labels, .. }) => labels.get(choice)?.as_deref(),
+            _ => None,
         }
     }
 
+    /// Un-interpolated text of the pending choice shown at displayed position `index`.
+    pub(crate) fn choice_template(&self, index: usize) -> Option<&str> {
+        let choice = *self.choice_order.get(index)?;
+        let templates = self.chunk.choice_templates.get(&self.ip)?;
+        templates.get(choice).map(String::as_str)
+    }
+
+    /// Tags of the pending choice shown at displayed position `index`.
+    pub(crate) fn choice_tags(&self, index: usize) -> &[String] {
+        let Some(&choice) = self.choice_order.get(index) else {
+            return &[];
+        };
+        self.chunk
+            .choice_tags
+            .get(&self.ip)
+            .and_then(|tags| tags.get(choice))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether the pending choice shown at displayed position `index` was picked
+    /// before. Only labelled choices are tracked.
+    pub(crate) fn choice_seen(&self, index: usize) -> bool {
+        self.choice_label(index).is_some_and(|label| {
+            matches!(
+                self.storage.get(&self.seen_key(label)),
+                Some(Value::Bool(true))
+            )
+        })
+    }
+
+    /// Whether the line just returned by a step had been shown before, in
+    /// this conversation or an earlier one. Always false unless seen lines
+    /// are tracked.
+    pub(crate) fn line_seen(&self) -> bool {
+        self.line_seen
+    }
+
+    /// Storage key counting how often the line at `offset` was shown. Like a
+    /// `once` counter, it combines the namespace with the line's source offset
+    /// and its text.
+    fn read_key(&self, offset: usize) -> String {
+        let template = self.chunk.templates.get(&offset).map_or("", String::as_str);
+        let line = self.chunk.lines[offset];
+        match &self.chunk.namespace {
+            Some(namespace) => format!("read:{}.{}:{}", namespace, line, template),
+            None => format!("read:{}:{}", line, template),
+        }
+    }
+
+    /// Storage key recording that the choice labelled `label` was picked.
+    fn seen_key(&self, label: &str) -> String {
+        match &self.chunk.namespace {
+            Some(namespace) => format!("seen:{}.{}", namespace, label),
+            None => format!("seen:{}", label),
+        }
+    }
+
+    /// Instruction range of the branch behind the pending choice shown at
+    /// displayed position `index`: from its first instruction up to the next
+    /// branch, or up to the gather point for the last one.
+    pub(crate) fn choice_branch(&self, index: usize) -> Option<Range<usize>> {
+        let choice = *self.choice_order.get(index)?;
+        match self.chunk.code.get(self.ip) {
+            Some(Instruction::ChoiceSet {
+                targets, gather, ..
+            }) => {
+                let end = targets.get(choice + 1).copied().unwrap_or(*gather);
+                Some(targets[choice]..end)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the VM is paused at a ChoiceSet waiting for a selection.
+    pub(crate) fn is_at_choice(&self) -> bool {
+        matches!(
+            self.chunk.code.get(self.ip),
+            Some(Instruction::ChoiceSet { .. })
+        )
+    }
+
     /// Returns true if the next instruction (following jumps) is Return (no more content).
     pub(crate) fn is_at_end(&self) -> bool {
         let mut ip = self.ip;
@@ -131,6 +537,25 @@ impl VM {
             match self.chunk.code.get(ip) {
                 Some(Instruction::Return) | None => return true,
                 Some(Instruction::Jump { target }) => ip = *target,
+                // Debug output, assertions and end-of-branch cleanup aren't
+                // content, nor are the reads that feed an assertion
+                Some(
+                    Instruction::DebugPrint { .. }
+                    | Instruction::Pop { .. }
+                    | Instruction::Assert { .. }
+                    | Instruction::Compare { .. }
+                    | Instruction::Constant { .. }
+                    | Instruction::GetLocal { .. }
+                    | Instruction::GetStorage { .. }
+                    | Instruction::GetHost { .. },
+                ) => ip += 1,
+                // A once line that was already shown is skipped
+                Some(Instruction::CheckOnce { key }) if self.once_count(key) > 0.0 => {
+                    match self.chunk.code.get(ip + 1) {
+                        Some(Instruction::JumpIfFalse { target }) => ip = *target,
+                        _ => return false,
+                    }
+                }
                 Some(Instruction::ChoiceSet { .. }) => {
                     // Waiting for choice - there's more content after selection
                     return false;
@@ -146,12 +571,20 @@ impl VM {
         // Read ChoiceSet to get targets
         let instruction = self.chunk.code[self.ip].clone();
 
-        if let Instruction::ChoiceSet { count, targets } = instruction {
+        if let Instruction::ChoiceSet { count, targets, .. } = instruction {
             if index >= count {
                 return Err(RuntimeError::InvalidChoiceIndex { index, count });
             }
-            self.ip += 1;
-            self.ip = targets[index];
+            // Check before committing so a cancelled selection can be retried
+            if self.cancel.load(Ordering::Relaxed) {
+                return Err(RuntimeError::Cancelled);
+            }
+            // Map the displayed index back to the choice's position in source
+            let choice = self.choice_order[index];
+            if let Some(label) = self.choice_label(index) {
+                self.storage.set(&self.seen_key(label), Value::Bool(true));
+            }
+            self.ip = targets[choice];
         } else {
             return Err(RuntimeError::NotAtChoice);
         }
@@ -165,9 +598,59 @@ impl VM {
         self.run()
     }
 
+    /// Produce a value for a save variable missing from storage, per the configured policy.
+    fn recover_missing_save(&self, name: String) -> Result<Value, RuntimeError> {
+        match self.missing_save_policy {
+            MissingSavePolicy::Error => Err(RuntimeError::MissingSaveVariable { name }),
+            MissingSavePolicy::UseDeclaredDefault => match self.chunk.save_defaults.get(&name) {
+                Some(default) => {
+                    self.storage.initialize_if_absent(&name, default.clone());
+                    Ok(default.clone())
+                }
+                None => Err(RuntimeError::MissingSaveVariable { name }),
+            },
+            MissingSavePolicy::Null => Ok(Value::Null),
+        }
+    }
+
+    /// Store the declared default of every `save` variable that is absent,
+    /// as if each declaration had already run. Existing values are kept.
+    pub(crate) fn initialize_save_defaults(&self) {
+        for (name, default) in &self.chunk.save_defaults {
+            self.storage.initialize_if_absent(name, default.clone());
+        }
+    }
+
+    /// Current value of a host variable: from the step's snapshot in snapshot
+    /// mode, otherwise straight from the host.
+    fn lookup_host(&self, name: &str) -> Option<Value> {
+        if self.snapshot_host {
+            self.host_snapshot.get(name).cloned().flatten()
+        } else {
+            self.host.lookup(name)
+        }
+    }
+
+    /// How many times the `once` line with this key has been reached.
+    fn once_count(&self, key: &str) -> f64 {
+        match self.storage.get(key) {
+            Some(Value::Number(count)) => count,
+            _ => 0.0,
+        }
+    }
+
     /// Core execution loop.
     fn run(&mut self) -> Result<StepResult, RuntimeError> {
+        if self.snapshot_host {
+            self.take_host_snapshot();
+        }
         loop {
+            // Instruction boundaries are safe points: the VM can resume from here
+            // once the host clears the flag.
+            if self.cancel.load(Ordering::Relaxed) {
+                return Err(RuntimeError::Cancelled);
+            }
+
             let instruction = self.chunk.code[self.ip].clone();
             self.ip += 1;
 
@@ -184,6 +667,15 @@ impl VM {
                     let value = self.stack.pop().expect("stack underflow: compiler bug");
                     self.stack[slot] = value;
                 }
+                Instruction::Pop { count } => {
+                    self.stack.truncate(self.stack.len() - count);
+                }
+                Instruction::FormatNumber { precision } => {
+                    if let Some(Value::Number(n)) = self.stack.last() {
+                        let text = format!("{:.*}", precision, n);
+                        *self.stack.last_mut().unwrap() = Value::String(text);
+                    }
+                }
                 Instruction::Concat { count } => {
                     // Pop `count` values and concatenate as strings
                     let start = self.stack.len() - count;
@@ -197,17 +689,32 @@ impl VM {
                 Instruction::Line => {
                     let value = self.stack.pop().expect("stack underflow: compiler bug");
                     let text = value.to_string_value();
+                    if self.track_seen_lines {
+                        let key = self.read_key(self.ip - 1);
+                        let count = self.once_count(&key);
+                        self.line_seen = count > 0.0;
+                        self.storage.set(&key, Value::Number(count + 1.0));
+                    }
                     return Ok(StepResult::Line(text));
                 }
-                Instruction::ChoiceSet { count, .. } => {
+                Instruction::ChoiceSet { count, shuffle, .. } => {
                     // Pop choice texts from stack
-                    let mut choices = Vec::with_capacity(count);
+                    let mut texts = Vec::with_capacity(count);
                     for _ in 0..count {
                         let value = self.stack.pop().expect("stack underflow: compiler bug");
-                        let text = value.to_string_value();
-                        choices.push(text);
+                        texts.push(value.to_string_value());
+                    }
+                    texts.reverse();
+
+                    self.choice_order = (0..count).collect();
+                    if shuffle {
+                        self.rng.shuffle(&mut self.choice_order);
                     }
-                    choices.reverse();
+                    let choices = self
+                        .choice_order
+                        .iter()
+                        .map(|&i| std::mem::take(&mut texts[i]))
+                        .collect();
                     // Back up ip so select_and_continue can read ChoiceSet for targets
                     self.ip -= 1;
                     return Ok(StepResult::Choice(choices));
@@ -215,27 +722,153 @@ impl VM {
                 Instruction::Jump { target } => {
                     self.ip = target;
                 }
+                Instruction::JumpIfFalse { target } => {
+                    let value = self.stack.pop().expect("stack underflow: compiler bug");
+                    if !value.coerce_bool() {
+                        self.ip = target;
+                    }
+                }
+                Instruction::CheckOnce { key } => {
+                    let count = self.once_count(&key);
+                    self.stack.push(Value::Bool(count == 0.0));
+                    self.storage.set(&key, Value::Number(count + 1.0));
+                }
                 Instruction::InitStorage { name } => {
                     let value = self.stack.pop().expect("stack underflow: compiler bug");
+                    // A value kept from an earlier save isn't a new write
+                    if !self.storage.contains(&name) {
+                        self.dirty.insert(name.clone());
+                    }
                     self.storage.initialize_if_absent(&name, value);
                 }
                 Instruction::GetStorage { name } => match self.storage.get(&name) {
                     Some(value) => self.stack.push(value),
-                    None => return Err(RuntimeError::MissingSaveVariable { name }),
+                    None => {
+                        let value = self.recover_missing_save(name)?;
+                        self.stack.push(value);
+                    }
                 },
                 Instruction::SetStorage { name } => {
                     let value = self.stack.pop().expect("stack underflow: compiler bug");
                     self.storage.set(&name, value);
+                    self.dirty.insert(name);
                 }
-                Instruction::GetHost { name } => match self.host.lookup(&name) {
-                    Some(value) => self.stack.push(value),
-                    None => return Err(RuntimeError::MissingExternVariable { name }),
+                Instruction::GetHost { name } => match self.lookup_host(&name) {
+                    Some(value) => {
+                        if let Some(expected) = self.chunk.extern_types.get(&name)
+                            && value.type_name() != expected.name()
+                        {
+                            return Err(RuntimeError::ExternTypeMismatch {
+                                expected: expected.name(),
+                                found: value.type_name(),
+                                name,
+                            });
+                        }
+                        self.stack.push(value)
+                    }
+                    None => match self.chunk.extern_defaults.get(&name) {
+                        Some(default) => self.stack.push(default.clone()),
+                        None => return Err(RuntimeError::MissingExternVariable { name }),
+                    },
                 },
+                Instruction::Arithmetic { op } => {
+                    let right = self.stack.pop().expect("stack underflow: compiler bug");
+                    let left = self.stack.pop().expect("stack underflow: compiler bug");
+                    let result = match op {
+                        ArithOp::Add => value_add(&left, &right)?,
+                        ArithOp::Subtract => value_sub(&left, &right)?,
+                        ArithOp::Multiply => value_mul(&left, &right)?,
+                        ArithOp::Divide => value_div(&left, &right)?,
+                    };
+                    self.stack.push(result);
+                }
+                Instruction::Compare { op } => {
+                    let right = self.stack.pop().expect("stack underflow: compiler bug");
+                    let left = self.stack.pop().expect("stack underflow: compiler bug");
+                    let holds = match op {
+                        CompareOp::Equal => value_eq(&left, &right),
+                        CompareOp::NotEqual => !value_eq(&left, &right),
+                        CompareOp::Less => value_cmp(op.symbol(), &left, &right)?.is_lt(),
+                        CompareOp::LessEqual => value_cmp(op.symbol(), &left, &right)?.is_le(),
+                        CompareOp::Greater => value_cmp(op.symbol(), &left, &right)?.is_gt(),
+                        CompareOp::GreaterEqual => value_cmp(op.symbol(), &left, &right)?.is_ge(),
+                    };
+                    self.stack.push(Value::Bool(holds));
+                }
+                Instruction::Assert { message, line } => {
+                    let value = self.stack.pop().expect("stack underflow: compiler bug");
+                    if !value.coerce_bool() {
+                        return Err(RuntimeError::AssertionFailed { message, line });
+                    }
+                }
+                Instruction::DebugPrint { message } => {
+                    if !self.forked {
+                        self.host.debug_print(&message);
+                    }
+                }
                 Instruction::Return => {
-                    // Note: stack may have locals remaining, that's OK
+                    // Top-level temps remain at the final Return, and an `end`
+                    // pops everything first; anything else means the compiler
+                    // got scoping wrong
+                    let expected = if self.ip == self.chunk.code.len() {
+                        self.chunk.return_depth
+                    } else {
+                        0
+                    };
+                    if cfg!(debug_assertions) && self.stack.len() != expected {
+                        return Err(RuntimeError::InternalStackImbalance {
+                            expected,
+                            found: self.stack.len(),
+                        });
+                    }
                     return Ok(StepResult::Done);
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoStorage;
+
+    impl VariableStorage for NoStorage {
+        fn get(&self, _name: &str) -> Option<Value> {
+            None
+        }
+        fn set(&self, _name: &str, _value: Value) {}
+        fn initialize_if_absent(&self, _name: &str, _value: Value) {}
+        fn contains(&self, _name: &str) -> bool {
+            false
+        }
+    }
+
+    struct NoHost;
+
+    impl HostState for NoHost {
+        fn lookup(&self, _name: &str) -> Option<Value> {
+            None
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn stack_imbalance_at_return_is_detected() {
+        // A temp pushed without the compiler accounting for it
+        let mut chunk = Chunk::new();
+        let index = chunk.add_constant(Value::Number(1.0));
+        chunk.emit(Instruction::Constant { index }, 0);
+        chunk.emit(Instruction::Return, 0);
+
+        let mut vm = VM::new(chunk, Arc::new(NoStorage), Arc::new(NoHost));
+        assert!(matches!(
+            vm.step(),
+            Err(RuntimeError::InternalStackImbalance {
+                expected: 0,
+                found: 1
+            })
+        ));
+    }
+}
@@ -2,8 +2,12 @@
 
 mod support;
 
-use bobbin_runtime::{HostState, Runtime, VariableStorage};
+use bobbin_runtime::{
+    BobbinError, ChoiceOption, Event, HostState, LintConfig, MissingSavePolicy, Prelude,
+    ReloadOutcome, Runtime, RuntimeError, RuntimeOptions, RuntimeState, VariableStorage,
+};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use support::{EmptyHostState, MemoryStorage};
 
 #[test]
@@ -19,9 +23,505 @@ fn empty_lines() {
 #[test]
 fn empty_source() {
     // Special case: empty source produces empty output
+    let runtime = support::runtime("");
+    assert_eq!(runtime.current_line(), "");
+    assert!(!runtime.has_more());
+}
+
+#[test]
+fn revision_moves_with_each_step() {
+    let mut runtime = support::runtime("Hello\nHello\nGoodbye\n");
+    let initial = runtime.revision();
+    assert!(initial > 0);
+
+    // Same text as before, but a new line to show
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Hello");
+    assert_eq!(runtime.revision(), initial + 1);
+
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Goodbye");
+    assert_eq!(runtime.revision(), initial + 2);
+
+    // Advancing past the end shows nothing new
+    assert!(!runtime.has_more());
+    runtime.advance().unwrap();
+    assert_eq!(runtime.revision(), initial + 2);
+}
+
+#[test]
+fn byte_order_mark() {
+    support::run_output_test(&support::cases_dir().join("basic/bom.bobbin"));
+}
+
+#[test]
+fn whitespace_only_source() {
+    // Like empty source: blank and space-only lines produce no dialogue
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("basic/whitespace_only.bobbin")).unwrap();
+    let runtime = support::runtime(&source);
+    assert_eq!(runtime.current_line(), "");
+    assert!(!runtime.has_more());
+}
+
+#[test]
+fn state_reports_line_choices_and_done() {
+    let mut runtime = support::runtime("Ready?\n- Yes\n- No\nBye\n");
+    assert_eq!(runtime.state(), RuntimeState::Line("Ready?"));
+
+    runtime.advance().unwrap();
+    let choices = ["Yes".to_string(), "No".to_string()];
+    assert_eq!(runtime.state(), RuntimeState::Choices(&choices));
+
+    runtime.select_choice(1).unwrap();
+    // The final line is still a Line, even though has_more() is already false
+    assert_eq!(runtime.state(), RuntimeState::Line("Bye"));
+    assert!(!runtime.has_more());
+
+    runtime.advance().unwrap();
+    assert_eq!(runtime.state(), RuntimeState::Done);
+}
+
+#[test]
+fn events_report_lines_choices_and_end() {
+    let source = std::fs::read_to_string(support::cases_dir().join("basic/events.bobbin")).unwrap();
+    let mut runtime = support::runtime(&source);
+
+    let line = |text: &str, line_number, depth| Event::Line {
+        text: text.to_string(),
+        line_number,
+        depth,
+    };
+    assert_eq!(
+        runtime.next_event(),
+        Some(line("The door creaks open.", 2, 0))
+    );
+    // Nothing new until the runtime moves on
+    assert_eq!(runtime.next_event(), None);
+
+    runtime.advance().unwrap();
+    let options = vec![
+        ChoiceOption {
+            text: "Attack".to_string(),
+            label: Some("fight".to_string()),
+            tags: vec!["combat".to_string(), "red".to_string()],
+            seen: false,
+        },
+        ChoiceOption {
+            text: "Flee".to_string(),
+            label: None,
+            tags: vec![],
+            seen: false,
+        },
+    ];
+    assert_eq!(runtime.next_event(), Some(Event::Choices { options }));
+
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.next_event(), Some(line("You swing first.", 4, 1)));
+    runtime.advance().unwrap();
+    assert_eq!(runtime.next_event(), Some(line("The hall is quiet.", 6, 0)));
+    runtime.advance().unwrap();
+    assert_eq!(runtime.next_event(), Some(Event::End));
+    runtime.advance().unwrap();
+    assert_eq!(runtime.next_event(), None);
+}
+
+#[test]
+fn events_end_once_without_a_final_line() {
+    let mut runtime = support::runtime("- Go\n");
+    assert!(matches!(runtime.next_event(), Some(Event::Choices { .. })));
+
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.next_event(), Some(Event::End));
+    runtime.advance().unwrap();
+    assert_eq!(runtime.next_event(), None);
+}
+
+#[test]
+fn blank_line() {
+    support::run_output_test(&support::cases_dir().join("basic/blank_line.bobbin"));
+}
+
+#[test]
+fn escaped_line_start() {
+    support::run_output_test(&support::cases_dir().join("basic/escaped_line_start.bobbin"));
+}
+
+#[test]
+fn cancel_flag_interrupts_steps() {
+    let mut runtime = support::runtime("One\nTwo\n- Go\n    Three\n");
+    let cancel = runtime.cancel_flag();
+
+    cancel.store(true, Ordering::Relaxed);
+    assert!(matches!(runtime.advance(), Err(RuntimeError::Cancelled)));
+    assert_eq!(runtime.current_line(), "One");
+
+    // Clearing the flag resumes where execution stopped
+    cancel.store(false, Ordering::Relaxed);
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Two");
+    runtime.advance().unwrap();
+    assert!(runtime.is_waiting_for_choice());
+
+    // A cancelled selection leaves the choices up so it can be retried
+    cancel.store(true, Ordering::Relaxed);
+    assert!(matches!(
+        runtime.select_choice(0),
+        Err(RuntimeError::Cancelled)
+    ));
+    assert!(runtime.is_waiting_for_choice());
+
+    cancel.store(false, Ordering::Relaxed);
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.current_line(), "Three");
+}
+
+#[test]
+fn once_lines_show_only_the_first_time() {
+    let source = std::fs::read_to_string(support::cases_dir().join("basic/once.bobbin")).unwrap();
+    // Counters live in storage, so a second run over the same storage skips them
     let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
     let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
-    let runtime = Runtime::new("", storage, host).unwrap();
-    assert_eq!(runtime.current_line(), "");
+
+    let mut runtime = Runtime::new(&source, Arc::clone(&storage), Arc::clone(&host)).unwrap();
+    let mut lines = vec![runtime.current_line().to_string()];
+    while runtime.has_more() {
+        runtime.advance().unwrap();
+        lines.push(runtime.current_line().to_string());
+    }
+    assert_eq!(
+        lines,
+        [
+            "Welcome, stranger.",
+            "What brings you here?",
+            "once upon a time, this was a busy inn.",
+            "Have a drink on the house.",
+        ]
+    );
+
+    let mut runtime = Runtime::new(&source, storage, host).unwrap();
+    assert_eq!(runtime.current_line(), "What brings you here?");
+    runtime.advance().unwrap();
+    assert_eq!(
+        runtime.current_line(),
+        "once upon a time, this was a busy inn."
+    );
+    // The skipped once line at the end doesn't count as more content
+    assert!(!runtime.has_more());
+}
+
+#[test]
+fn once_lines_are_kept_apart_by_namespace() {
+    // Same offset and text in both files, so only the namespace tells them apart
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    for namespace in ["inn", "pub"] {
+        let source = format!("namespace {}\nonce Welcome!\nBye.\n", namespace);
+        let runtime = Runtime::new(&source, Arc::clone(&storage), Arc::clone(&host)).unwrap();
+        assert_eq!(runtime.current_line(), "Welcome!", "in {}", namespace);
+    }
+    let runtime = Runtime::new("namespace inn\nonce Welcome!\nBye.\n", storage, host).unwrap();
+    assert_eq!(runtime.current_line(), "Bye.");
+}
+
+/// Host that records `debug_print` output.
+#[derive(Default)]
+struct LoggingHost {
+    log: std::sync::Mutex<Vec<String>>,
+}
+
+impl HostState for LoggingHost {
+    fn lookup(&self, _name: &str) -> Option<bobbin_runtime::Value> {
+        None
+    }
+
+    fn debug_print(&self, message: &str) {
+        self.log.lock().unwrap().push(message.to_string());
+    }
+}
+
+#[test]
+fn debug_print_goes_to_host_not_dialogue() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("basic/debug_print.bobbin")).unwrap();
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host = Arc::new(LoggingHost::default());
+    let mut runtime = Runtime::new(&source, storage, host.clone()).unwrap();
+
+    assert_eq!(runtime.current_line(), "Hello there.");
+    assert_eq!(*host.log.lock().unwrap(), ["conversation started"]);
+
+    runtime.advance().unwrap();
+    // Peeking runs ahead without logging
+    runtime.peek_branch_length(0).unwrap();
+    assert_eq!(host.log.lock().unwrap().len(), 1);
+
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.current_line(), "You wave.");
+    // A trailing debug_print isn't content: the branch line is the last one
+    assert!(!runtime.has_more());
+    runtime.advance().unwrap();
+    assert_eq!(
+        *host.log.lock().unwrap(),
+        [
+            "conversation started",
+            "took the wave branch",
+            "reached the end"
+        ]
+    );
+}
+
+#[test]
+fn failing_grouped_assertion_reports_parentheses() {
+    let source = "temp gold = 5\nassert (gold > 10) == true\nRich.\n";
+    match support::try_runtime(source) {
+        Err(BobbinError::Runtime(RuntimeError::AssertionFailed { message, .. })) => {
+            assert_eq!(message, "(gold > 10) == true");
+        }
+        Err(e) => panic!("expected a failed assertion, got {:?}", e),
+        Ok(_) => panic!("expected a failed assertion, but the runtime was created"),
+    }
+}
+
+#[test]
+fn runtime_is_ready_once_created() {
+    let runtime = support::runtime("Hello.\n");
+    assert!(runtime.is_ready());
+    assert_eq!(runtime.current_line(), "Hello.");
+}
+
+#[test]
+fn error_in_first_step_is_returned_by_constructor() {
+    let source = "temp gold = -1\nassert gold >= 0\nYou have {gold} gold.\n";
+    match support::try_runtime(source) {
+        Err(BobbinError::Runtime(RuntimeError::AssertionFailed { message, line })) => {
+            assert_eq!(message, "gold >= 0");
+            assert_eq!(line, 2);
+        }
+        Err(e) => panic!("expected a failed assertion, got {:?}", e),
+        Ok(_) => panic!("expected a failed assertion, but the runtime was created"),
+    }
+}
+
+#[test]
+fn passing_assertions_have_no_effect() {
+    support::run_output_test(&support::cases_dir().join("basic/assert.bobbin"));
+}
+
+#[test]
+fn failing_assertion_reports_condition_and_line() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("basic/assert_fails.bobbin")).unwrap();
+    let mut runtime = support::runtime(&source);
+    assert_eq!(runtime.current_line(), "You have 5 gold.");
+
+    match runtime.advance() {
+        Err(RuntimeError::AssertionFailed { message, line }) => {
+            assert_eq!(message, "gold >= 0");
+            assert_eq!(line, 4);
+        }
+        other => panic!("expected a failed assertion, got {:?}", other),
+    }
+}
+
+#[test]
+fn builder_applies_options() {
+    let storage = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let cancel = Arc::new(AtomicBool::new(false));
+    let source = "save visits = 0\nWelcome.\nVisits: {visits}\nshuffle\n- Red\n- Green\n- Blue\n";
+
+    let mut runtime = Runtime::builder(storage.clone(), Arc::clone(&host))
+        .seed(7)
+        .missing_save_policy(MissingSavePolicy::Null)
+        .cancel_flag(Arc::clone(&cancel))
+        .build(source)
+        .unwrap();
+    assert!(Arc::ptr_eq(&runtime.cancel_flag(), &cancel));
+
+    // A save dropped mid-run reads as null instead of failing
+    storage.remove("visits");
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Visits: ");
+    runtime.advance().unwrap();
+
+    // Same seed, same order as RuntimeOptions would give
+    let options = RuntimeOptions {
+        seed: 7,
+        ..Default::default()
+    };
+    let mut expected =
+        Runtime::with_options(source, Arc::new(MemoryStorage::new()), host, options).unwrap();
+    expected.advance().unwrap();
+    expected.advance().unwrap();
+    assert_eq!(runtime.current_choices(), expected.current_choices());
+}
+
+#[test]
+fn line_transform_rewrites_lines_after_interpolation() {
+    let source = "temp name = \"Ada\"\nHello, {name}.\n- Wave\n    Bye.\n";
+    let mut runtime = support::runtime(source);
+
+    // The line already showing is transformed too
+    runtime.set_line_transform(Box::new(|line| line.to_uppercase()));
+    assert_eq!(runtime.current_line(), "HELLO, ADA.");
+
+    // Choices are left alone
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_choices(), ["Wave"]);
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.current_line(), "BYE.");
+
+    runtime.clear_line_transform();
+    assert_eq!(runtime.current_line(), "Bye.");
+}
+
+// =============================================================================
+// Reload
+// =============================================================================
+
+#[test]
+fn reload_keeps_position_when_edit_is_ahead() {
+    let old = "temp name = \"Ada\"\nFirst.\nSecond.\nThird.\n";
+    let new = "temp name = \"Ada\"\nFirst.\nSecond.\nRewritten, {name}.\nAdded.\n";
+    let mut runtime = support::runtime(old);
+    runtime.advance().unwrap();
+
+    let outcome = runtime.reload(old, new, RuntimeOptions::default());
+    assert_eq!(outcome.unwrap(), ReloadOutcome::Preserved);
+    assert_eq!(runtime.current_line(), "Second.");
+
+    // Temps declared before the position are still on the stack
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Rewritten, Ada.");
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Added.");
     assert!(!runtime.has_more());
 }
+
+#[test]
+fn reload_restarts_when_edit_is_behind() {
+    let old = "First.\nSecond.\nThird.\n";
+    let new = "First, reworded.\nSecond.\nThird.\n";
+    let mut runtime = support::runtime(old);
+    runtime.advance().unwrap();
+
+    let outcome = runtime.reload(old, new, RuntimeOptions::default());
+    assert_eq!(outcome.unwrap(), ReloadOutcome::Restarted);
+    assert_eq!(runtime.current_line(), "First, reworded.");
+}
+
+#[test]
+fn reload_restarts_when_current_line_is_edited() {
+    let old = "First.\nSecond.\n";
+    let new = "First.\nSecond, reworded.\n";
+    let mut runtime = support::runtime(old);
+    runtime.advance().unwrap();
+
+    let outcome = runtime.reload(old, new, RuntimeOptions::default());
+    assert_eq!(outcome.unwrap(), ReloadOutcome::Restarted);
+    assert_eq!(runtime.current_line(), "First.");
+}
+
+#[test]
+fn reload_keeps_pending_choices() {
+    let old = "Pick one.\n- Left\n    You went left.\n- Right\n    You went right.\n";
+    let new = "Pick one.\n- Left\n    You went left, carefully.\n- Right\n    You went right.\n";
+    let mut runtime = support::runtime(old);
+    runtime.advance().unwrap();
+    assert!(runtime.is_waiting_for_choice());
+
+    let outcome = runtime.reload(old, new, RuntimeOptions::default());
+    assert_eq!(outcome.unwrap(), ReloadOutcome::Preserved);
+    assert_eq!(runtime.current_choices(), ["Left", "Right"]);
+
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.current_line(), "You went left, carefully.");
+}
+
+#[test]
+fn reload_restarts_when_choice_is_added_to_pending_set() {
+    let old = "Pick one.\n- Left\n- Right\n";
+    let new = "Pick one.\n- Left\n- Right\n- Back\n";
+    let mut runtime = support::runtime(old);
+    runtime.advance().unwrap();
+
+    let outcome = runtime.reload(old, new, RuntimeOptions::default());
+    assert_eq!(outcome.unwrap(), ReloadOutcome::Restarted);
+    assert_eq!(runtime.current_line(), "Pick one.");
+}
+
+#[test]
+fn reload_restart_keeps_old_choice_tokens_stale() {
+    let old = "- Left\n- Right\n";
+    let new = "- Left\n- Back\n";
+    let mut runtime = support::runtime(old);
+    let token = runtime.choice_token();
+
+    let outcome = runtime.reload(old, new, RuntimeOptions::default());
+    assert_eq!(outcome.unwrap(), ReloadOutcome::Restarted);
+    assert_eq!(runtime.current_choices(), ["Left", "Back"]);
+    assert_ne!(runtime.choice_token(), token);
+    assert!(matches!(
+        runtime.select_choice_checked(1, token),
+        Err(RuntimeError::StaleChoice { .. })
+    ));
+}
+
+#[test]
+fn reload_restarts_when_prelude_changes() {
+    let source = "First.\nSecond.\n";
+    let with_prelude = |globals: &str| RuntimeOptions {
+        prelude: Some(Prelude::new("globals.bobbin", globals)),
+        ..Default::default()
+    };
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let options = with_prelude("save gold = 5\n");
+    let mut runtime = Runtime::with_options(source, storage, host, options).unwrap();
+    runtime.advance().unwrap();
+
+    let outcome = runtime.reload(source, source, with_prelude("save gold = 5\n"));
+    assert_eq!(outcome.unwrap(), ReloadOutcome::Preserved);
+    assert_eq!(runtime.current_line(), "Second.");
+
+    let outcome = runtime.reload(source, source, with_prelude("save gold = 10\n"));
+    assert_eq!(outcome.unwrap(), ReloadOutcome::Restarted);
+    assert_eq!(runtime.current_line(), "First.");
+}
+
+#[test]
+fn reload_with_errors_leaves_runtime_unchanged() {
+    let old = "First.\nSecond.\n";
+    let mut runtime = support::runtime(old);
+    runtime.advance().unwrap();
+
+    let result = runtime.reload(old, "First.\n{missing}\n", RuntimeOptions::default());
+    assert!(result.is_err());
+    assert_eq!(runtime.current_line(), "Second.");
+}
+
+// =============================================================================
+// Text Length
+// =============================================================================
+
+#[test]
+fn text_length_warns_over_the_limit() {
+    support::run_warning_test_with_lints(
+        &support::cases_dir().join("basic/warnings/text_length.bobbin"),
+        LintConfig::default()
+            .max_line_length(40)
+            .max_choice_length(20),
+    );
+}
+
+#[test]
+fn text_length_skips_interpolated_values() {
+    // `Hi, {name}.` counts as 5 characters, the conditional as its longer branch
+    support::run_warning_test_with_lints(
+        &support::cases_dir().join("basic/warnings/within_text_length.bobbin"),
+        LintConfig::default()
+            .max_line_length(14)
+            .max_choice_length(12),
+    );
+}
@@ -2,6 +2,14 @@
 
 mod support;
 
+use bobbin_runtime::ast::{ChoiceMarker, Stmt};
+use bobbin_runtime::{
+    ChoiceRecord, ChoiceStrategy, DEFAULT_MAX_CHOICES, HostState, Lint, LintConfig, Runtime,
+    RuntimeError, RuntimeOptions, ScannerConfig, TranscriptEntry, Value, VariableStorage,
+};
+use std::sync::Arc;
+use support::{EmptyHostState, MemoryStorage};
+
 // =============================================================================
 // Basic Choices
 // =============================================================================
@@ -22,6 +30,14 @@ fn basic_select_second() {
     );
 }
 
+#[test]
+fn basic_advance_while_waiting() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/basic.bobbin"),
+        "advance_while_waiting",
+    );
+}
+
 // =============================================================================
 // Choices with Content
 // =============================================================================
@@ -82,6 +98,46 @@ fn gather_door_b() {
     );
 }
 
+#[test]
+fn nested_tail_north() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/nested_tail.bobbin"),
+        "north",
+    );
+}
+
+#[test]
+fn nested_tail_south() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/nested_tail.bobbin"),
+        "south",
+    );
+}
+
+#[test]
+fn nested_tail_east_swim() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/nested_tail.bobbin"),
+        "east_swim",
+    );
+}
+
+#[test]
+fn nested_tail_east_wade() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/nested_tail.bobbin"),
+        "east_wade",
+    );
+}
+
+#[test]
+fn nested_tail_rest() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/nested_tail.bobbin"),
+        "rest",
+    );
+}
+
 // =============================================================================
 // Sequential Choices
 // =============================================================================
@@ -118,6 +174,16 @@ fn sequential_no_blue() {
     );
 }
 
+#[test]
+fn repeat_knock() {
+    support::run_trace_test(&support::cases_dir().join("choices/repeat.bobbin"), "knock");
+}
+
+#[test]
+fn repeat_wait() {
+    support::run_trace_test(&support::cases_dir().join("choices/repeat.bobbin"), "wait");
+}
+
 // =============================================================================
 // Mixed (some with content, some without)
 // =============================================================================
@@ -156,3 +222,820 @@ fn nested_talk_to_bob() {
 fn nested_leave() {
     support::run_trace_test(&support::cases_dir().join("choices/nested.bobbin"), "leave");
 }
+
+#[test]
+fn dedent_multiple_forest() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/dedent_multiple.bobbin"),
+        "forest",
+    );
+}
+
+#[test]
+fn dedent_multiple_stay_in() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/dedent_multiple.bobbin"),
+        "stay_in",
+    );
+}
+
+// =============================================================================
+// Ending Early
+// =============================================================================
+
+#[test]
+fn end_attack() {
+    support::run_trace_test(&support::cases_dir().join("choices/end.bobbin"), "attack");
+}
+
+#[test]
+fn end_bribe() {
+    support::run_trace_test(&support::cases_dir().join("choices/end.bobbin"), "bribe");
+}
+
+// =============================================================================
+// Choice Markers
+// =============================================================================
+
+fn all_markers() -> RuntimeOptions {
+    RuntimeOptions {
+        scanner: ScannerConfig {
+            choice_markers: vec![ChoiceMarker::Dash, ChoiceMarker::Star, ChoiceMarker::Plus],
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn markers_red() {
+    support::run_trace_test_with_options(
+        &support::cases_dir().join("choices/markers.bobbin"),
+        "red",
+        all_markers(),
+    );
+}
+
+#[test]
+fn markers_blue() {
+    support::run_trace_test_with_options(
+        &support::cases_dir().join("choices/markers.bobbin"),
+        "blue",
+        all_markers(),
+    );
+}
+
+#[test]
+fn marker_kind_is_recorded() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("choices/markers.bobbin")).unwrap();
+    let script = bobbin_runtime::parse_with_config(&source, &all_markers().scanner).unwrap();
+    let Stmt::ChoiceSet { choices, .. } = &script.statements[1] else {
+        panic!("expected a choice set");
+    };
+    let markers: Vec<_> = choices.iter().map(|choice| choice.marker).collect();
+    assert_eq!(
+        markers,
+        [ChoiceMarker::Star, ChoiceMarker::Plus, ChoiceMarker::Dash]
+    );
+}
+
+#[test]
+fn unconfigured_markers_are_dialogue() {
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let mut runtime = start("choices/markers.bobbin", storage);
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "* Red door");
+}
+
+// =============================================================================
+// End of File
+// =============================================================================
+
+#[test]
+fn no_trailing_newline() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/no_trailing_newline.bobbin"),
+        "select_last",
+    );
+}
+
+#[test]
+fn nested_no_trailing_newline() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/nested_no_trailing_newline.bobbin"),
+        "down",
+    );
+}
+
+#[test]
+fn extra_spaces_after_marker_are_trimmed() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/extra_spaces.bobbin"),
+        "select",
+    );
+}
+
+#[test]
+fn empty_nested_at_eof() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/empty_nested_at_eof.bobbin"),
+        "select",
+    );
+}
+
+// =============================================================================
+// Shuffled Choices
+// =============================================================================
+
+#[test]
+fn shuffle_seed_7_first() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/shuffle.bobbin"),
+        "seed_7_first",
+    );
+}
+
+#[test]
+fn shuffle_seed_7_last() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/shuffle.bobbin"),
+        "seed_7_last",
+    );
+}
+
+#[test]
+fn shuffle_seed_42() {
+    support::run_trace_test(
+        &support::cases_dir().join("choices/shuffle.bobbin"),
+        "seed_42",
+    );
+}
+
+// =============================================================================
+// Warnings
+// =============================================================================
+
+#[test]
+fn warns_on_duplicate_choice_text() {
+    support::run_warning_test(&support::cases_dir().join("choices/warnings/duplicate_text.bobbin"));
+}
+
+#[test]
+fn duplicate_choice_warning_points_at_text() {
+    let source = "Where to?\n- North\n-   North\n";
+    let runtime = support::runtime(source);
+
+    let spans: Vec<_> = runtime.warnings()[0]
+        .labels
+        .iter()
+        .map(|label| &source[label.span.start..label.span.end])
+        .collect();
+    assert_eq!(spans, ["North", "North"]);
+}
+
+#[test]
+fn interpolated_choices_are_not_duplicates() {
+    support::run_warning_test(
+        &support::cases_dir().join("choices/warnings/interpolated_not_duplicate.bobbin"),
+    );
+}
+
+#[test]
+fn warns_on_statements_after_end() {
+    support::run_warning_test(&support::cases_dir().join("choices/warnings/unreachable.bobbin"));
+}
+
+#[test]
+fn duplicate_choice_lint_can_be_disabled() {
+    let source = std::fs::read_to_string(
+        support::cases_dir().join("choices/warnings/duplicate_text.bobbin"),
+    )
+    .unwrap();
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let options = RuntimeOptions {
+        lints: LintConfig::default().disable(Lint::DuplicateChoice),
+        ..Default::default()
+    };
+
+    let runtime = Runtime::with_options(&source, storage, host, options).unwrap();
+    assert!(runtime.warnings().is_empty());
+}
+
+// =============================================================================
+// Branch Length
+// =============================================================================
+
+fn start(case: &str, storage: Arc<dyn VariableStorage>) -> Runtime {
+    let source = std::fs::read_to_string(support::cases_dir().join(case)).unwrap();
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    Runtime::new(&source, storage, host).unwrap()
+}
+
+#[test]
+fn peek_branch_length_counts_through_gather() {
+    let mut runtime = start("choices/gather.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+
+    // Branch line + two gathered lines, up to the end
+    assert_eq!(runtime.peek_branch_length(0).unwrap(), 3);
+    assert_eq!(runtime.peek_branch_length(1).unwrap(), 3);
+
+    // Peeking doesn't select anything
+    assert_eq!(runtime.current_choices(), ["Door A", "Door B"]);
+    runtime.select_choice(1).unwrap();
+    assert_eq!(runtime.current_line(), "You chose door B.");
+}
+
+#[test]
+fn peek_branch_length_stops_at_next_choice() {
+    let mut runtime = start("choices/nested.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+
+    // "Who would you like to talk to?" then the nested choice set
+    assert_eq!(runtime.peek_branch_length(0).unwrap(), 1);
+    // "Goodbye!" and "The end."
+    assert_eq!(runtime.peek_branch_length(1).unwrap(), 2);
+    assert!(runtime.peek_branch_length(2).is_err());
+}
+
+#[test]
+fn peek_branch_length_discards_assignments() {
+    let storage = Arc::new(MemoryStorage::new());
+    let mut runtime = start("variables/save/in_choices.bobbin", storage.clone());
+    runtime.advance().unwrap();
+
+    assert_eq!(runtime.peek_branch_length(0).unwrap(), 2);
+    assert_eq!(storage.get("mood"), Some(Value::String("neutral".into())));
+
+    runtime.select_choice(0).unwrap();
+    assert_eq!(storage.get("mood"), Some(Value::String("happy".into())));
+}
+
+#[test]
+fn choice_previews_show_first_branch_line() {
+    let mut runtime = start(
+        "choices/with_content.bobbin",
+        Arc::new(MemoryStorage::new()),
+    );
+    assert!(runtime.choice_previews().is_empty());
+    runtime.advance().unwrap();
+
+    assert_eq!(
+        runtime.choice_previews(),
+        [
+            Some("That's wonderful!".to_string()),
+            Some("I'm sorry to hear that.".to_string()),
+        ]
+    );
+
+    // Previewing doesn't select anything
+    runtime.select_choice(1).unwrap();
+    assert_eq!(runtime.current_line(), "I'm sorry to hear that.");
+}
+
+#[test]
+fn choice_previews_are_none_for_empty_branches() {
+    let mut runtime = start("choices/empty.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+
+    // Both branches go straight to the gathered "Nice to meet you!"
+    assert_eq!(runtime.choice_previews(), [None, None]);
+}
+
+#[test]
+fn choice_branch_has_content_only_for_nested_statements() {
+    let mut runtime = start("choices/mixed.bobbin", Arc::new(MemoryStorage::new()));
+    assert!(!runtime.choice_branch_has_content(0));
+    runtime.advance().unwrap();
+
+    // "Talk" has a line of its own; "Leave" goes straight to "Goodbye!"
+    assert!(runtime.choice_branch_has_content(0));
+    assert!(!runtime.choice_branch_has_content(1));
+    assert!(!runtime.choice_branch_has_content(2));
+}
+
+#[test]
+fn choice_branch_has_content_is_false_for_empty_branches() {
+    let mut runtime = start("choices/empty.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+
+    assert!(!runtime.choice_branch_has_content(0));
+    assert!(!runtime.choice_branch_has_content(1));
+}
+
+#[test]
+fn choices_with_indices_maps_displayed_to_source_order() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("choices/shuffle.bobbin")).unwrap();
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let options = RuntimeOptions {
+        seed: 7,
+        ..Default::default()
+    };
+    let mut runtime = Runtime::with_options(&source, storage, host, options).unwrap();
+    assert!(runtime.choices_with_indices().is_empty());
+    runtime.advance().unwrap();
+
+    assert_eq!(
+        runtime.choices_with_indices(),
+        [(1, "Green door"), (2, "Blue door"), (0, "Red door")]
+    );
+
+    // Selection uses the displayed index
+    runtime.select_choice(2).unwrap();
+    assert_eq!(runtime.current_line(), "You open the red door.");
+    assert!(runtime.choices_with_indices().is_empty());
+}
+
+#[test]
+fn choice_history_records_labels() {
+    let mut runtime = start("choices/labels.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+    // The label isn't part of the displayed text; `\(` shows a parenthesis
+    assert_eq!(
+        runtime.current_choices(),
+        ["Take the sword", "Take the shield", "(sighs) Take nothing"]
+    );
+
+    runtime.select_choice(0).unwrap();
+    runtime.advance().unwrap();
+    runtime.advance().unwrap();
+    runtime.select_choice(0).unwrap();
+
+    assert_eq!(
+        runtime.choice_history(),
+        [
+            ChoiceRecord {
+                text: "Take the sword".to_string(),
+                label: Some("chose_sword".to_string()),
+            },
+            ChoiceRecord {
+                text: "Leave".to_string(),
+                label: Some("leave".to_string()),
+            },
+        ]
+    );
+}
+
+#[test]
+fn choice_history_without_label() {
+    let mut runtime = start("choices/labels.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+    runtime.select_choice(2).unwrap();
+
+    assert_eq!(
+        runtime.choice_history(),
+        [ChoiceRecord {
+            text: "(sighs) Take nothing".to_string(),
+            label: None,
+        }]
+    );
+}
+
+#[test]
+fn choice_seen_after_picking_in_an_earlier_visit() {
+    let storage = Arc::new(MemoryStorage::new());
+
+    let mut runtime = start("choices/labels.bobbin", storage.clone());
+    runtime.advance().unwrap();
+    assert!(!runtime.choice_seen(0));
+    runtime.select_choice(0).unwrap();
+
+    // A later conversation sharing the storage still offers the choice
+    let mut runtime = start("choices/labels.bobbin", storage);
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_choices().len(), 3);
+    assert!(runtime.choice_seen(0));
+    assert!(!runtime.choice_seen(1));
+    // Unlabelled choices aren't tracked
+    runtime.select_choice(2).unwrap();
+    runtime.advance().unwrap();
+    assert!(!runtime.choice_seen(0));
+    assert!(!runtime.choice_seen(3));
+    assert_eq!(runtime.variable_names(), Vec::<String>::new());
+}
+
+/// Start `choices/replay.bobbin` with seen lines tracked in `storage`.
+fn start_replay(storage: Arc<MemoryStorage>) -> Runtime {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("choices/replay.bobbin")).unwrap();
+    Runtime::builder(storage, Arc::new(EmptyHostState))
+        .track_seen_lines(true)
+        .build(&source)
+        .unwrap()
+}
+
+#[test]
+fn skip_seen_shows_only_new_lines_on_replay() {
+    let storage = Arc::new(MemoryStorage::new());
+
+    // First pass: take the road branch
+    let mut runtime = start_replay(storage.clone());
+    assert!(!runtime.current_line_seen());
+    runtime.skip_to_choice().unwrap();
+    runtime.select_choice(0).unwrap();
+    runtime.skip_to_choice().unwrap();
+    assert_eq!(runtime.current_line(), "You head out.");
+
+    // Second pass: the opening was read, the weather branch is new
+    let mut runtime = start_replay(storage.clone());
+    assert!(runtime.current_line_seen());
+    runtime.set_skip_seen(true).unwrap();
+    assert!(runtime.is_waiting_for_choice());
+    runtime.select_choice(1).unwrap();
+    assert_eq!(runtime.current_line(), "\"Rain by nightfall.\"");
+    assert!(!runtime.current_line_seen());
+    // The final line stops skipping even when it was read before
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "You head out.");
+    assert!(runtime.current_line_seen());
+
+    // Third pass: every line of the road branch was read
+    let mut runtime = start_replay(storage);
+    runtime.set_skip_seen(true).unwrap();
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.current_line(), "You head out.");
+}
+
+#[test]
+fn seen_lines_are_not_tracked_by_default() {
+    let storage = Arc::new(MemoryStorage::new());
+    start("choices/replay.bobbin", storage.clone())
+        .skip_to_choice()
+        .unwrap();
+
+    let mut runtime = start("choices/replay.bobbin", storage);
+    assert!(!runtime.current_line_seen());
+    runtime.set_skip_seen(true).unwrap();
+    assert_eq!(runtime.current_line(), "You wake in the inn.");
+}
+
+#[test]
+fn tags_attack() {
+    support::run_trace_test(&support::cases_dir().join("choices/tags.bobbin"), "attack");
+}
+
+#[test]
+fn current_choice_tags_per_choice() {
+    let mut runtime = start("choices/tags.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+
+    assert_eq!(
+        runtime.current_choices(),
+        ["Attack", "Flee", "Pick lock #2"]
+    );
+    assert_eq!(runtime.current_choice_tags(0), ["combat", "red"]);
+    assert!(runtime.current_choice_tags(1).is_empty());
+    assert_eq!(runtime.current_choice_tags(2), ["stealth"]);
+    // Out of range is empty rather than a panic
+    assert!(runtime.current_choice_tags(3).is_empty());
+}
+
+#[test]
+fn errors_tag_then_text() {
+    support::run_error_test(&support::cases_dir().join("choices/errors/tag_then_text.bobbin"));
+}
+
+#[test]
+fn errors_tags_without_text() {
+    support::run_error_test(&support::cases_dir().join("choices/errors/tags_without_text.bobbin"));
+}
+
+#[test]
+fn errors_label_without_text() {
+    support::run_error_test(&support::cases_dir().join("choices/errors/label_without_text.bobbin"));
+}
+
+#[test]
+fn current_depth_follows_nesting() {
+    let mut runtime = start("choices/nested.bobbin", Arc::new(MemoryStorage::new()));
+    assert_eq!(runtime.current_depth(), 0);
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_depth(), 0);
+
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.current_line(), "Who would you like to talk to?");
+    assert_eq!(runtime.current_depth(), 1);
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_depth(), 1);
+
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.current_line(), "You chat with Alice.");
+    assert_eq!(runtime.current_depth(), 2);
+
+    // Back out through the gather points
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_depth(), 1);
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "The end.");
+    assert_eq!(runtime.current_depth(), 0);
+}
+
+#[test]
+fn errors_duplicate_label() {
+    support::run_error_test(&support::cases_dir().join("choices/errors/duplicate_label.bobbin"));
+}
+
+#[test]
+fn errors_under_indented() {
+    support::run_error_test(&support::cases_dir().join("choices/errors/under_indented.bobbin"));
+}
+
+#[test]
+fn errors_over_indented() {
+    support::run_error_test(&support::cases_dir().join("choices/errors/over_indented.bobbin"));
+}
+
+#[test]
+fn can_select_choice_only_offered_indices() {
+    let mut runtime = start("choices/basic.bobbin", Arc::new(MemoryStorage::new()));
+    assert!(!runtime.can_select_choice(0), "not at a choice yet");
+
+    runtime.advance().unwrap();
+    let count = runtime.current_choices().len();
+    assert!((0..count).all(|index| runtime.can_select_choice(index)));
+    assert!(!runtime.can_select_choice(count));
+    assert!(!runtime.can_select_choice(usize::MAX));
+
+    runtime.select_choice(0).unwrap();
+    assert!(
+        !runtime.can_select_choice(0),
+        "choices are gone once selected"
+    );
+}
+
+#[test]
+fn can_select_choice_is_false_while_cancelled() {
+    use std::sync::atomic::Ordering;
+
+    let mut runtime = start("choices/basic.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+    runtime.cancel_flag().store(true, Ordering::Relaxed);
+    assert!(!runtime.can_select_choice(0));
+    assert!(runtime.select_choice(0).is_err());
+
+    runtime.cancel_flag().store(false, Ordering::Relaxed);
+    assert!(runtime.can_select_choice(0));
+}
+
+#[test]
+fn select_choice_checked_rejects_a_stale_token() {
+    let mut runtime = start("choices/sequential.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+    let token = runtime.choice_token();
+    runtime.select_choice_checked(0, token).unwrap();
+
+    // The UI still holds the first set's token when the second set shows,
+    // with just as many choices
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_choices(), ["Red", "Blue"]);
+    let err = runtime.select_choice_checked(1, token).unwrap_err();
+    assert!(matches!(err, RuntimeError::StaleChoice { .. }));
+    assert!(runtime.is_waiting_for_choice());
+
+    runtime
+        .select_choice_checked(1, runtime.choice_token())
+        .unwrap();
+    assert_eq!(runtime.current_line(), "Done!");
+    assert!(matches!(
+        runtime.select_choice_checked(0, runtime.choice_token()),
+        Err(RuntimeError::NotAtChoice)
+    ));
+}
+
+#[test]
+fn choice_validator_can_veto_a_selection() {
+    let mut runtime = start("choices/gather.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+    runtime.set_choice_validator(Box::new(|index, text| {
+        assert_eq!(text, ["Door A", "Door B"][index]);
+        index != 0
+    }));
+    assert!(!runtime.can_select_choice(0));
+    assert!(runtime.can_select_choice(1));
+
+    let err = runtime.select_choice(0).unwrap_err();
+    assert!(matches!(err, RuntimeError::ChoiceVetoed { index: 0 }));
+    assert!(runtime.is_waiting_for_choice());
+    assert_eq!(runtime.current_choices(), ["Door A", "Door B"]);
+    assert!(runtime.choice_history().is_empty());
+
+    runtime.select_choice(1).unwrap();
+    assert_eq!(runtime.current_line(), "You chose door B.");
+}
+
+#[test]
+fn skip_to_choice_stops_at_choices_and_final_line() {
+    let mut runtime = start("choices/gather.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.skip_to_choice().unwrap();
+    assert_eq!(runtime.current_choices(), ["Door A", "Door B"]);
+
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.current_line(), "You chose door A.");
+    runtime.skip_to_choice().unwrap();
+    // No more choices: stop on the final line rather than past it
+    assert_eq!(runtime.current_line(), "Goodbye!");
+    assert!(!runtime.has_more());
+}
+
+#[test]
+fn skip_to_choice_across_sequential_sets() {
+    let mut runtime = start("choices/sequential.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.skip_to_choice().unwrap();
+    assert_eq!(runtime.current_choices(), ["Yes", "No"]);
+
+    // Already waiting: nothing to skip
+    runtime.skip_to_choice().unwrap();
+    assert_eq!(runtime.current_choices(), ["Yes", "No"]);
+
+    runtime.select_choice(1).unwrap();
+    assert_eq!(runtime.current_line(), "Second question?");
+    runtime.skip_to_choice().unwrap();
+    assert_eq!(runtime.current_choices(), ["Red", "Blue"]);
+}
+
+#[test]
+fn advance_n_stops_early_at_a_choice() {
+    let source = "One.\nTwo.\nThree.\n- Left\n- Right\nFour.\nFive.\nSix.\n";
+    let mut runtime = support::runtime(source);
+
+    assert_eq!(runtime.advance_n(1).unwrap(), ["Two."]);
+    // Three lines asked for, but the choices come after one
+    assert_eq!(runtime.advance_n(3).unwrap(), ["Three."]);
+    assert!(runtime.advance_n(3).unwrap().is_empty());
+    assert_eq!(runtime.current_choices(), ["Left", "Right"]);
+
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.current_line(), "Four.");
+    // Stops on the final line rather than past it
+    assert_eq!(runtime.advance_n(10).unwrap(), ["Five.", "Six."]);
+    assert!(!runtime.has_more());
+    assert!(runtime.advance_n(10).unwrap().is_empty());
+    assert_eq!(runtime.current_line(), "Six.");
+}
+
+// =============================================================================
+// Transcripts
+// =============================================================================
+
+fn line(text: &str) -> TranscriptEntry {
+    TranscriptEntry::Line(text.to_string())
+}
+
+fn choice(options: &[&str], selected: usize) -> TranscriptEntry {
+    TranscriptEntry::Choice {
+        options: options.iter().map(|s| s.to_string()).collect(),
+        selected,
+    }
+}
+
+#[test]
+fn transcript_always_first() {
+    let mut runtime = start("choices/nested.bobbin", Arc::new(MemoryStorage::new()));
+    let transcript = runtime.transcript(ChoiceStrategy::AlwaysFirst).unwrap();
+
+    assert_eq!(
+        transcript,
+        [
+            line("What do you want to do?"),
+            choice(&["Talk to someone", "Leave"], 0),
+            line("Who would you like to talk to?"),
+            choice(&["Alice", "Bob"], 0),
+            line("You chat with Alice."),
+            line("That was a nice conversation."),
+            line("The end."),
+        ]
+    );
+    // Stops on the final line
+    assert_eq!(runtime.current_line(), "The end.");
+    assert!(!runtime.has_more());
+}
+
+#[test]
+fn transcript_always_last() {
+    let mut runtime = start("choices/nested.bobbin", Arc::new(MemoryStorage::new()));
+    let transcript = runtime.transcript(ChoiceStrategy::AlwaysLast).unwrap();
+
+    assert_eq!(
+        transcript,
+        [
+            line("What do you want to do?"),
+            choice(&["Talk to someone", "Leave"], 1),
+            line("Goodbye!"),
+            line("The end."),
+        ]
+    );
+}
+
+#[test]
+fn transcript_custom_strategy_from_current_state() {
+    let mut runtime = start("choices/nested.bobbin", Arc::new(MemoryStorage::new()));
+    runtime.advance().unwrap();
+
+    let mut picks = vec![0, 1].into_iter();
+    let transcript = runtime
+        .transcript(ChoiceStrategy::Custom(Box::new(|_| picks.next().unwrap())))
+        .unwrap();
+
+    assert_eq!(
+        transcript,
+        [
+            choice(&["Talk to someone", "Leave"], 0),
+            line("Who would you like to talk to?"),
+            choice(&["Alice", "Bob"], 1),
+            line("You chat with Bob."),
+            line("That was a nice conversation."),
+            line("The end."),
+        ]
+    );
+}
+
+#[test]
+fn transcript_rejects_out_of_range_pick() {
+    let mut runtime = start("choices/nested.bobbin", Arc::new(MemoryStorage::new()));
+    let result = runtime.transcript(ChoiceStrategy::Custom(Box::new(|options| options.len())));
+    assert!(result.is_err());
+    assert_eq!(runtime.current_choices(), ["Talk to someone", "Leave"]);
+}
+
+/// A chain of choices, each nested under the one before, `depth` levels deep.
+fn nested_chain(depth: usize) -> String {
+    let mut source = String::from("Start\n");
+    for level in 0..depth {
+        source.push_str(&format!("{}- Level {}\n", "    ".repeat(level), level));
+    }
+    source
+}
+
+#[test]
+fn nesting_at_the_limit_compiles() {
+    assert!(support::try_runtime(&nested_chain(65)).is_ok());
+}
+
+#[test]
+fn errors_nesting_too_deep() {
+    let source = nested_chain(500);
+    let Err(error) = support::try_runtime(&source) else {
+        panic!("expected deep nesting to be rejected");
+    };
+    let rendered = error.render("deep.bobbin", &source).to_lowercase();
+    assert!(rendered.contains("nested too deeply"), "{}", rendered);
+}
+
+/// One choice set with `count` choices.
+fn wide_set(count: usize) -> String {
+    let mut source = String::from("The merchant lays out the wares.\n");
+    for item in 1..=count {
+        source.push_str(&format!("- Item {}\n", item));
+    }
+    source
+}
+
+#[test]
+fn choice_count_is_unlimited_by_default() {
+    assert!(bobbin_runtime::compile(&wide_set(100)).is_ok());
+}
+
+#[test]
+fn choice_count_at_the_limit_compiles() {
+    let options = RuntimeOptions {
+        max_choices: Some(DEFAULT_MAX_CHOICES),
+        ..Default::default()
+    };
+    let chunk = bobbin_runtime::compile_with_options(&wide_set(DEFAULT_MAX_CHOICES), &options);
+    assert!(chunk.is_ok());
+}
+
+#[test]
+fn errors_too_many_choices() {
+    let options = RuntimeOptions {
+        max_choices: Some(DEFAULT_MAX_CHOICES),
+        ..Default::default()
+    };
+    let source = wide_set(33);
+    let Err(error) = bobbin_runtime::compile_with_options(&source, &options) else {
+        panic!("expected a 33-choice set to be rejected");
+    };
+    let rendered = error.render("wares.bobbin", &source).to_lowercase();
+    assert!(
+        rendered.contains("choice set has 33 choices, more than the limit of 32"),
+        "{}",
+        rendered
+    );
+    // Points at the first choice over the limit
+    assert!(rendered.contains("wares.bobbin:34:1"), "{}", rendered);
+}
+
+#[test]
+fn max_choices_is_configurable() {
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let build = |limit| {
+        Runtime::builder(Arc::new(MemoryStorage::new()), Arc::clone(&host))
+            .max_choices(limit)
+            .build(&wide_set(40))
+    };
+    assert!(build(40).is_ok());
+    assert!(build(39).is_err());
+}
@@ -3,10 +3,16 @@
 //! This module provides infrastructure for running data-driven tests using
 //! sidecar files that specify expected outputs.
 
+// Each test binary compiles this module separately and uses only part of it.
+#![allow(dead_code)]
+
 mod host_state;
 mod storage;
 
-use bobbin_runtime::{HostState, Runtime, Value, VariableStorage};
+use bobbin_runtime::{
+    AriadneRenderer, BobbinError, HostState, LintConfig, Renderer, Runtime, RuntimeOptions, Value,
+    VariableStorage,
+};
 use std::path::Path;
 use std::sync::Arc;
 
@@ -25,14 +31,14 @@ pub struct TracePath {
 }
 
 /// A single step in a trace execution.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Step {
     Assert(Assertion),
     Action(Action),
 }
 
 /// An assertion to verify runtime state.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Assertion {
     /// Assert current_line() equals the given text
     Line(String),
@@ -49,7 +55,7 @@ pub enum Assertion {
 }
 
 /// An action to perform on the runtime.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Action {
     /// Call advance()
     Advance,
@@ -57,6 +63,24 @@ pub enum Action {
     SelectChoice(usize),
     /// Set a host variable value (collected before execution)
     SetHost { name: String, value: Value },
+    /// Seed the runtime's random number generator (collected before execution)
+    Seed(u64),
+}
+
+// =============================================================================
+// Runtime Construction
+// =============================================================================
+
+/// Start `source` with empty storage and no host variables.
+pub fn runtime(source: &str) -> Runtime {
+    try_runtime(source).unwrap_or_else(|e| panic!("Failed to create runtime:\n{}", e))
+}
+
+/// Like [`runtime`], but hands back the error instead of panicking.
+pub fn try_runtime(source: &str) -> Result<Runtime, BobbinError> {
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    Runtime::new(source, storage, host)
 }
 
 // =============================================================================
@@ -133,6 +157,12 @@ pub fn run_output_test(case_path: &Path) {
 ///
 /// Executes a specific named path through the test case.
 pub fn run_trace_test(case_path: &Path, path_name: &str) {
+    run_trace_test_with_options(case_path, path_name, RuntimeOptions::default());
+}
+
+/// Like [`run_trace_test`], starting from non-default options. A `[seed N]`
+/// in the trace still overrides the seed.
+pub fn run_trace_test_with_options(case_path: &Path, path_name: &str, mut options: RuntimeOptions) {
     let source = std::fs::read_to_string(case_path)
         .unwrap_or_else(|e| panic!("Failed to read test case {}: {}", case_path.display(), e));
 
@@ -154,24 +184,29 @@ pub fn run_trace_test(case_path: &Path, path_name: &str) {
             )
         });
 
-    // Pre-collect host values from trace
+    // Pre-collect host values and seed from trace
     let mut host = MockHostState::new();
     for step in &trace.steps {
-        if let Step::Action(Action::SetHost { name, value }) = step {
-            host.set(name.clone(), value.clone());
+        match step {
+            Step::Action(Action::SetHost { name, value }) => {
+                host.set(name.clone(), value.clone());
+            }
+            Step::Action(Action::Seed(seed)) => options.seed = *seed,
+            _ => {}
         }
     }
 
     // Create runtime with host state
     let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
     let host: Arc<dyn HostState> = Arc::new(host);
-    let mut runtime = Runtime::new(&source, Arc::clone(&storage), Arc::clone(&host))
-        .unwrap_or_else(|e| {
-            panic!(
-                "Failed to create runtime:\n{}",
-                e.render(case_path.to_str().unwrap_or("<unknown>"), &source)
-            )
-        });
+    let mut runtime =
+        Runtime::with_options(&source, Arc::clone(&storage), Arc::clone(&host), options)
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Failed to create runtime:\n{}",
+                    e.render(case_path.to_str().unwrap_or("<unknown>"), &source)
+                )
+            });
 
     for (step_idx, step) in trace.steps.iter().enumerate() {
         match step {
@@ -193,8 +228,8 @@ pub fn run_trace_test(case_path: &Path, path_name: &str) {
             Step::Assert(assertion) => {
                 execute_runtime_assertion(&runtime, assertion, case_path, path_name, step_idx);
             }
-            Step::Action(Action::SetHost { .. }) => {
-                // Skip - host values were pre-collected before runtime creation
+            Step::Action(Action::SetHost { .. } | Action::Seed(_)) => {
+                // Skip - host values and seed were pre-collected before runtime creation
             }
             Step::Action(action) => {
                 execute_action(&mut runtime, action, case_path, path_name, step_idx);
@@ -207,6 +242,11 @@ pub fn run_trace_test(case_path: &Path, path_name: &str) {
 ///
 /// Expects the runtime to fail with an error containing the specified substrings.
 pub fn run_error_test(case_path: &Path) {
+    run_error_test_with_options(case_path, RuntimeOptions::default());
+}
+
+/// Like [`run_error_test`], with non-default runtime options.
+pub fn run_error_test_with_options(case_path: &Path, options: RuntimeOptions) {
     let source = std::fs::read_to_string(case_path)
         .unwrap_or_else(|e| panic!("Failed to read test case {}: {}", case_path.display(), e));
 
@@ -221,7 +261,7 @@ pub fn run_error_test(case_path: &Path) {
 
     let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
     let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
-    match Runtime::new(&source, storage, host) {
+    match Runtime::with_options(&source, storage, host, options) {
         Ok(_) => {
             panic!(
                 "Expected error in {} but script executed successfully",
@@ -250,6 +290,71 @@ pub fn run_error_test(case_path: &Path) {
     }
 }
 
+/// Run a warning test (.warn sidecar).
+///
+/// Expects the script to compile, and the rendered warnings to contain each
+/// line of the sidecar (case-insensitive). An empty sidecar expects no warnings.
+pub fn run_warning_test(case_path: &Path) {
+    run_warning_test_with_lints(case_path, LintConfig::default());
+}
+
+/// Like [`run_warning_test`], with a chosen set of lints (for opt-in lints).
+pub fn run_warning_test_with_lints(case_path: &Path, lints: LintConfig) {
+    let source = std::fs::read_to_string(case_path)
+        .unwrap_or_else(|e| panic!("Failed to read test case {}: {}", case_path.display(), e));
+
+    let warn_path = case_path.with_extension("warn");
+    let expected = std::fs::read_to_string(&warn_path).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read expected warnings {}: {}",
+            warn_path.display(),
+            e
+        )
+    });
+
+    let source_id = case_path.to_str().unwrap_or("<unknown>");
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let options = RuntimeOptions {
+        lints,
+        ..Default::default()
+    };
+    let runtime = Runtime::with_options(&source, storage, host, options).unwrap_or_else(|e| {
+        panic!(
+            "Failed to create runtime:\n{}",
+            e.render(source_id, &source)
+        )
+    });
+
+    let rendered = AriadneRenderer::new().render_all(runtime.warnings(), source_id, &source);
+    let expected: Vec<&str> = expected
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    if expected.is_empty() {
+        assert!(
+            runtime.warnings().is_empty(),
+            "Expected no warnings in {}, got:\n{}",
+            case_path.display(),
+            rendered
+        );
+        return;
+    }
+
+    let rendered_lower = rendered.to_lowercase();
+    for expected_substring in expected {
+        assert!(
+            rendered_lower.contains(&expected_substring.to_lowercase()),
+            "Warnings missing expected substring in {}\nExpected to contain: {:?}\nActual warnings:\n{}",
+            case_path.display(),
+            expected_substring,
+            rendered
+        );
+    }
+}
+
 // =============================================================================
 // Trace File Parsing
 // =============================================================================
@@ -258,6 +363,8 @@ pub fn run_error_test(case_path: &Path) {
 pub fn parse_trace(content: &str) -> Vec<TracePath> {
     let mut paths = Vec::new();
     let mut current_path: Option<TracePath> = None;
+    // Open `[repeat N]` blocks: (count, first step index, line number)
+    let mut repeats: Vec<(usize, usize, usize)> = Vec::new();
 
     for (line_num, line) in content.lines().enumerate() {
         let line_num = line_num + 1; // 1-indexed for human readability
@@ -271,6 +378,9 @@ pub fn parse_trace(content: &str) -> Vec<TracePath> {
         // Path delimiter
         if line.starts_with("--- path:") {
             // Save previous path if any
+            if let Some((_, _, open_line)) = repeats.last() {
+                panic!("Line {}: [repeat] block is never closed", open_line);
+            }
             if let Some(path) = current_path.take() {
                 paths.push(path);
             }
@@ -294,6 +404,29 @@ pub fn parse_trace(content: &str) -> Vec<TracePath> {
             line
         };
 
+        // Repeat blocks are expanded in place when they close
+        if let Some(count) = line
+            .strip_prefix("[repeat ")
+            .and_then(|s| s.strip_suffix(']'))
+        {
+            let count: usize = count
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("Line {}: Invalid repeat count: {}", line_num, count));
+            repeats.push((count, path.steps.len(), line_num));
+            continue;
+        }
+        if line == "[end repeat]" {
+            let (count, start, _) = repeats
+                .pop()
+                .unwrap_or_else(|| panic!("Line {}: [end repeat] without [repeat]", line_num));
+            let body = path.steps.split_off(start);
+            for _ in 0..count {
+                path.steps.extend(body.iter().cloned());
+            }
+            continue;
+        }
+
         // Parse the step
         if let Some(step) = parse_step(line, line_num) {
             path.steps.push(step);
@@ -301,6 +434,9 @@ pub fn parse_trace(content: &str) -> Vec<TracePath> {
     }
 
     // Don't forget the last path
+    if let Some((_, _, open_line)) = repeats.last() {
+        panic!("Line {}: [repeat] block is never closed", open_line);
+    }
     if let Some(path) = current_path {
         paths.push(path);
     }
@@ -343,7 +479,7 @@ fn parse_step(line: &str, line_num: usize) -> Option<Step> {
         return Some(Step::Assert(Assertion::StorageVar { name, value }));
     }
 
-    // Actions: [advance], [choice N], [host name = value]
+    // Actions: [advance], [choice N], [host name = value], [seed N]
     if let Some(inner) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
         if inner == "advance" {
             return Some(Step::Action(Action::Advance));
@@ -355,6 +491,13 @@ fn parse_step(line: &str, line_num: usize) -> Option<Step> {
                 .unwrap_or_else(|_| panic!("Line {}: Invalid choice index: {}", line_num, idx_str));
             return Some(Step::Action(Action::SelectChoice(idx)));
         }
+        if let Some(seed_str) = inner.strip_prefix("seed ") {
+            let seed: u64 = seed_str
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("Line {}: Invalid seed: {}", line_num, seed_str));
+            return Some(Step::Action(Action::Seed(seed)));
+        }
         if let Some(rest) = inner.strip_prefix("host ") {
             // Parse "name = value"
             let parts: Vec<&str> = rest.splitn(2, '=').collect();
@@ -609,8 +752,8 @@ fn execute_action(
                 )
             });
         }
-        Action::SetHost { .. } => {
-            // SetHost actions are pre-collected and applied before runtime creation.
+        Action::SetHost { .. } | Action::Seed(_) => {
+            // SetHost and Seed actions are pre-collected and applied before runtime creation.
             // They should be skipped in run_trace_test, but we handle them here
             // for completeness if execute_action is called directly.
         }
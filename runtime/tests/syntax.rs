@@ -1,7 +1,9 @@
-//! Syntax and lexical error tests.
+//! Syntax tests: comments, and lexical and syntax errors.
 
 mod support;
 
+use bobbin_runtime::ast::{Choice, Stmt, TextPart};
+
 #[test]
 fn errors_tabs() {
     support::run_error_test(&support::cases_dir().join("syntax/errors/tabs.bobbin"));
@@ -14,11 +16,117 @@ fn errors_unclosed_interpolation() {
     );
 }
 
+#[test]
+fn errors_assert_single_equals() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/assert_single_equals.bobbin"),
+    );
+}
+
+#[test]
+fn errors_assert_chained_comparison() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/assert_chained_comparison.bobbin"),
+    );
+}
+
+#[test]
+fn errors_assert_chained_equality() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/assert_chained_equality.bobbin"),
+    );
+}
+
+#[test]
+fn errors_assert_unclosed_group() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/assert_unclosed_group.bobbin"),
+    );
+}
+
+/// `assert` with `1` inside `depth` pairs of parentheses.
+fn nested_groups(depth: usize) -> String {
+    format!("assert {}1{}\n", "(".repeat(depth), ")".repeat(depth))
+}
+
+#[test]
+fn groups_at_the_limit_compile() {
+    assert!(bobbin_runtime::compile(&nested_groups(64)).is_ok());
+}
+
+#[test]
+fn errors_groups_nested_too_deep() {
+    // Deep enough to overflow the stack if parsing recursed without a limit
+    let source = nested_groups(20_000);
+    let Err(error) = bobbin_runtime::parse(&source) else {
+        panic!("expected deep parentheses to be rejected");
+    };
+    let rendered = error.render("deep.bobbin", &source).to_lowercase();
+    assert!(rendered.contains("nested too deeply"), "{}", rendered);
+    assert!(bobbin_runtime::compile(&source).is_err());
+}
+
+/// A line with `x` inside `depth` nested `{if a}` blocks.
+fn nested_conditionals(depth: usize) -> String {
+    format!(
+        "temp a = true\n{}x{}\nAfter.\n",
+        "{if a}".repeat(depth),
+        "{endif}".repeat(depth)
+    )
+}
+
+#[test]
+fn conditionals_at_the_limit_compile() {
+    let source = nested_conditionals(64);
+    let runtime = support::runtime(&source);
+    assert_eq!(runtime.current_line(), "x");
+}
+
+#[test]
+fn errors_conditionals_nested_too_deep() {
+    // Unclosed too: the skipped blocks run to the end of the line
+    for source in [nested_conditionals(5000), "{if a}".repeat(5000)] {
+        let Err(error) = bobbin_runtime::parse(&source) else {
+            panic!("expected deep conditionals to be rejected");
+        };
+        // Rendering would print the 30 KB line once per error
+        assert!(format!("{:?}", error).contains("nested too deeply"));
+    }
+}
+
+#[test]
+fn errors_format_spec_variable() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/format_spec_variable.bobbin"),
+    );
+}
+
+#[test]
+fn errors_format_spec_precision_too_large() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/format_spec_precision_too_large.bobbin"),
+    );
+}
+
+#[test]
+fn errors_format_spec_unsupported() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/format_spec_unsupported.bobbin"),
+    );
+}
+
 #[test]
 fn errors_empty_interpolation() {
     support::run_error_test(&support::cases_dir().join("syntax/errors/empty_interpolation.bobbin"));
 }
 
+#[test]
+fn errors_spaced_empty_interpolation() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/spaced_empty_interpolation.bobbin"),
+    );
+}
+
 #[test]
 fn errors_lone_closing_brace() {
     support::run_error_test(&support::cases_dir().join("syntax/errors/lone_closing_brace.bobbin"));
@@ -40,14 +148,450 @@ fn errors_set_missing_equals() {
     support::run_error_test(&support::cases_dir().join("syntax/errors/set_missing_equals.bobbin"));
 }
 
+#[test]
+fn errors_set_missing_equals_at_end_of_line() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/set_missing_equals_at_end.bobbin"),
+    );
+}
+
 #[test]
 fn errors_set_missing_value() {
     support::run_error_test(&support::cases_dir().join("syntax/errors/set_missing_value.bobbin"));
 }
 
+#[test]
+fn errors_set_non_literal_value() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/set_non_literal_value.bobbin"),
+    );
+}
+
+#[test]
+fn errors_extern_unknown_type() {
+    support::run_error_test(&support::cases_dir().join("syntax/errors/extern_unknown_type.bobbin"));
+}
+
+#[test]
+fn errors_extern_missing_default() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/extern_missing_default.bobbin"),
+    );
+}
+
 #[test]
 fn errors_invalid_declaration_character() {
     support::run_error_test(
         &support::cases_dir().join("syntax/errors/invalid_declaration_character.bobbin"),
     );
 }
+
+#[test]
+fn errors_shuffle_without_choices() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/shuffle_without_choices.bobbin"),
+    );
+}
+
+#[test]
+fn errors_once_without_text() {
+    support::run_error_test(&support::cases_dir().join("syntax/errors/once_without_text.bobbin"));
+}
+
+#[test]
+fn errors_effect_on_choice() {
+    support::run_error_test(&support::cases_dir().join("syntax/errors/effect_on_choice.bobbin"));
+}
+
+#[test]
+fn errors_effect_without_set() {
+    support::run_error_test(&support::cases_dir().join("syntax/errors/effect_without_set.bobbin"));
+}
+
+#[test]
+fn errors_unclosed_conditional_text() {
+    support::run_error_test(&support::cases_dir().join("syntax/errors/unclosed_if.bobbin"));
+}
+
+#[test]
+fn errors_stray_endif() {
+    support::run_error_test(&support::cases_dir().join("syntax/errors/stray_endif.bobbin"));
+}
+
+#[test]
+fn errors_double_else() {
+    support::run_error_test(&support::cases_dir().join("syntax/errors/double_else.bobbin"));
+}
+
+#[test]
+fn errors_if_without_variable() {
+    support::run_error_test(&support::cases_dir().join("syntax/errors/if_without_variable.bobbin"));
+}
+
+#[test]
+fn errors_debug_print_without_message() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/debug_print_without_message.bobbin"),
+    );
+}
+
+// =============================================================================
+// Comments
+// =============================================================================
+
+#[test]
+fn comments_are_skipped() {
+    support::run_output_test(&support::cases_dir().join("syntax/comments.bobbin"));
+}
+
+#[test]
+fn comments_attach_to_following_statement() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("syntax/comments.bobbin")).unwrap();
+    let script = bobbin_runtime::parse(&source).unwrap();
+
+    let save = &script.statements[0];
+    assert!(matches!(save, Stmt::SaveDecl(_)));
+    assert_eq!(
+        script.leading_comments(save),
+        [
+            "The player's standing with the guild.",
+            "Raised by completing contracts."
+        ]
+    );
+
+    // A blank line between a comment and a statement detaches it
+    assert!(script.leading_comments(&script.statements[1]).is_empty());
+
+    // Consecutive comment lines attach together, whatever their indentation
+    assert_eq!(
+        script.leading_comments(&script.statements[2]),
+        [
+            "Comment indentation doesn't matter",
+            "Greeting depends on standing"
+        ]
+    );
+}
+
+#[test]
+fn debug_notes_are_not_displayed() {
+    support::run_output_test(&support::cases_dir().join("syntax/debug_notes.bobbin"));
+}
+
+#[test]
+fn debug_notes_are_available_separately() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("syntax/debug_notes.bobbin")).unwrap();
+    let mut runtime = support::runtime(&source);
+
+    assert_eq!(runtime.current_line(), "The door creaks open.");
+    assert_eq!(runtime.current_line_debug_note(), Some("needs a creak SFX"));
+
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "It's dark inside.");
+    assert_eq!(runtime.current_line_debug_note(), None);
+
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line_debug_note(), None);
+
+    // Everything after `//!` is the note, including what looks like an effect
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "The chest opens.");
+    assert_eq!(
+        runtime.current_line_debug_note(),
+        Some("placeholder VO ~ set opened = true")
+    );
+}
+
+#[test]
+fn errors_debug_note_on_choice() {
+    support::run_error_test(
+        &support::cases_dir().join("syntax/errors/debug_note_on_choice.bobbin"),
+    );
+}
+
+// =============================================================================
+// Line Endings
+// =============================================================================
+
+#[test]
+fn cr_line_endings_go() {
+    support::run_trace_test(
+        &support::cases_dir().join("syntax/cr_line_endings.bobbin"),
+        "go",
+    );
+}
+
+#[test]
+fn cr_line_endings_stay() {
+    support::run_trace_test(
+        &support::cases_dir().join("syntax/cr_line_endings.bobbin"),
+        "stay",
+    );
+}
+
+#[test]
+fn cr_line_endings_keep_comments() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("syntax/cr_line_endings.bobbin"))
+            .unwrap();
+    let script = bobbin_runtime::parse(&source).unwrap();
+    assert_eq!(
+        script.leading_comments(&script.statements[0]),
+        ["Classic Mac OS line endings"]
+    );
+}
+
+#[test]
+fn errors_cr_line_endings_report_lines() {
+    support::run_error_test(&support::cases_dir().join("syntax/errors/cr_line_endings.bobbin"));
+}
+
+// =============================================================================
+// Tokens
+// =============================================================================
+
+#[test]
+fn scanned_tokens_cover_the_source_in_order() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("syntax/comments.bobbin")).unwrap();
+    let (tokens, errors) = bobbin_runtime::scan(&source);
+    assert!(errors.is_empty());
+
+    // Tokens are contiguous except for text the scanner skips: spaces,
+    // comment lines, and the `\` that forces a line to be dialogue
+    let mut covered = 0;
+    for token in &tokens {
+        assert!(token.span.start >= covered, "{:?} overlaps", token);
+        let gap = &source[covered..token.span.start];
+        assert!(
+            gap.trim().is_empty() || gap.trim_start().starts_with("//") || gap == "\\",
+            "unexpected gap {:?} before {:?}",
+            gap,
+            token
+        );
+        covered = token.span.end;
+    }
+    assert!(source[covered..].trim().is_empty() || source[covered..].trim().starts_with("//"));
+}
+
+#[test]
+fn parse_tokens_matches_parse() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("syntax/comments.bobbin")).unwrap();
+    let (tokens, _) = bobbin_runtime::scan(&source);
+    let from_tokens = bobbin_runtime::parse_tokens(&source, &tokens).unwrap();
+    let parsed = bobbin_runtime::parse(&source).unwrap();
+
+    assert_eq!(format!("{:?}", from_tokens), format!("{:?}", parsed));
+}
+
+#[test]
+fn scan_collects_lexical_errors() {
+    let (tokens, errors) = bobbin_runtime::scan("Hello }\nStill {scanning}\n");
+    assert_eq!(errors.len(), 1);
+    // Scanning recovers and carries on with the next line
+    assert!(tokens.iter().any(|t| t.lexeme == "scanning"));
+
+    let rendered = bobbin_runtime::BobbinError::from(errors).render("test", "Hello }\n");
+    assert!(rendered.contains("Unexpected '}'"));
+}
+
+// =============================================================================
+// Emitting Source
+// =============================================================================
+
+#[test]
+fn emit_round_trips_canonical_source() {
+    let source = std::fs::read_to_string(support::cases_dir().join("syntax/emit.bobbin")).unwrap();
+    let parsed = bobbin_runtime::parse(&source).unwrap();
+
+    let emitted = bobbin_runtime::emit(&parsed);
+    assert_eq!(emitted, source);
+
+    let reparsed = bobbin_runtime::parse(&emitted).unwrap();
+    assert_eq!(format!("{:?}", reparsed), format!("{:?}", parsed));
+}
+
+#[test]
+fn escaped_leading_space_is_shown() {
+    let mut runtime = support::runtime("\\  indented\n- \\ spaced\n- (calm) \\ labelled\n");
+    assert_eq!(runtime.current_line(), "  indented");
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_choices(), [" spaced", " labelled"]);
+}
+
+#[test]
+fn emit_normalizes_layout() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("syntax/comments.bobbin")).unwrap();
+    let emitted = bobbin_runtime::emit(&bobbin_runtime::parse(&source).unwrap());
+
+    // Blank lines go, comments move to their statement's indentation
+    assert!(emitted.contains("Welcome back.\n// Comment indentation doesn't matter\n"));
+    let reparsed = bobbin_runtime::parse(&emitted).unwrap();
+    assert_eq!(bobbin_runtime::emit(&reparsed), emitted);
+}
+
+// =============================================================================
+// Visiting the Syntax Tree
+// =============================================================================
+
+/// Counts lines and choices, and the deepest choice nesting.
+#[derive(Default)]
+struct Counter {
+    lines: usize,
+    choices: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl bobbin_runtime::visit::Visitor for Counter {
+    fn visit_line(&mut self, _parts: &[TextPart], _span: bobbin_runtime::token::Span) {
+        self.lines += 1;
+    }
+
+    fn visit_choice(&mut self, _choice: &Choice) {
+        self.choices += 1;
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+
+    fn leave_choice(&mut self, _choice: &Choice) {
+        self.depth -= 1;
+    }
+}
+
+#[test]
+fn visitor_counts_nested_lines_and_choices() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("choices/nested.bobbin")).unwrap();
+    let script = bobbin_runtime::parse(&source).unwrap();
+
+    let mut counter = Counter::default();
+    bobbin_runtime::visit::walk(&script, &mut counter);
+    assert_eq!(counter.lines, 7);
+    assert_eq!(counter.choices, 4);
+    assert_eq!(counter.max_depth, 2);
+    assert_eq!(counter.depth, 0);
+}
+
+// =============================================================================
+// Serialized Syntax Tree
+// =============================================================================
+
+/// Compare a fixture's serialized syntax tree with the `.json` beside it.
+#[cfg(feature = "serde")]
+fn assert_json_snapshot(case: &str) {
+    let case = support::cases_dir().join(case);
+    let source = std::fs::read_to_string(&case).unwrap();
+    let expected = std::fs::read_to_string(case.with_extension("json")).unwrap();
+
+    let json = bobbin_runtime::parse_to_json(&source).unwrap();
+    assert_eq!(json.trim_end(), expected.trim_end());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn syntax_tree_json_snapshot() {
+    assert_json_snapshot("syntax/ast.bobbin");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn syntax_tree_json_keeps_debug_notes() {
+    assert_json_snapshot("syntax/ast_debug_note.bobbin");
+}
+
+// =============================================================================
+// Source Hash
+// =============================================================================
+
+#[test]
+fn source_hash_is_stable() {
+    use bobbin_runtime::source_hash;
+
+    let case = support::cases_dir().join("choices/nested.bobbin");
+    let first = std::fs::read_to_string(&case).unwrap();
+    let second = std::fs::read_to_string(&case).unwrap();
+    assert_eq!(source_hash(&first), source_hash(&second));
+    // Pinned values: the hash must not change between versions
+    assert_eq!(source_hash(""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(source_hash("a"), 0xaf63_dc4c_8601_ec8c);
+}
+
+#[test]
+fn source_hash_changes_with_content_but_not_line_endings() {
+    use bobbin_runtime::source_hash;
+
+    let source = "Hello.\n- Go\n    Gone.\n";
+    assert_ne!(
+        source_hash(source),
+        source_hash("Hello!\n- Go\n    Gone.\n")
+    );
+    assert_ne!(source_hash(source), source_hash("Hello.\n- Go\n  Gone.\n"));
+    assert_eq!(
+        source_hash(source),
+        source_hash(&source.replace('\n', "\r\n"))
+    );
+    assert_eq!(
+        source_hash(source),
+        source_hash(&source.replace('\n', "\r"))
+    );
+    assert_eq!(
+        source_hash(source),
+        source_hash(&format!("\u{feff}{}", source))
+    );
+}
+
+// =============================================================================
+// Fuzzing Entry Points
+// =============================================================================
+
+/// Every `.bobbin` file under `dir`, recursively.
+fn fixtures(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            found.extend(fixtures(&path));
+        } else if path.extension().is_some_and(|ext| ext == "bobbin") {
+            found.push(path);
+        }
+    }
+    found
+}
+
+#[test]
+fn fuzz_regressions_do_not_panic() {
+    use bobbin_runtime::fuzz::{fuzz_parse, fuzz_runtime, fuzz_scan};
+
+    // Inputs that once crashed a fuzz target; the first is also in
+    // fuzz/regressions, which seeds the corpus
+    let inputs = [
+        "temp x = 1.5\n{x:.70000}\n",
+        "temp x = 1.5\n{x:.99999999}\n",
+        "temp x = 1.5\n{x:.18446744073709551615}\n",
+    ];
+    for input in inputs {
+        fuzz_scan(input.as_bytes());
+        fuzz_parse(input.as_bytes());
+        fuzz_runtime(input.as_bytes());
+    }
+}
+
+#[test]
+fn fuzz_entry_points_survive_truncated_fixtures() {
+    use bobbin_runtime::fuzz::{fuzz_parse, fuzz_runtime, fuzz_scan};
+
+    // Cutting a fixture anywhere, even inside a multibyte character, leaves
+    // input the pipeline must reject or run without panicking
+    for case in fixtures(&support::cases_dir()) {
+        let data = std::fs::read(&case).unwrap();
+        for end in 0..=data.len() {
+            fuzz_scan(&data[..end]);
+            fuzz_parse(&data[..end]);
+            fuzz_runtime(&data[..end]);
+        }
+    }
+}
@@ -26,6 +26,38 @@ fn multiple_uses() {
     support::run_output_test(&support::cases_dir().join("variables/multiple_uses.bobbin"));
 }
 
+#[test]
+fn line_template_is_the_uninterpolated_text() {
+    let source = "temp name = \"World\"\nHello, {name}!\nPrice: {{5}}\n- Go\n";
+    let mut runtime = support::runtime(source);
+
+    assert_eq!(runtime.current_line(), "Hello, World!");
+    assert_eq!(runtime.current_line_template(), Some("Hello, {name}!"));
+
+    // Literal braces stay escaped, as written
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Price: {5}");
+    assert_eq!(runtime.current_line_template(), Some("Price: {{5}}"));
+
+    runtime.advance().unwrap();
+    assert!(runtime.is_waiting_for_choice());
+    assert_eq!(runtime.current_line_template(), None);
+}
+
+#[test]
+fn choice_template_is_the_uninterpolated_text() {
+    let source = "temp name = \"Ada\"\nPick one.\n- Greet {name}\n- Leave #exit\n";
+    let mut runtime = support::runtime(source);
+    assert_eq!(runtime.current_choice_template(0), None);
+
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_choices(), ["Greet Ada", "Leave"]);
+    assert_eq!(runtime.current_choice_template(0), Some("Greet {name}"));
+    // Tags are not part of the template
+    assert_eq!(runtime.current_choice_template(1), Some("Leave"));
+    assert_eq!(runtime.current_choice_template(2), None);
+}
+
 // =============================================================================
 // Assignment
 // =============================================================================
@@ -40,6 +72,11 @@ fn assignment_multiple() {
     support::run_output_test(&support::cases_dir().join("variables/assignment_multiple.bobbin"));
 }
 
+#[test]
+fn compound_assignment() {
+    support::run_output_test(&support::cases_dir().join("variables/compound_assignment.bobbin"));
+}
+
 #[test]
 fn assignment_types() {
     support::run_output_test(&support::cases_dir().join("variables/assignment_types.bobbin"));
@@ -81,6 +118,22 @@ fn save_assignment() {
     );
 }
 
+#[test]
+fn save_compound_bribe() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/save/compound.bobbin"),
+        "bribe",
+    );
+}
+
+#[test]
+fn save_compound_leave() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/save/compound.bobbin"),
+        "leave",
+    );
+}
+
 #[test]
 fn save_in_choices_happy() {
     support::run_trace_test(
@@ -105,6 +158,226 @@ fn save_mixed_with_temp() {
     );
 }
 
+/// Run `save/missing.bobbin`, dropping `gold` from storage after the first line.
+fn run_with_missing_save(
+    policy: bobbin_runtime::MissingSavePolicy,
+) -> (
+    Result<String, bobbin_runtime::RuntimeError>,
+    std::sync::Arc<support::MemoryStorage>,
+) {
+    use bobbin_runtime::{HostState, Runtime, RuntimeOptions, VariableStorage};
+    use std::sync::Arc;
+    use support::{EmptyHostState, MemoryStorage};
+
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("variables/save/missing.bobbin"))
+            .unwrap();
+    let storage = Arc::new(MemoryStorage::new());
+    let storage_dyn: Arc<dyn VariableStorage> = storage.clone();
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let options = RuntimeOptions {
+        missing_save_policy: policy,
+        ..Default::default()
+    };
+
+    let mut runtime = Runtime::with_options(&source, storage_dyn, host, options).unwrap();
+    assert_eq!(runtime.current_line(), "You check your purse.");
+
+    storage.remove("gold");
+    let result = runtime
+        .advance()
+        .map(|_| runtime.current_line().to_string());
+    (result, storage)
+}
+
+#[test]
+fn save_missing_policy_error() {
+    use bobbin_runtime::{MissingSavePolicy, RuntimeError};
+
+    let (result, _) = run_with_missing_save(MissingSavePolicy::Error);
+    match result {
+        Err(RuntimeError::MissingSaveVariable { name }) => assert_eq!(name, "gold"),
+        other => panic!("Expected MissingSaveVariable error, got: {:?}", other),
+    }
+}
+
+#[test]
+fn save_missing_policy_use_declared_default() {
+    use bobbin_runtime::{MissingSavePolicy, Value, VariableStorage};
+
+    let (result, storage) = run_with_missing_save(MissingSavePolicy::UseDeclaredDefault);
+    assert_eq!(result.unwrap(), "You have 10 gold.");
+    // The default is written back so later reads see it too
+    assert_eq!(storage.get("gold"), Some(Value::Number(10.0)));
+}
+
+#[test]
+fn save_missing_policy_null() {
+    use bobbin_runtime::{MissingSavePolicy, VariableStorage};
+
+    let (result, storage) = run_with_missing_save(MissingSavePolicy::Null);
+    assert_eq!(result.unwrap(), "You have  gold.");
+    // Null does not write to storage
+    assert!(!storage.contains("gold"));
+}
+
+#[test]
+fn initialize_defaults_fills_only_missing_saves() {
+    use bobbin_runtime::{HostState, Runtime, Value, VariableStorage};
+    use std::sync::Arc;
+    use support::{EmptyHostState, MemoryStorage};
+
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("variables/save/late.bobbin")).unwrap();
+    // A save from before `health` and `rested` were added
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    storage.set("gold", Value::Number(500.0));
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let runtime = Runtime::new(&source, Arc::clone(&storage), host).unwrap();
+    assert_eq!(runtime.current_line(), "You wake up.");
+    assert!(!storage.contains("health"));
+
+    runtime.initialize_defaults();
+
+    assert_eq!(storage.get("gold"), Some(Value::Number(500.0)));
+    assert_eq!(storage.get("health"), Some(Value::Number(50.0)));
+    assert_eq!(storage.get("rested"), Some(Value::Bool(true)));
+}
+
+#[test]
+fn game_writes_to_shared_storage_mid_conversation() {
+    use bobbin_runtime::{HostState, Runtime, Value, VariableStorage};
+    use std::sync::Arc;
+    use support::{EmptyHostState, MemoryStorage};
+
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("variables/save/shared.bobbin")).unwrap();
+    // The game keeps its own handle to the storage the runtime uses
+    let storage = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let mut runtime = Runtime::new(&source, storage.clone(), host).unwrap();
+    assert_eq!(runtime.current_line(), "You have 10 gold.");
+
+    storage.set("gold", Value::Number(99.0));
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Now you have 99 gold.");
+    assert!(Arc::ptr_eq(
+        runtime.storage(),
+        &(storage as Arc<dyn VariableStorage>)
+    ));
+}
+
+#[test]
+fn namespaces_keep_save_variables_apart() {
+    use bobbin_runtime::{HostState, Runtime, Value, VariableStorage};
+    use std::sync::Arc;
+    use support::{EmptyHostState, MemoryStorage};
+
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let run = |file: &str| {
+        let path = support::cases_dir()
+            .join("variables/save/namespace")
+            .join(file);
+        let source = std::fs::read_to_string(path).unwrap();
+        let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+        let mut runtime = Runtime::new(&source, storage.clone(), host).unwrap();
+        let first = runtime.current_line().to_string();
+        while runtime.has_more() {
+            runtime.advance().unwrap();
+        }
+        first
+    };
+
+    // Both files declare `visited`; each sees only its own
+    assert_eq!(run("quest_intro.bobbin"), "You reach the gate.");
+    assert_eq!(run("tavern.bobbin"), "A new face in the tavern.");
+    assert_eq!(run("quest_intro.bobbin"), "Back at the gate.");
+
+    assert_eq!(storage.get("quest_intro.visited"), Some(Value::Bool(true)));
+    assert_eq!(storage.get("tavern.visited"), Some(Value::Bool(true)));
+    assert_eq!(storage.get("visited"), None);
+}
+
+#[test]
+fn storage_enumerates_in_insertion_order() {
+    use bobbin_runtime::{HostState, Runtime, Value, VariableStorage};
+    use std::sync::Arc;
+    use support::{EmptyHostState, MemoryStorage};
+
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("variables/save/multiple.bobbin"))
+            .unwrap();
+    let storage = Arc::new(MemoryStorage::new());
+    storage.set("zebra", Value::Bool(true));
+    let storage_dyn: Arc<dyn VariableStorage> = storage.clone();
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    Runtime::new(&source, storage_dyn, host).unwrap();
+    // Updating a value keeps its position
+    storage.set("zebra", Value::Bool(false));
+
+    let names: Vec<String> = storage
+        .get_all()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(names, ["zebra", "player_name", "gold", "health"]);
+    assert_eq!(storage.names(), names);
+
+    storage.remove("player_name");
+    assert_eq!(storage.names(), ["zebra", "gold", "health"]);
+    assert_eq!(storage.get("health"), Some(Value::Number(50.0)));
+}
+
+#[test]
+fn variable_names_lists_save_variables() {
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("variables/save/multiple.bobbin"))
+            .unwrap();
+    let runtime = support::runtime(&source);
+
+    assert_eq!(runtime.variable_names(), ["gold", "health", "player_name"]);
+}
+
+#[test]
+fn dirty_variables_tracks_writes_until_cleared() {
+    use bobbin_runtime::{HostState, Runtime, Value, VariableStorage};
+    use std::sync::Arc;
+    use support::{EmptyHostState, MemoryStorage};
+
+    let source = "save gold = 10\nsave visits = 0\ntemp bonus = 2\nonce Hello.\nset gold = 20\nset bonus = 3\nDone.\n";
+    // `visits` comes from an earlier save, so its declaration writes nothing
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    storage.set("visits", Value::Number(3.0));
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let mut runtime = Runtime::new(source, storage, host).unwrap();
+    assert_eq!(runtime.dirty_variables(), ["gold"]);
+
+    runtime.clear_dirty();
+    assert!(runtime.dirty_variables().is_empty());
+
+    // Temps and once counters aren't save variables
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Done.");
+    assert_eq!(runtime.dirty_variables(), ["gold"]);
+}
+
+#[test]
+fn variable_names_skips_temps_and_once_counters() {
+    use bobbin_runtime::{HostState, Runtime, VariableStorage};
+    use std::sync::Arc;
+    use support::{EmptyHostState, MemoryStorage};
+
+    let source = "save visits = 1\ntemp bonus = 2\nonce Hello again.\nDone.\n";
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let mut runtime = Runtime::new(source, Arc::clone(&storage), host).unwrap();
+    runtime.advance().unwrap();
+
+    assert_eq!(runtime.variable_names(), ["visits"]);
+    // The counter is still in storage; it is just not listed
+    assert_eq!(storage.names().len(), 2);
+}
+
 // =============================================================================
 // Extern Variables (Host State)
 // =============================================================================
@@ -157,21 +430,33 @@ fn extern_mixed_with_temp_and_save() {
     );
 }
 
+#[test]
+fn extern_default_when_omitted() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/extern/default.bobbin"),
+        "omitted",
+    );
+}
+
+#[test]
+fn extern_default_overridden_by_host() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/extern/default.bobbin"),
+        "provided",
+    );
+}
+
 #[test]
 fn extern_missing_at_runtime() {
     // Test that using a declared extern variable that the host doesn't provide
     // results in a runtime error (MissingExternVariable)
-    use bobbin_runtime::{HostState, Runtime, RuntimeError, VariableStorage};
-    use std::sync::Arc;
-    use support::{EmptyHostState, MemoryStorage};
+    use bobbin_runtime::RuntimeError;
 
     let source = "extern player_health\n\nYou have {player_health} HP.\n";
 
-    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
-    let host: Arc<dyn HostState> = Arc::new(EmptyHostState); // Doesn't provide player_health
-
-    // Runtime::new steps on creation, which will try to interpolate the extern variable
-    let result = Runtime::new(source, storage, host);
+    // Runtime::new steps on creation, which will try to interpolate the extern
+    // variable, and the empty host doesn't provide it
+    let result = support::try_runtime(source);
 
     // Should fail with MissingExternVariable since EmptyHostState doesn't provide it
     match result {
@@ -183,6 +468,171 @@ fn extern_missing_at_runtime() {
     }
 }
 
+#[test]
+fn extern_typed_matching() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/extern/typed.bobbin"),
+        "matching",
+    );
+}
+
+#[test]
+fn extern_typed_default() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/extern/typed.bobbin"),
+        "default",
+    );
+}
+
+#[test]
+fn extern_type_mismatch_at_runtime() {
+    use bobbin_runtime::{HostState, Runtime, RuntimeError, Value, VariableStorage};
+    use std::sync::Arc;
+    use support::{MemoryStorage, MockHostState};
+
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("variables/extern/typed.bobbin"))
+            .unwrap();
+    let mut host = MockHostState::new();
+    // A string where the script declares a number
+    host.set("gold", Value::String("12".to_string()));
+    host.set("is_vip", Value::Bool(false));
+
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(host);
+    match Runtime::new(&source, storage, host) {
+        Err(bobbin_runtime::BobbinError::Runtime(RuntimeError::ExternTypeMismatch {
+            name,
+            expected,
+            found,
+        })) => {
+            assert_eq!(name, "gold");
+            assert_eq!(expected, "number");
+            assert_eq!(found, "string");
+        }
+        Err(e) => panic!("Expected ExternTypeMismatch error, got: {:?}", e),
+        Ok(_) => panic!("Expected ExternTypeMismatch error, but runtime succeeded"),
+    }
+}
+
+/// Host whose `tick` advances on every read, like state the game changes
+/// while a step is running.
+#[derive(Default)]
+struct TickingHost {
+    tick: std::sync::atomic::AtomicU32,
+    batch_reads: std::sync::atomic::AtomicU32,
+}
+
+impl bobbin_runtime::HostState for TickingHost {
+    fn lookup(&self, name: &str) -> Option<bobbin_runtime::Value> {
+        use std::sync::atomic::Ordering;
+        (name == "tick").then(|| {
+            let tick = self.tick.fetch_add(1, Ordering::SeqCst) + 1;
+            bobbin_runtime::Value::Number(tick as f64)
+        })
+    }
+
+    fn lookup_many(&self, names: &[&str]) -> Vec<Option<bobbin_runtime::Value>> {
+        self.batch_reads
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        names.iter().map(|name| self.lookup(name)).collect()
+    }
+}
+
+fn start_ticking(host_snapshot: bool) -> (bobbin_runtime::Runtime, std::sync::Arc<TickingHost>) {
+    use bobbin_runtime::{Runtime, RuntimeOptions};
+    use std::sync::Arc;
+
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("variables/extern/snapshot.bobbin"))
+            .unwrap();
+    let host = Arc::new(TickingHost::default());
+    let options = RuntimeOptions {
+        host_snapshot,
+        ..Default::default()
+    };
+    let runtime = Runtime::with_options(
+        &source,
+        Arc::new(support::MemoryStorage::new()),
+        host.clone(),
+        options,
+    )
+    .unwrap();
+    (runtime, host)
+}
+
+#[test]
+fn extern_reads_are_live_by_default() {
+    let (mut runtime, host) = start_ticking(false);
+    assert_eq!(runtime.current_line(), "The clock reads 1, and still 2.");
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Later it reads 3.");
+    assert_eq!(
+        host.batch_reads.load(std::sync::atomic::Ordering::SeqCst),
+        0
+    );
+}
+
+#[test]
+fn host_snapshot_gives_each_step_a_consistent_view() {
+    let (mut runtime, host) = start_ticking(true);
+    // Both reads in the line see the value from the start of the step
+    assert_eq!(runtime.current_line(), "The clock reads 1, and still 1.");
+    // The next step takes a fresh snapshot
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Later it reads 2.");
+    assert_eq!(
+        host.batch_reads.load(std::sync::atomic::Ordering::SeqCst),
+        2
+    );
+}
+
+/// Host whose hour the test moves; `is_night` is derived from it.
+struct ClockHost {
+    hour: std::sync::atomic::AtomicU32,
+}
+
+impl bobbin_runtime::HostState for ClockHost {
+    fn lookup(&self, name: &str) -> Option<bobbin_runtime::Value> {
+        use bobbin_runtime::Value;
+        let hour = self.hour.load(std::sync::atomic::Ordering::SeqCst);
+        match name {
+            "current_hour" => Some(Value::Number(hour as f64)),
+            "is_night" => Some(Value::Bool(!(6..20).contains(&hour))),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn time_of_day_comes_from_host() {
+    use bobbin_runtime::Runtime;
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+
+    let source =
+        std::fs::read_to_string(support::cases_dir().join("variables/extern/time_of_day.bobbin"))
+            .unwrap();
+    let host = Arc::new(ClockHost { hour: 22.into() });
+    let mut runtime = Runtime::new(
+        &source,
+        Arc::new(support::MemoryStorage::new()),
+        host.clone(),
+    )
+    .unwrap();
+    assert_eq!(runtime.current_line(), "It is 22:00.");
+
+    // The game moves its clock; the next line sees the new time
+    host.hour.store(9, Ordering::SeqCst);
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "The sun is up.");
+
+    host.hour.store(23, Ordering::SeqCst);
+    let mut night = Runtime::new(&source, Arc::new(support::MemoryStorage::new()), host).unwrap();
+    night.advance().unwrap();
+    assert_eq!(night.current_line(), "The stars are out.");
+}
+
 // =============================================================================
 // Type-specific Interpolation
 // =============================================================================
@@ -212,6 +662,11 @@ fn types_empty_string() {
     support::run_output_test(&support::cases_dir().join("variables/types/empty_string.bobbin"));
 }
 
+#[test]
+fn types_precision() {
+    support::run_output_test(&support::cases_dir().join("variables/types/precision.bobbin"));
+}
+
 // =============================================================================
 // Variables in Choices
 // =============================================================================
@@ -280,6 +735,22 @@ fn in_choices_sibling_reuse_path_b() {
     );
 }
 
+#[test]
+fn in_choices_after_gather_path_a() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/in_choices/after_gather.bobbin"),
+        "path_a",
+    );
+}
+
+#[test]
+fn in_choices_after_gather_path_b() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/in_choices/after_gather.bobbin"),
+        "path_b",
+    );
+}
+
 #[test]
 fn in_choices_outer_scope_assignment_cheer_up() {
     support::run_trace_test(
@@ -315,6 +786,21 @@ fn errors_redeclaration() {
     support::run_error_test(&support::cases_dir().join("variables/errors/redeclaration.bobbin"));
 }
 
+#[test]
+fn errors_reserved_temp_name() {
+    support::run_error_test(&support::cases_dir().join("variables/errors/reserved_temp.bobbin"));
+}
+
+#[test]
+fn errors_reserved_save_name() {
+    support::run_error_test(&support::cases_dir().join("variables/errors/reserved_save.bobbin"));
+}
+
+#[test]
+fn errors_reserved_extern_name() {
+    support::run_error_test(&support::cases_dir().join("variables/errors/reserved_extern.bobbin"));
+}
+
 #[test]
 fn errors_assignment_undefined() {
     support::run_error_test(
@@ -322,6 +808,20 @@ fn errors_assignment_undefined() {
     );
 }
 
+#[test]
+fn errors_compound_declaration() {
+    support::run_error_test(
+        &support::cases_dir().join("variables/errors/compound_declaration.bobbin"),
+    );
+}
+
+#[test]
+fn errors_compound_type_mismatch() {
+    support::run_error_test(
+        &support::cases_dir().join("variables/errors/compound_type_mismatch.bobbin"),
+    );
+}
+
 #[test]
 fn errors_assignment_typo() {
     support::run_error_test(&support::cases_dir().join("variables/errors/assignment_typo.bobbin"));
@@ -366,6 +866,13 @@ fn errors_extern_assignment() {
     );
 }
 
+#[test]
+fn errors_extern_compound_assignment() {
+    support::run_error_test(
+        &support::cases_dir().join("variables/errors/extern_compound_assignment.bobbin"),
+    );
+}
+
 #[test]
 fn errors_extern_redeclaration() {
     support::run_error_test(
@@ -373,6 +880,20 @@ fn errors_extern_redeclaration() {
     );
 }
 
+#[test]
+fn errors_namespace_misplaced() {
+    support::run_error_test(
+        &support::cases_dir().join("variables/errors/namespace_misplaced.bobbin"),
+    );
+}
+
+#[test]
+fn errors_extern_default_type() {
+    support::run_error_test(
+        &support::cases_dir().join("variables/errors/extern_default_type.bobbin"),
+    );
+}
+
 #[test]
 fn errors_extern_shadows_temp() {
     support::run_error_test(
@@ -405,3 +926,369 @@ fn errors_save_shadows_extern() {
 fn errors_extern_undefined() {
     support::run_error_test(&support::cases_dir().join("variables/errors/extern_undefined.bobbin"));
 }
+
+// =============================================================================
+// Prelude (globals.bobbin)
+// =============================================================================
+
+/// Run a fixture from `variables/prelude/` with `globals.bobbin` as its prelude.
+fn run_with_prelude(
+    script: &str,
+    globals: &str,
+) -> (
+    Result<bobbin_runtime::Runtime, bobbin_runtime::BobbinError>,
+    std::sync::Arc<support::MemoryStorage>,
+) {
+    use bobbin_runtime::{HostState, Prelude, Runtime, RuntimeOptions, Value, VariableStorage};
+    use std::sync::Arc;
+    use support::{MemoryStorage, MockHostState};
+
+    let dir = support::cases_dir().join("variables/prelude");
+    let source = std::fs::read_to_string(dir.join(script)).unwrap();
+    let globals = std::fs::read_to_string(dir.join(globals)).unwrap();
+
+    let storage = Arc::new(MemoryStorage::new());
+    let storage_dyn: Arc<dyn VariableStorage> = storage.clone();
+    let mut host = MockHostState::new();
+    host.set("player_name", Value::String("Ada".to_string()));
+    let host: Arc<dyn HostState> = Arc::new(host);
+    let options = RuntimeOptions {
+        prelude: Some(Prelude::new("globals.bobbin", globals)),
+        ..Default::default()
+    };
+
+    let runtime = Runtime::with_options(&source, storage_dyn, host, options);
+    (runtime, storage)
+}
+
+#[test]
+fn prelude_declarations_are_shared() {
+    use bobbin_runtime::{Value, VariableStorage};
+
+    let (runtime, storage) = run_with_prelude("shop.bobbin", "globals.bobbin");
+    let mut runtime = runtime.unwrap();
+
+    // Prelude save defaults are initialized before the script runs
+    assert_eq!(
+        storage.get("merchant_relationship"),
+        Some(Value::Number(0.0))
+    );
+    assert_eq!(runtime.current_line(), "Welcome, Ada.");
+    runtime.advance().unwrap();
+    assert_eq!(runtime.current_line(), "Relationship: 10.");
+}
+
+#[test]
+fn prelude_conflicting_declaration() {
+    let (runtime, _) = run_with_prelude("conflict.bobbin", "globals.bobbin");
+    let err = runtime.err().expect("expected a conflict error");
+    let rendered = err.render("conflict.bobbin", "save player_name = \"Hero\"\nHello.\n");
+    assert!(
+        rendered.contains("already declared in the prelude"),
+        "{}",
+        rendered
+    );
+}
+
+#[test]
+fn prelude_rejects_dialogue() {
+    let (runtime, _) = run_with_prelude("shop.bobbin", "globals_with_dialogue.bobbin");
+    let err = runtime.err().expect("expected a prelude error");
+    // Rendered against the prelude's own source, whatever the caller passes
+    let rendered = err.render("shop.bobbin", "");
+    assert!(rendered.contains("globals.bobbin"), "{}", rendered);
+    assert!(
+        rendered.contains("only 'save' and 'extern' declarations"),
+        "{}",
+        rendered
+    );
+}
+
+// =============================================================================
+// Line Effects
+// =============================================================================
+
+#[test]
+fn effects_basic() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/effects/basic.bobbin"),
+        "basic",
+    );
+}
+
+#[test]
+fn effects_in_choices_insult() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/effects/in_choices.bobbin"),
+        "insult",
+    );
+}
+
+#[test]
+fn effects_in_choices_bribe() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/effects/in_choices.bobbin"),
+        "bribe",
+    );
+}
+
+#[test]
+fn effects_escaped_tilde() {
+    support::run_output_test(&support::cases_dir().join("variables/effects/escaped.bobbin"));
+}
+
+// =============================================================================
+// Conditional Text
+// =============================================================================
+
+#[test]
+fn conditional_text_both_branches() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/conditional_text/basic.bobbin"),
+        "basic",
+    );
+}
+
+#[test]
+fn conditional_text_without_else() {
+    support::run_trace_test(
+        &support::cases_dir().join("variables/conditional_text/no_else.bobbin"),
+        "no_else",
+    );
+}
+
+#[test]
+fn conditional_text_nested() {
+    let case = support::cases_dir().join("variables/conditional_text/nested.bobbin");
+    for path in [
+        "armed and angry",
+        "armed and calm",
+        "unarmed and angry",
+        "unarmed and calm",
+    ] {
+        support::run_trace_test(&case, path);
+    }
+}
+
+#[test]
+fn conditional_text_in_choices() {
+    let case = support::cases_dir().join("variables/conditional_text/in_choices.bobbin");
+    support::run_trace_test(&case, "key");
+    support::run_trace_test(&case, "no key");
+}
+
+#[test]
+fn errors_conditional_text_undefined_variable() {
+    support::run_error_test(
+        &support::cases_dir().join("variables/errors/conditional_undefined.bobbin"),
+    );
+}
+
+// =============================================================================
+// Constants
+// =============================================================================
+
+fn with_constants(difficulty: &str, hardcore: bool) -> bobbin_runtime::RuntimeOptions {
+    use bobbin_runtime::Value;
+
+    let mut options = bobbin_runtime::RuntimeOptions::default();
+    options.constants.insert(
+        "difficulty".to_string(),
+        Value::String(difficulty.to_string()),
+    );
+    options
+        .constants
+        .insert("hardcore".to_string(), Value::Bool(hardcore));
+    options
+}
+
+#[test]
+fn constants_are_read_like_variables() {
+    let case = support::cases_dir().join("variables/constants/difficulty.bobbin");
+    support::run_trace_test_with_options(&case, "hard", with_constants("hard", false));
+    support::run_trace_test_with_options(&case, "hardcore", with_constants("nightmare", true));
+}
+
+#[test]
+fn builder_defines_constants() {
+    use bobbin_runtime::{HostState, Runtime, Value, VariableStorage};
+    use std::sync::Arc;
+    use support::{EmptyHostState, MemoryStorage};
+
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let runtime = Runtime::builder(storage, host)
+        .constant("language", Value::String("fr".to_string()))
+        .build("Language: {language}\n")
+        .unwrap();
+    assert_eq!(runtime.current_line(), "Language: fr");
+}
+
+#[test]
+fn errors_constant_assignment() {
+    support::run_error_test_with_options(
+        &support::cases_dir().join("variables/constants/assignment.bobbin"),
+        with_constants("hard", false),
+    );
+}
+
+#[test]
+fn errors_constant_compound_assignment() {
+    support::run_error_test_with_options(
+        &support::cases_dir().join("variables/constants/compound_assignment.bobbin"),
+        with_constants("hard", false),
+    );
+}
+
+#[test]
+fn const_is_inlined_in_conditions_and_interpolation() {
+    let case = support::cases_dir().join("variables/const/inlined.bobbin");
+    support::run_trace_test(&case, "sell");
+    support::run_trace_test(&case, "leave");
+}
+
+#[test]
+fn const_compiles_to_no_variable_ops() {
+    let source = "const MAX_GOLD = 9999\nCarry up to {MAX_GOLD} gold.\n";
+    let listing = bobbin_runtime::compile(source).unwrap().disassemble(source);
+    assert!(listing.contains("CONSTANT 1 (9999)"), "{}", listing);
+    for op in [
+        "GET_LOCAL",
+        "SET_LOCAL",
+        "GET_STORAGE",
+        "SET_STORAGE",
+        "POP",
+    ] {
+        assert!(!listing.contains(op), "{} in:\n{}", op, listing);
+    }
+}
+
+#[test]
+fn errors_const_assignment() {
+    support::run_error_test(&support::cases_dir().join("variables/errors/const_assignment.bobbin"));
+}
+
+#[test]
+fn errors_const_read_before_declaration() {
+    support::run_error_test(&support::cases_dir().join("variables/errors/const_undefined.bobbin"));
+}
+
+// =============================================================================
+// Initial Temps
+// =============================================================================
+
+#[test]
+fn initial_temp_overrides_declared_value() {
+    use bobbin_runtime::{HostState, Runtime, Value, VariableStorage};
+    use std::sync::Arc;
+    use support::{EmptyHostState, MemoryStorage};
+
+    let source = "temp mood = \"calm\"\nYou feel {mood}.\n- Wait\n    temp tone = \"soft\"\n    A {tone} voice.\n";
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let mut runtime = Runtime::builder(storage, host)
+        .initial_temp("mood", Value::String("furious".to_string()))
+        .build(source)
+        .unwrap();
+    assert_eq!(runtime.current_line(), "You feel furious.");
+
+    runtime.advance().unwrap();
+    runtime.select_choice(0).unwrap();
+    assert_eq!(runtime.current_line(), "A soft voice.");
+}
+
+#[test]
+fn errors_initial_temp_matching_no_top_level_temp() {
+    use bobbin_runtime::{HostState, Runtime, Value, VariableStorage};
+    use std::sync::Arc;
+    use support::{EmptyHostState, MemoryStorage};
+
+    let source = "temp mood = \"calm\"\nYou feel {mood}.\n- Wait\n    temp tone = \"soft\"\n    A {tone} voice.\n";
+    let storage: Arc<dyn VariableStorage> = Arc::new(MemoryStorage::new());
+    let host: Arc<dyn HostState> = Arc::new(EmptyHostState);
+    let Err(error) = Runtime::builder(storage, host)
+        .initial_temp("mod", Value::String("furious".to_string()))
+        // Temps declared inside a branch can't be overridden either
+        .initial_temp("tone", Value::String("loud".to_string()))
+        .build(source)
+    else {
+        panic!("expected unknown initial temps to be rejected");
+    };
+    let diagnostics = error.into_diagnostics();
+    let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "initial value given for unknown temp 'mod'",
+            "initial value given for unknown temp 'tone'",
+        ]
+    );
+    assert!(
+        diagnostics[0]
+            .notes
+            .iter()
+            .any(|note| note == "did you mean 'mood'?")
+    );
+}
+
+// =============================================================================
+// Lints
+// =============================================================================
+
+#[test]
+fn bool_interpolation_lint() {
+    use bobbin_runtime::{Lint, LintConfig};
+
+    support::run_warning_test_with_lints(
+        &support::cases_dir().join("variables/warnings/bool_interpolation.bobbin"),
+        LintConfig::default().enable(Lint::BoolInterpolation),
+    );
+}
+
+#[test]
+fn bool_interpolation_lint_ignores_numbers_and_strings() {
+    use bobbin_runtime::{Lint, LintConfig};
+
+    support::run_warning_test_with_lints(
+        &support::cases_dir().join("variables/warnings/no_bool_interpolation.bobbin"),
+        LintConfig::default().enable(Lint::BoolInterpolation),
+    );
+}
+
+#[test]
+fn bare_interpolation_lint() {
+    use bobbin_runtime::{Lint, LintConfig};
+
+    support::run_warning_test_with_lints(
+        &support::cases_dir().join("variables/warnings/bare_interpolation.bobbin"),
+        LintConfig::default().enable(Lint::BareInterpolation),
+    );
+}
+
+#[test]
+fn bare_interpolation_lint_ignores_text_and_choices() {
+    use bobbin_runtime::{Lint, LintConfig};
+
+    support::run_warning_test_with_lints(
+        &support::cases_dir().join("variables/warnings/no_bare_interpolation.bobbin"),
+        LintConfig::default().enable(Lint::BareInterpolation),
+    );
+}
+
+#[test]
+fn bool_interpolation_lint_is_off_by_default() {
+    support::run_warning_test(
+        &support::cases_dir().join("variables/warnings/no_bool_interpolation.bobbin"),
+    );
+    let source = std::fs::read_to_string(
+        support::cases_dir().join("variables/warnings/bool_interpolation.bobbin"),
+    )
+    .unwrap();
+    let runtime = bobbin_runtime::Runtime::new(
+        &source,
+        std::sync::Arc::new(support::MemoryStorage::new()),
+        std::sync::Arc::new(support::EmptyHostState),
+    )
+    .unwrap();
+    assert!(runtime.warnings().is_empty());
+}